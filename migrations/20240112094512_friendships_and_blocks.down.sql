@@ -0,0 +1,14 @@
+ALTER TABLE users DROP COLUMN friend_request_privacy;
+DROP TYPE friend_request_privacy;
+
+DROP TABLE blocks;
+
+-- put the friend arrays back, both sides of each friendship
+ALTER TABLE users ADD COLUMN friends text[] NOT NULL DEFAULT array[]::text[];
+UPDATE users SET friends = ARRAY(
+    SELECT user_b FROM friendships WHERE user_a = users.id
+    UNION
+    SELECT user_a FROM friendships WHERE user_b = users.id
+);
+
+DROP TABLE friendships;
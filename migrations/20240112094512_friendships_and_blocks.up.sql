@@ -0,0 +1,33 @@
+-- friendships are stored once per pair, with the smaller id first. ids are
+-- compared byte-wise like the server does, not by the database's collation,
+-- which would put mixed case ids in another order.
+CREATE TABLE friendships (
+    user_a      text        COLLATE "C" NOT NULL REFERENCES users (id) ON DELETE cascade,
+    user_b      text        COLLATE "C" NOT NULL REFERENCES users (id) ON DELETE cascade,
+    since       timestamp   NOT NULL DEFAULT now(),
+    PRIMARY KEY (user_a, user_b),
+    CHECK (user_a < user_b)
+);
+
+-- move the old friend arrays over, dropping duplicates and ids that no longer exist
+INSERT INTO friendships (user_a, user_b)
+    SELECT DISTINCT
+        LEAST(users.id COLLATE "C", f.friend_id COLLATE "C"),
+        GREATEST(users.id COLLATE "C", f.friend_id COLLATE "C")
+    FROM users
+    CROSS JOIN LATERAL unnest(users.friends) AS f(friend_id)
+    WHERE users.id <> f.friend_id
+    AND EXISTS (SELECT 1 FROM users AS friend WHERE friend.id = f.friend_id)
+ON CONFLICT DO NOTHING;
+
+ALTER TABLE users DROP COLUMN friends;
+
+CREATE TABLE blocks (
+    blocker     text        NOT NULL REFERENCES users (id) ON DELETE cascade,
+    blocked     text        NOT NULL REFERENCES users (id) ON DELETE cascade,
+    created_at  timestamp   NOT NULL DEFAULT now(),
+    PRIMARY KEY (blocker, blocked)
+);
+
+CREATE TYPE friend_request_privacy AS ENUM ('everyone', 'friends_of_friends', 'nobody');
+ALTER TABLE users ADD COLUMN friend_request_privacy friend_request_privacy NOT NULL DEFAULT 'everyone';
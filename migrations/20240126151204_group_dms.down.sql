@@ -0,0 +1,10 @@
+DROP TABLE group_dm_participants;
+
+DELETE FROM dmchannels WHERE is_group;
+
+ALTER TABLE dmchannels DROP COLUMN owner;
+ALTER TABLE dmchannels DROP COLUMN icon;
+ALTER TABLE dmchannels DROP COLUMN name;
+ALTER TABLE dmchannels DROP COLUMN is_group;
+ALTER TABLE dmchannels ALTER COLUMN to_user SET NOT NULL;
+ALTER TABLE dmchannels ALTER COLUMN from_user SET NOT NULL;
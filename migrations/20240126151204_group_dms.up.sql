@@ -0,0 +1,14 @@
+-- group dms reuse the dmchannels table, direct dms keep using from_user/to_user
+ALTER TABLE dmchannels ALTER COLUMN from_user DROP NOT NULL;
+ALTER TABLE dmchannels ALTER COLUMN to_user DROP NOT NULL;
+ALTER TABLE dmchannels ADD COLUMN is_group boolean NOT NULL DEFAULT false;
+ALTER TABLE dmchannels ADD COLUMN name text;
+ALTER TABLE dmchannels ADD COLUMN icon text;
+ALTER TABLE dmchannels ADD COLUMN owner text REFERENCES users (id) ON DELETE set null;
+
+CREATE TABLE group_dm_participants (
+    channel_id  text        NOT NULL REFERENCES dmchannels (id) ON DELETE cascade,
+    user_id     text        NOT NULL REFERENCES users (id) ON DELETE cascade,
+    joined_at   timestamp   NOT NULL DEFAULT now(),
+    PRIMARY KEY (channel_id, user_id)
+);
@@ -0,0 +1,2 @@
+DROP INDEX messages_channel_id_created_at;
+DROP TABLE dm_read_states;
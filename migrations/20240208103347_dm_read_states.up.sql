@@ -0,0 +1,9 @@
+CREATE TABLE dm_read_states (
+    channel_id      text        NOT NULL REFERENCES dmchannels (id) ON DELETE cascade,
+    user_id         text        NOT NULL REFERENCES users (id) ON DELETE cascade,
+    last_read_at    timestamp   NOT NULL DEFAULT now(),
+    PRIMARY KEY (channel_id, user_id)
+);
+
+-- finding the latest message in a channel is done a lot when listing dms
+CREATE INDEX messages_channel_id_created_at ON messages (channel_id, created_at);
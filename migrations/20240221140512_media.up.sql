@@ -0,0 +1,11 @@
+CREATE TABLE media (
+    id          text        NOT NULL PRIMARY KEY,
+    uploader    text        NOT NULL REFERENCES users (id) ON DELETE cascade,
+    name        text        NOT NULL,
+    size        bigint      NOT NULL,
+    sha256      text        NOT NULL,
+    mime_type   text        NOT NULL,
+    created_at  timestamp   NOT NULL DEFAULT now()
+);
+
+CREATE INDEX media_uploader ON media (uploader);
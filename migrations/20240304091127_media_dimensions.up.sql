@@ -0,0 +1,3 @@
+-- only set for images that could be decoded
+ALTER TABLE media ADD COLUMN width integer;
+ALTER TABLE media ADD COLUMN height integer;
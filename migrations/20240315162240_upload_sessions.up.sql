@@ -0,0 +1,10 @@
+-- chunked uploads that haven't been finalized yet
+CREATE TABLE upload_sessions (
+    id          text        NOT NULL PRIMARY KEY,
+    uploader    text        NOT NULL REFERENCES users (id) ON DELETE cascade,
+    name        text        NOT NULL,
+    size        bigint      NOT NULL,
+    received    bigint      NOT NULL DEFAULT 0,
+    created_at  timestamp   NOT NULL DEFAULT now(),
+    expires_at  timestamp   NOT NULL
+);
@@ -0,0 +1,4 @@
+DROP INDEX dmchannels_icon;
+DROP INDEX guilds_icon;
+DROP INDEX users_avatar;
+DROP TABLE message_attachments;
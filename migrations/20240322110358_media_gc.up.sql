@@ -0,0 +1,20 @@
+-- which uploads are attached to which messages, so that uploads nothing
+-- refers to anymore can be deleted
+CREATE TABLE message_attachments (
+    message_id  text    NOT NULL REFERENCES messages (id) ON DELETE cascade,
+    media_id    text    NOT NULL REFERENCES media (id) ON DELETE cascade,
+    PRIMARY KEY (message_id, media_id)
+);
+
+CREATE INDEX message_attachments_media_id ON message_attachments (media_id);
+
+INSERT INTO message_attachments (message_id, media_id)
+    SELECT DISTINCT messages.id, attachment->>'id'
+    FROM messages, json_array_elements(messages.attachments) AS attachment
+    WHERE json_typeof(messages.attachments) = 'array'
+    AND EXISTS (SELECT 1 FROM media WHERE media.id = attachment->>'id');
+
+-- avatars and icons are looked up by url when collecting garbage
+CREATE INDEX users_avatar ON users (avatar);
+CREATE INDEX guilds_icon ON guilds (icon);
+CREATE INDEX dmchannels_icon ON dmchannels (icon);
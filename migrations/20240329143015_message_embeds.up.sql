@@ -0,0 +1,2 @@
+-- link previews, filled in after the message is sent
+ALTER TABLE messages ADD COLUMN embeds json;
@@ -0,0 +1,2 @@
+ALTER TABLE members DROP COLUMN voice_deafened;
+ALTER TABLE members DROP COLUMN voice_muted;
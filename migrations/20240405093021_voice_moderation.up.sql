@@ -0,0 +1,3 @@
+-- set by moderators, kept when leaving and rejoining voice
+ALTER TABLE members ADD COLUMN voice_muted boolean NOT NULL DEFAULT false;
+ALTER TABLE members ADD COLUMN voice_deafened boolean NOT NULL DEFAULT false;
@@ -0,0 +1,2 @@
+DROP TABLE voice_recording_tracks;
+DROP TABLE voice_recordings;
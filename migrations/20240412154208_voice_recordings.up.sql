@@ -0,0 +1,21 @@
+CREATE TABLE voice_recordings (
+    id          text        NOT NULL PRIMARY KEY,
+    channel_id  text        NOT NULL REFERENCES channels (id) ON DELETE cascade,
+    started_by  text        NOT NULL REFERENCES users (id) ON DELETE cascade,
+    started_at  timestamp   NOT NULL DEFAULT now(),
+    -- null while the recording is still going
+    ended_at    timestamp
+);
+
+CREATE INDEX voice_recordings_channel_id ON voice_recordings (channel_id);
+
+-- one file per producer that was recorded
+CREATE TABLE voice_recording_tracks (
+    recording_id    text    NOT NULL REFERENCES voice_recordings (id) ON DELETE cascade,
+    media_id        text    NOT NULL REFERENCES media (id) ON DELETE cascade,
+    user_id         text    NOT NULL REFERENCES users (id) ON DELETE cascade,
+    source          text    NOT NULL,
+    PRIMARY KEY (recording_id, media_id)
+);
+
+CREATE INDEX voice_recording_tracks_media_id ON voice_recording_tracks (media_id);
@@ -8,8 +8,9 @@ use utoipa::{
 
 use crate::{
     auth::routes::AuthApiDocs, bot::routes::BotsApiDoc, channels::routes::ChannelsApiDocs,
-    friends::management::FriendsManagementApiDoc, friends::messaging::FriendsMessagingApiDoc,
-    guilds::routes::GuildsApiDocs, invites::routes::InvitesApiDoc, media::routes::MediaApiDocs,
+    friends::groups::GroupsApiDoc, friends::management::FriendsManagementApiDoc,
+    friends::messaging::FriendsMessagingApiDoc, guilds::routes::GuildsApiDocs,
+    invites::routes::InvitesApiDoc, media::routes::MediaApiDocs,
     messaging::routes::MessagingApiDocs, realtime::pubsub::PubSubApiDoc,
     voice::routes::VoiceApiDoc,
 };
@@ -37,6 +38,7 @@ pub fn setup_oapi() -> utoipa::openapi::OpenApi {
     oapi.merge(BotsApiDoc::openapi());
     oapi.merge(FriendsManagementApiDoc::openapi());
     oapi.merge(FriendsMessagingApiDoc::openapi());
+    oapi.merge(GroupsApiDoc::openapi());
     oapi.merge(InvitesApiDoc::openapi());
     oapi
 }
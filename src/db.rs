@@ -7,8 +7,9 @@ use sqlx::{Pool, Postgres};
 
 use crate::auth::user::{PublicUserInfo, User};
 use crate::crypto;
-use crate::friends::friend_request::{FriendRequest, FriendRequestType};
-use crate::messaging::message::Message;
+use crate::friends::friend_request::{FriendRequest, FriendRequestPrivacy, FriendRequestType};
+use crate::media::{record::Media, signing};
+use crate::messaging::{message::Message, unfurl};
 
 pub type DB = Data<Database>;
 
@@ -16,10 +17,6 @@ pub struct Database {
     pub pool: Pool<Postgres>,
 }
 
-pub struct Friends {
-    pub friends: Vec<String>,
-}
-
 impl Database {
     pub fn with_pool(pool: Pool<Postgres>) -> Self {
         Self { pool }
@@ -158,6 +155,75 @@ impl Database {
         self.can_user_see_channel(user_id, channel_id).await
     }
 
+    /// Whether the user can join a voice channel. Only voice channels in
+    /// guilds the user is in can be joined.
+    pub async fn can_user_connect_to_voice_in(
+        &self,
+        user_id: &str,
+        channel_id: &str,
+    ) -> Result<bool, sqlx::Error> {
+        // TODO: check a connect permission once channels have permissions
+        let result = sqlx::query!(
+            r#"
+            SELECT EXISTS (
+                SELECT 1
+                    FROM members, channels
+                    WHERE (
+                        channels.id = $1            AND
+                        channels.type = 'voice'     AND
+                        members.user_id = $2        AND
+                        members.guild_id = channels.guild_id
+                    )
+            ) AS "can_connect!"
+            "#,
+            channel_id,
+            user_id
+        )
+        .fetch_one(&self.pool)
+        .await?
+        .can_connect;
+
+        Ok(result)
+    }
+
+    /// Whether the user can mute, deafen, move and disconnect other people in
+    /// the voice channels of a guild.
+    pub async fn can_user_moderate_voice_in(
+        &self,
+        user_id: &str,
+        guild_id: &str,
+    ) -> Result<bool, sqlx::Error> {
+        // TODO: make this a permission once guilds have roles
+        let result = sqlx::query!(
+            r#"SELECT EXISTS (SELECT 1 FROM guilds WHERE id = $1 AND owner = $2) AS "is_owner!""#,
+            guild_id,
+            user_id
+        )
+        .fetch_one(&self.pool)
+        .await?
+        .is_owner;
+
+        Ok(result)
+    }
+
+    pub async fn can_user_record_voice_in(
+        &self,
+        user_id: &str,
+        guild_id: &str,
+    ) -> Result<bool, sqlx::Error> {
+        // TODO: make this a permission once guilds have roles
+        let result = sqlx::query!(
+            r#"SELECT EXISTS (SELECT 1 FROM guilds WHERE id = $1 AND owner = $2) AS "is_owner!""#,
+            guild_id,
+            user_id
+        )
+        .fetch_one(&self.pool)
+        .await?
+        .is_owner;
+
+        Ok(result)
+    }
+
     // TODO: Add permissions
     pub async fn can_user_create_invite_in(
         &self,
@@ -196,6 +262,7 @@ impl Database {
                 messages.content, 
                 messages.created_at,
                 messages.attachments,
+                messages.embeds,
                 users.name AS "author_username",
                 users.avatar AS "author_avatar",
                 users.id AS "author_id",
@@ -212,21 +279,20 @@ impl Database {
         )
         .fetch_one(&self.pool)
         .map_ok(|record| {
-            let attachments = match record.attachments.clone() {
-                Some(some) => serde_json::from_value(some).ok(),
-                None => None,
-            };
+            let attachments = signing::attachments_from_json(record.attachments.clone());
 
             Message {
                 id: record.id.clone(),
                 content: record.content.clone(),
                 attachments,
+                embeds: unfurl::embeds_from_json(record.embeds.clone()),
                 created_at: DateTime::<Utc>::from_naive_utc_and_offset(record.created_at, Utc),
                 author: PublicUserInfo {
                     id: record.author_id.clone(),
                     username: record.author_username.clone(),
                     avatar: record.author_avatar.clone(),
                 },
+                author_blocked: false,
             }
         })
         .await?;
@@ -246,16 +312,105 @@ impl Database {
     }
 
     pub async fn is_user_friend(&self, me_id: &str, friend_id: &str) -> Result<bool, sqlx::Error> {
-        sqlx::query_as!(
-            Friends,
-            r#"SELECT friends 
-                FROM users 
+        let (user_a, user_b) = friendship_key(me_id, friend_id);
+
+        let result = sqlx::query!(
+            "SELECT EXISTS (SELECT 1 FROM friendships WHERE user_a = $1 AND user_b = $2) AS is_friend",
+            user_a,
+            user_b
+        )
+        .fetch_one(&self.pool)
+        .await?
+        .is_friend
+        .unwrap();
+
+        Ok(result)
+    }
+
+    /// Returns true if `blocker_id` has blocked `blocked_id`. This is one-way,
+    /// use `is_block_between` to check both directions.
+    pub async fn is_user_blocked(
+        &self,
+        blocker_id: &str,
+        blocked_id: &str,
+    ) -> Result<bool, sqlx::Error> {
+        let result = sqlx::query!(
+            "SELECT EXISTS (SELECT 1 FROM blocks WHERE blocker = $1 AND blocked = $2) AS is_blocked",
+            blocker_id,
+            blocked_id
+        )
+        .fetch_one(&self.pool)
+        .await?
+        .is_blocked
+        .unwrap();
+
+        Ok(result)
+    }
+
+    /// Returns true if either user has blocked the other.
+    pub async fn is_block_between(&self, id1: &str, id2: &str) -> Result<bool, sqlx::Error> {
+        let result = sqlx::query!(
+            r#"SELECT EXISTS (
+                SELECT 1 FROM blocks
+                WHERE (blocker = $1 AND blocked = $2)
+                OR (blocker = $2 AND blocked = $1)
+            ) AS is_blocked"#,
+            id1,
+            id2
+        )
+        .fetch_one(&self.pool)
+        .await?
+        .is_blocked
+        .unwrap();
+
+        Ok(result)
+    }
+
+    pub async fn have_mutual_friend(&self, id1: &str, id2: &str) -> Result<bool, sqlx::Error> {
+        let result = sqlx::query!(
+            r#"SELECT EXISTS (
+                SELECT 1
+                FROM friendships AS f1, friendships AS f2
+                WHERE (f1.user_a = $1 OR f1.user_b = $1)
+                AND (f2.user_a = $2 OR f2.user_b = $2)
+                AND (CASE WHEN f1.user_a = $1 THEN f1.user_b ELSE f1.user_a END)
+                    = (CASE WHEN f2.user_a = $2 THEN f2.user_b ELSE f2.user_a END)
+            ) AS have_mutual"#,
+            id1,
+            id2
+        )
+        .fetch_one(&self.pool)
+        .await?
+        .have_mutual
+        .unwrap();
+
+        Ok(result)
+    }
+
+    /// Checks the recipient's friend request privacy setting. Blocks are not
+    /// taken into account here, check those separately.
+    pub async fn can_user_send_friend_request_to(
+        &self,
+        sender_id: &str,
+        recipient_id: &str,
+    ) -> Result<bool, sqlx::Error> {
+        let privacy = sqlx::query!(
+            r#"SELECT friend_request_privacy AS "privacy: FriendRequestPrivacy"
+                FROM users
                 WHERE id = $1"#,
-            me_id
+            recipient_id
         )
         .fetch_one(&self.pool)
-        .await
-        .map(|e| e.friends.contains(&friend_id.to_string()))
+        .await?
+        .privacy;
+
+        match privacy {
+            FriendRequestPrivacy::Everyone => Ok(true),
+            FriendRequestPrivacy::FriendsOfFriends => {
+                self.have_mutual_friend(sender_id, recipient_id).await
+            }
+            FriendRequestPrivacy::Nobody => Ok(false),
+        }
     }
 
     pub async fn list_incoming_friend_requests(
@@ -303,27 +458,37 @@ impl Database {
         })
     }
     pub async fn add_friends(&self, id1: &str, id2: &str) -> Result<(), sqlx::Error> {
-        // This can create duplicate friends, be careful
+        let (user_a, user_b) = friendship_key(id1, id2);
+
         sqlx::query!(
-            r#"UPDATE users
-                SET friends = ARRAY_APPEND(friends,$1)
-                WHERE id=$2"#,
-            id1,
-            id2
+            r#"INSERT INTO friendships (user_a, user_b)
+                VALUES ($1, $2)
+                ON CONFLICT DO NOTHING"#,
+            user_a,
+            user_b
         )
         .execute(&self.pool)
         .await?;
-        sqlx::query!(
-            r#"UPDATE users
-                SET friends = ARRAY_APPEND(friends,$1)
-                WHERE id=$2"#,
-            id2,
-            id1
+
+        Ok(())
+    }
+
+    /// Returns true if the two users were friends before this call
+    pub async fn remove_friends(&self, id1: &str, id2: &str) -> Result<bool, sqlx::Error> {
+        let (user_a, user_b) = friendship_key(id1, id2);
+
+        let rows_affected = sqlx::query!(
+            "DELETE FROM friendships WHERE user_a = $1 AND user_b = $2",
+            user_a,
+            user_b
         )
         .execute(&self.pool)
-        .await?;
-        Ok(())
+        .await?
+        .rows_affected();
+
+        Ok(rows_affected > 0)
     }
+
     pub async fn get_dm_channel_id(
         &self,
         from_id: &str,
@@ -357,4 +522,270 @@ impl Database {
 
         Ok(channel_id)
     }
+
+    /// Marks everything in a DM channel as read for a user, up to now.
+    pub async fn mark_dm_read(&self, channel_id: &str, user_id: &str) -> Result<(), sqlx::Error> {
+        sqlx::query!(
+            r#"INSERT INTO dm_read_states (channel_id, user_id, last_read_at)
+                VALUES ($1, $2, now())
+                ON CONFLICT (channel_id, user_id) DO UPDATE SET last_read_at = now()"#,
+            channel_id,
+            user_id
+        )
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Returns the IDs of everyone in a group DM, longest-standing first.
+    /// Everyone a group was created with joined at the same time, so they are
+    /// ordered by id. The list is empty if the group does not exist.
+    pub async fn get_group_dm_participants(
+        &self,
+        group_id: &str,
+    ) -> Result<Vec<String>, sqlx::Error> {
+        let participants = sqlx::query!(
+            r#"SELECT group_dm_participants.user_id
+                FROM group_dm_participants, dmchannels
+                WHERE group_dm_participants.channel_id = $1
+                AND dmchannels.id = group_dm_participants.channel_id
+                AND dmchannels.is_group
+                ORDER BY group_dm_participants.joined_at, group_dm_participants.user_id"#,
+            group_id
+        )
+        .fetch_all(&self.pool)
+        .await?
+        .into_iter()
+        .map(|r| r.user_id)
+        .collect();
+
+        Ok(participants)
+    }
+
+    pub async fn insert_media(&self, media: &Media) -> Result<(), sqlx::Error> {
+        sqlx::query!(
+            r#"INSERT INTO media (id, uploader, name, size, sha256, mime_type, width, height, created_at)
+                VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9)"#,
+            media.id,
+            media.uploader,
+            media.name,
+            media.size,
+            media.sha256,
+            media.mime_type,
+            media.width,
+            media.height,
+            media.created_at
+        )
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Returns the media with the given ids that were uploaded by `uploader`.
+    /// Ids that don't exist or belong to someone else are left out.
+    pub async fn get_owned_media(
+        &self,
+        uploader: &str,
+        ids: &[String],
+    ) -> Result<Vec<Media>, sqlx::Error> {
+        let media = sqlx::query_as!(
+            Media,
+            r#"SELECT id, uploader, name, size, sha256, mime_type, width, height, created_at
+                FROM media
+                WHERE uploader = $1 AND id = ANY($2)"#,
+            uploader,
+            ids
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(media)
+    }
+
+    /// Returns how many bytes of media a user has uploaded and how many files
+    /// that is.
+    pub async fn get_media_usage(&self, uploader: &str) -> Result<(i64, i64), sqlx::Error> {
+        let usage = sqlx::query!(
+            r#"SELECT COALESCE(SUM(size), 0)::bigint AS "bytes!", COUNT(*) AS "files!"
+                FROM media
+                WHERE uploader = $1"#,
+            uploader
+        )
+        .fetch_one(&self.pool)
+        .await?;
+
+        Ok((usage.bytes, usage.files))
+    }
+
+    /// Media used as an avatar or an icon can be downloaded by anyone.
+    pub async fn is_media_public(&self, media: &Media) -> Result<bool, sqlx::Error> {
+        self.is_media_url_public(&media.url()).await
+    }
+
+    /// Whether a file is used as an avatar or icon, which anyone can see
+    pub async fn is_media_url_public(&self, url: &str) -> Result<bool, sqlx::Error> {
+        let result = sqlx::query!(
+            r#"SELECT (
+                EXISTS (SELECT 1 FROM users WHERE avatar = $1)
+                OR EXISTS (SELECT 1 FROM guilds WHERE icon = $1)
+                OR EXISTS (SELECT 1 FROM dmchannels WHERE icon = $1)
+            ) AS "public!""#,
+            url
+        )
+        .fetch_one(&self.pool)
+        .await?
+        .public;
+
+        Ok(result)
+    }
+
+    pub async fn is_user_in_dm_channel(
+        &self,
+        user_id: &str,
+        channel_id: &str,
+    ) -> Result<bool, sqlx::Error> {
+        let result = sqlx::query!(
+            r#"SELECT EXISTS (
+                SELECT 1 FROM dmchannels
+                WHERE id = $1 AND (
+                    from_user = $2
+                    OR to_user = $2
+                    OR EXISTS (
+                        SELECT 1 FROM group_dm_participants
+                        WHERE channel_id = $1 AND user_id = $2
+                    )
+                )
+            ) AS "is_in_channel!""#,
+            channel_id,
+            user_id
+        )
+        .fetch_one(&self.pool)
+        .await?
+        .is_in_channel;
+
+        Ok(result)
+    }
+
+    /// Whether a user can download a file: they uploaded it, or it is attached
+    /// to a message they can see.
+    pub async fn can_user_access_media(
+        &self,
+        user_id: &str,
+        media: &Media,
+    ) -> Result<bool, sqlx::Error> {
+        if media.uploader == user_id {
+            return Ok(true);
+        }
+
+        let channels = sqlx::query!(
+            r#"SELECT DISTINCT messages.channel_id
+                FROM message_attachments, messages
+                WHERE message_attachments.media_id = $1
+                AND messages.id = message_attachments.message_id"#,
+            media.id
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        for channel in channels {
+            if self.can_user_view_messages_in(user_id, &channel.channel_id).await?
+                || self.is_user_in_dm_channel(user_id, &channel.channel_id).await?
+            {
+                return Ok(true);
+            }
+        }
+
+        // voice recordings can be downloaded by whoever can join the channel
+        let recorded_in = sqlx::query!(
+            r#"SELECT voice_recordings.channel_id
+                FROM voice_recording_tracks, voice_recordings
+                WHERE voice_recording_tracks.media_id = $1
+                AND voice_recordings.id = voice_recording_tracks.recording_id"#,
+            media.id
+        )
+        .fetch_optional(&self.pool)
+        .await?;
+
+        if let Some(recorded_in) = recorded_in {
+            return self
+                .can_user_connect_to_voice_in(user_id, &recorded_in.channel_id)
+                .await;
+        }
+
+        Ok(false)
+    }
+
+    /// Whether a user can see a message that a file uploaded before media was
+    /// recorded in the database is attached to. Those attachments are only
+    /// known from the messages themselves.
+    pub async fn can_user_access_legacy_attachment(
+        &self,
+        user_id: &str,
+        media_id: &str,
+    ) -> Result<bool, sqlx::Error> {
+        let channels = sqlx::query!(
+            r#"SELECT DISTINCT messages.channel_id
+                FROM messages, json_array_elements(messages.attachments) AS attachment
+                WHERE json_typeof(messages.attachments) = 'array'
+                AND attachment->>'id' = $1"#,
+            media_id
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        for channel in channels {
+            if self.can_user_view_messages_in(user_id, &channel.channel_id).await?
+                || self.is_user_in_dm_channel(user_id, &channel.channel_id).await?
+            {
+                return Ok(true);
+            }
+        }
+
+        Ok(false)
+    }
+
+    pub async fn get_media(&self, id: &str) -> Result<Option<Media>, sqlx::Error> {
+        let media = sqlx::query_as!(
+            Media,
+            r#"SELECT id, uploader, name, size, sha256, mime_type, width, height, created_at
+                FROM media
+                WHERE id = $1"#,
+            id
+        )
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(media)
+    }
+}
+
+/// Friendships are stored once per pair of users, ordered so that the smaller
+/// id always comes first. Ids are compared byte-wise, the same as the
+/// `COLLATE "C"` columns they are stored in.
+fn friendship_key<'a>(id1: &'a str, id2: &'a str) -> (&'a str, &'a str) {
+    (std::cmp::min(id1, id2), std::cmp::max(id1, id2))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::friendship_key;
+
+    #[test]
+    fn friendship_key_is_the_same_either_way() {
+        assert_eq!(friendship_key("abc", "xyz"), ("abc", "xyz"));
+        assert_eq!(friendship_key("xyz", "abc"), ("abc", "xyz"));
+        assert_eq!(friendship_key("abc", "abc"), ("abc", "abc"));
+    }
+
+    #[test]
+    fn friendship_key_compares_bytes() {
+        // a linguistic collation would put these the other way around
+        assert_eq!(friendship_key("a", "B"), ("B", "a"));
+        assert_eq!(friendship_key("_a", "Z"), ("Z", "_a"));
+        assert_eq!(friendship_key("a", "_"), ("_", "a"));
+        assert_eq!(friendship_key("9", "-"), ("-", "9"));
+        assert_eq!(friendship_key("ab", "a-"), ("a-", "ab"));
+    }
 }
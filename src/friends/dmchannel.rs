@@ -6,20 +6,98 @@ use actix_web::{web::Path, FromRequest};
 use crate::db::DB;
 use crate::error::macros::err;
 use crate::error::{HResult, HandlerError, IntoHandlerErrorResult};
+use crate::realtime::pubsub::topic::{Topic, TopicType};
 use futures::Future;
 use serde::Deserialize;
 use utoipa::IntoParams;
 
 use crate::auth::access_token::AccessToken;
 
+/// The largest number of people that can be in a group DM, including the owner
+pub const MAX_GROUP_DM_PARTICIPANTS: usize = 10;
+
 #[derive(Deserialize, IntoParams)]
 pub struct DMPath {
     pub user_id: String,
 }
 
+#[derive(Deserialize, IntoParams)]
+pub struct GroupDMPath {
+    pub group_id: String,
+}
+
+/// A direct message channel, either between two friends or a group DM. This
+/// is extracted from a `{user_id}` path parameter for direct DMs and from a
+/// `{group_id}` path parameter for group DMs.
+#[derive(Clone)]
 pub struct DMChannel {
     pub id: String,
-    pub to_user_id: String,
+    /// The user making the request
+    pub from_user_id: String,
+    pub is_group: bool,
+    /// Everyone in the channel, including the user making the request
+    pub participants: Vec<String>,
+}
+
+impl DMChannel {
+    /// The topic that events in this channel should be delivered on for a
+    /// certain participant.
+    ///
+    /// For direct DMs this is the other user's ID relative to the participant,
+    /// for group DMs it is the group's ID.
+    pub fn topic_for(&self, participant_id: &str) -> Topic {
+        if self.is_group {
+            return Topic::new(TopicType::GroupDm, self.id.clone());
+        }
+
+        let other = self
+            .participants
+            .iter()
+            .find(|p| *p != participant_id)
+            // somehow we are in a dm with ourselves
+            .map_or(participant_id, |p| p.as_str());
+
+        Topic::new(TopicType::DmChannel, other.to_string())
+    }
+
+    async fn direct(db: &DB, from_id: &str, to_id: &str) -> HResult<Self> {
+        if db.is_block_between(from_id, to_id).await? {
+            err!(403, "You cannot message this user.")?;
+        }
+
+        if !db.is_user_friend(from_id, to_id).await? {
+            err!(
+                403,
+                "You cannot message this user as you are not their friend."
+            )?;
+        }
+
+        let mut participants = vec![from_id.to_string(), to_id.to_string()];
+        participants.dedup();
+
+        Ok(Self {
+            id: db.get_dm_channel_id(from_id, to_id).await.or_err(500)?,
+            from_user_id: from_id.to_string(),
+            is_group: false,
+            participants,
+        })
+    }
+
+    async fn group(db: &DB, from_id: &str, group_id: &str) -> HResult<Self> {
+        let participants = db.get_group_dm_participants(group_id).await?;
+
+        // also covers the group not existing
+        if !participants.iter().any(|p| p == from_id) {
+            err!(403, "You are not in this group.")?;
+        }
+
+        Ok(Self {
+            id: group_id.to_string(),
+            from_user_id: from_id.to_string(),
+            is_group: true,
+            participants,
+        })
+    }
 }
 
 impl FromRequest for DMChannel {
@@ -30,25 +108,23 @@ impl FromRequest for DMChannel {
         let req = req.clone();
 
         Box::pin(async move {
-            let to_id = &Path::<DMPath>::from_request(&req, &mut Payload::None)
-                .await
-                .or_err(400)?
-                .user_id;
-
             let from_id = &AccessToken::from_request(&req, &mut Payload::None)
                 .await?
                 .user_id;
 
             let db = req.app_data::<DB>().or_err(500)?;
 
-            if !db.is_user_friend(from_id, to_id).await? {
-                err!(403, "You cannot message this user as you are not their friend.")?;
+            // check for a group first, as group routes may also have a
+            // `{user_id}` parameter, eg. for managing participants
+            if let Ok(path) = Path::<GroupDMPath>::from_request(&req, &mut Payload::None).await {
+                return Self::group(db, from_id, &path.group_id).await;
             }
 
-            Ok(Self {
-                id: db.get_dm_channel_id(from_id, to_id).await.or_err(500)?,
-                to_user_id: to_id.clone(),
-            })
+            let path = Path::<DMPath>::from_request(&req, &mut Payload::None)
+                .await
+                .or_err(400)?;
+
+            Self::direct(db, from_id, &path.user_id).await
         })
     }
 }
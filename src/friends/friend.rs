@@ -0,0 +1,15 @@
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use utoipa::ToSchema;
+
+/// A user in your friends list, along with when you became friends
+#[derive(Serialize, ToSchema)]
+pub struct Friend {
+    #[schema(example = "xoKM4W7NDqHjK_V0g9s3y")]
+    pub id: String,
+    #[schema(example = "someone#1234")]
+    pub username: String,
+    #[schema(example = "/media/9ybevZcdBh-3Z2KRLBidT/avatar.png")]
+    pub avatar: String,
+    pub since: DateTime<Utc>,
+}
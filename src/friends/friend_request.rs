@@ -17,6 +17,19 @@ pub struct FriendRequest {
     pub user: PublicUserInfo,
 }
 
+/// Who is allowed to send a user friend requests
+#[derive(Copy, Clone, PartialEq, Eq, sqlx::Type, Serialize, Deserialize, Debug, ToSchema)]
+#[sqlx(type_name = "friend_request_privacy", rename_all = "snake_case")]
+#[serde(rename_all = "snake_case")]
+pub enum FriendRequestPrivacy {
+    /// Anyone who isn't blocked
+    Everyone,
+    /// Only users who share at least one friend
+    FriendsOfFriends,
+    /// No one, but outgoing requests can still be sent
+    Nobody,
+}
+
 #[derive(Serialize, Deserialize, IntoParams)]
 pub struct UserIdParams {
     pub user_id: String,
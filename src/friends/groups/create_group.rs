@@ -0,0 +1,134 @@
+use actix_web::{
+    post,
+    web::{Data, Json},
+};
+use nanoid::nanoid;
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use crate::{
+    auth::access_token::AccessToken,
+    db::DB,
+    error::{macros::err, HResult},
+    friends::{
+        dmchannel::{DMChannel, MAX_GROUP_DM_PARTICIPANTS},
+        groups::validate_group_details,
+    },
+    media::storage::Storage,
+    realtime::pubsub::pubsub::PubSub,
+};
+
+#[derive(Deserialize, ToSchema)]
+pub struct CreateGroupRequest {
+    #[schema(example = "Study Group")]
+    name: Option<String>,
+    /// ID of an image you uploaded through the media API
+    #[schema(example = "s6NIiu2oOh1FEL0Xfjc7n")]
+    icon: Option<String>,
+    /// IDs of the friends to add to the group, not including yourself
+    participants: Vec<String>,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct CreateGroupResponse {
+    #[schema(example = "rMBrzZ7FQk6ZImWlTiRPo")]
+    id: String,
+}
+
+/// Create Group DM
+///
+/// Creates a group DM with some of your friends. You will be the owner of the
+/// group. A group can have at most 10 participants, including the owner.
+#[utoipa::path(
+    responses(
+        (status = OK, description = "Group created", body = CreateGroupResponse),
+        (status = BAD_REQUEST, description = "Invalid name, icon or participant list"),
+        (status = FORBIDDEN, description = "You are not friends with one of the participants")
+    ),
+    tag = "DMs",
+    security(("token" = []))
+)]
+#[post("/groups")]
+pub async fn create_group(
+    db: DB,
+    storage: Storage,
+    token: AccessToken,
+    pubsub: Data<PubSub>,
+    req: Json<CreateGroupRequest>,
+) -> HResult<Json<CreateGroupResponse>> {
+    let req = req.into_inner();
+
+    let icon = validate_group_details(
+        &db,
+        &storage,
+        &token.user_id,
+        req.name.as_deref(),
+        req.icon.as_deref(),
+    )
+    .await?;
+
+    let mut participants = req.participants;
+    participants.sort();
+    participants.dedup();
+    participants.retain(|p| *p != token.user_id);
+
+    if participants.is_empty() {
+        err!(400, "A group needs at least one other participant.")?;
+    }
+
+    if participants.len() + 1 > MAX_GROUP_DM_PARTICIPANTS {
+        err!(
+            400,
+            format!(
+                "A group cannot have more than {} participants.",
+                MAX_GROUP_DM_PARTICIPANTS
+            )
+        )?;
+    }
+
+    for participant in participants.iter() {
+        if !db.is_user_friend(&token.user_id, participant).await? {
+            err!(403, "You can only add your friends to a group.")?;
+        }
+    }
+
+    // the owner goes first in the list everyone is sent now. Everyone joins at
+    // the same time, so later reads order the participants by id
+    participants.insert(0, token.user_id.clone());
+
+    let group_id = nanoid!();
+    let mut tx = db.pool.begin().await?;
+
+    sqlx::query!(
+        r#"INSERT INTO dmchannels (id, is_group, name, icon, owner) VALUES ($1, true, $2, $3, $4)"#,
+        group_id,
+        req.name,
+        icon,
+        token.user_id
+    )
+    .execute(&mut tx)
+    .await?;
+
+    for participant in participants.iter() {
+        sqlx::query!(
+            r#"INSERT INTO group_dm_participants (channel_id, user_id) VALUES ($1, $2)"#,
+            group_id,
+            participant
+        )
+        .execute(&mut tx)
+        .await?;
+    }
+
+    tx.commit().await?;
+
+    let channel = DMChannel {
+        id: group_id.clone(),
+        from_user_id: token.user_id.clone(),
+        is_group: true,
+        participants,
+    };
+
+    pubsub.notify_group_dm_update(&channel).await;
+
+    Ok(Json(CreateGroupResponse { id: group_id }))
+}
@@ -0,0 +1,66 @@
+use actix_web::{get, web::Json};
+use serde::Serialize;
+use utoipa::ToSchema;
+
+use crate::{
+    auth::user::PublicUserInfo,
+    db::DB,
+    error::HResult,
+    friends::dmchannel::{DMChannel, GroupDMPath},
+};
+
+#[derive(Serialize, ToSchema)]
+pub struct GroupInfo {
+    #[schema(example = "rMBrzZ7FQk6ZImWlTiRPo")]
+    pub id: String,
+    #[schema(example = "Study Group")]
+    pub name: Option<String>,
+    #[schema(example = "/media/s6NIiu2oOh1FEL0Xfjc7n/cat.jpg")]
+    pub icon: Option<String>,
+    /// ID of the owner, this is only empty if the owner deleted their account
+    #[schema(example = "xoKM4W7NDqHjK_V0g9s3y")]
+    pub owner: Option<String>,
+    pub participants: Vec<PublicUserInfo>,
+}
+
+/// Get Group DM
+///
+/// Get the name, icon, owner and participants of a group DM you are in.
+#[utoipa::path(
+    params(GroupDMPath),
+    responses(
+        (status = OK, description = "Group information", body = GroupInfo),
+        (status = FORBIDDEN, description = "You are not in this group")
+    ),
+    tag = "DMs",
+    security(("token" = []))
+)]
+#[get("/groups/{group_id}")]
+pub async fn get_group(db: DB, channel: DMChannel) -> HResult<Json<GroupInfo>> {
+    let group = sqlx::query!(
+        r#"SELECT name, icon, owner FROM dmchannels WHERE id = $1"#,
+        channel.id
+    )
+    .fetch_one(&db.pool)
+    .await?;
+
+    let participants = sqlx::query_as!(
+        PublicUserInfo,
+        r#"SELECT users.id, users.name AS "username", users.avatar
+            FROM group_dm_participants, users
+            WHERE group_dm_participants.channel_id = $1
+            AND users.id = group_dm_participants.user_id
+            ORDER BY group_dm_participants.joined_at, group_dm_participants.user_id"#,
+        channel.id
+    )
+    .fetch_all(&db.pool)
+    .await?;
+
+    Ok(Json(GroupInfo {
+        id: channel.id,
+        name: group.name,
+        icon: group.icon,
+        owner: group.owner,
+        participants,
+    }))
+}
@@ -0,0 +1,79 @@
+use utoipa::OpenApi;
+
+use crate::{
+    db::DB,
+    error::{macros::err, HResult},
+    friends::dmchannel::DMChannel,
+    media::{attachments::resolve_square_image, storage::Storage},
+};
+
+pub mod create_group;
+pub mod get_group;
+pub mod participants;
+pub mod update_group;
+
+pub fn configure_app(cfg: &mut actix_web::web::ServiceConfig) {
+    cfg.service(create_group::create_group);
+    cfg.service(get_group::get_group);
+    cfg.service(update_group::update_group);
+    cfg.service(participants::add_participant);
+    cfg.service(participants::remove_participant);
+}
+
+/// Checks the optional name and icon of a group DM, shared between creating
+/// and updating a group. The icon is the ID of an image uploaded by `user_id`,
+/// its URL is returned.
+pub async fn validate_group_details(
+    db: &DB,
+    storage: &Storage,
+    user_id: &str,
+    name: Option<&str>,
+    icon: Option<&str>,
+) -> HResult<Option<String>> {
+    if let Some(name) = name {
+        if name.trim().is_empty() || name.chars().count() > 32 {
+            err!(400, "Group name must be between 1 and 32 characters.")?;
+        }
+    }
+
+    match icon {
+        Some(icon) => Ok(Some(
+            resolve_square_image(db, storage, user_id, icon).await?,
+        )),
+        None => Ok(None),
+    }
+}
+
+pub async fn is_group_owner(db: &DB, channel: &DMChannel) -> Result<bool, sqlx::Error> {
+    let result = sqlx::query!(
+        r#"SELECT EXISTS (
+            SELECT 1 FROM dmchannels WHERE id = $1 AND owner = $2
+        ) AS is_owner"#,
+        channel.id,
+        channel.from_user_id
+    )
+    .fetch_one(&db.pool)
+    .await?
+    .is_owner
+    .unwrap();
+
+    Ok(result)
+}
+
+#[derive(OpenApi)]
+#[openapi(
+    paths(
+        create_group::create_group,
+        get_group::get_group,
+        update_group::update_group,
+        participants::add_participant,
+        participants::remove_participant
+    ),
+    components(schemas(
+        create_group::CreateGroupRequest,
+        create_group::CreateGroupResponse,
+        get_group::GroupInfo,
+        update_group::UpdateGroupRequest
+    ))
+)]
+pub struct GroupsApiDoc;
@@ -0,0 +1,190 @@
+use actix_web::{
+    delete, put,
+    web::{Data, Path},
+    HttpResponse,
+};
+use serde::Deserialize;
+use utoipa::IntoParams;
+
+use crate::{
+    db::DB,
+    error::{macros::err, HResult, IntoHandlerErrorResult},
+    friends::{
+        dmchannel::{DMChannel, MAX_GROUP_DM_PARTICIPANTS},
+        groups::is_group_owner,
+    },
+    realtime::pubsub::pubsub::PubSub,
+};
+
+#[derive(Deserialize, IntoParams)]
+pub struct GroupParticipantPath {
+    pub group_id: String,
+    pub user_id: String,
+}
+
+/// Add Group DM participant
+///
+/// Adds one of your friends to a group DM you are in. Any participant can add
+/// their own friends, as long as the group is not full.
+#[utoipa::path(
+    params(GroupParticipantPath),
+    responses(
+        (status = OK, description = "Participant added"),
+        (status = BAD_REQUEST, description = "The group is full or the user is already in it"),
+        (status = FORBIDDEN, description = "You are not in this group, or not friends with the user")
+    ),
+    tag = "DMs",
+    security(("token" = []))
+)]
+#[put("/groups/{group_id}/participants/{user_id}")]
+pub async fn add_participant(
+    db: DB,
+    mut channel: DMChannel,
+    pubsub: Data<PubSub>,
+    path: Path<GroupParticipantPath>,
+) -> HResult<HttpResponse> {
+    if channel.participants.contains(&path.user_id) {
+        err!(400, "That user is already in this group.")?;
+    }
+
+    if channel.participants.len() >= MAX_GROUP_DM_PARTICIPANTS {
+        err!(400, "This group is full.")?;
+    }
+
+    if !db
+        .is_user_friend(&channel.from_user_id, &path.user_id)
+        .await?
+    {
+        err!(403, "You can only add your friends to a group.")?;
+    }
+
+    let mut tx = db.pool.begin().await?;
+
+    // adds to the same group wait for each other here, so two of them can't
+    // both take the last place
+    sqlx::query!(
+        r#"SELECT id FROM dmchannels WHERE id = $1 FOR UPDATE"#,
+        channel.id
+    )
+    .fetch_optional(&mut tx)
+    .await?
+    .or_err(404)?;
+
+    let added = sqlx::query!(
+        r#"INSERT INTO group_dm_participants (channel_id, user_id)
+            SELECT $1, $2
+            WHERE (SELECT count(*) FROM group_dm_participants WHERE channel_id = $1) < $3
+            ON CONFLICT DO NOTHING"#,
+        channel.id,
+        path.user_id,
+        MAX_GROUP_DM_PARTICIPANTS as i64
+    )
+    .execute(&mut tx)
+    .await?
+    .rows_affected();
+
+    if added == 0 {
+        let already_in = sqlx::query!(
+            r#"SELECT user_id FROM group_dm_participants WHERE channel_id = $1 AND user_id = $2"#,
+            channel.id,
+            path.user_id
+        )
+        .fetch_optional(&mut tx)
+        .await?
+        .is_some();
+
+        if already_in {
+            err!(400, "That user is already in this group.")?;
+        }
+        err!(400, "This group is full.")?;
+    }
+
+    tx.commit().await?;
+
+    // others may have joined since the channel was loaded
+    channel.participants = db.get_group_dm_participants(&channel.id).await?;
+    pubsub.notify_group_dm_update(&channel).await;
+
+    Ok(HttpResponse::Ok().finish())
+}
+
+/// Remove Group DM participant
+///
+/// Removes a participant from a group DM. You can always remove yourself, which
+/// leaves the group. Only the owner can remove other participants.
+///
+/// If the owner leaves, ownership passes to the longest standing participant.
+/// The group is deleted when the last participant leaves.
+#[utoipa::path(
+    params(GroupParticipantPath),
+    responses(
+        (status = OK, description = "Participant removed"),
+        (status = BAD_REQUEST, description = "That user is not in the group"),
+        (status = FORBIDDEN, description = "You are not the owner of this group")
+    ),
+    tag = "DMs",
+    security(("token" = []))
+)]
+#[delete("/groups/{group_id}/participants/{user_id}")]
+pub async fn remove_participant(
+    db: DB,
+    mut channel: DMChannel,
+    pubsub: Data<PubSub>,
+    path: Path<GroupParticipantPath>,
+) -> HResult<HttpResponse> {
+    let leaving = path.user_id == channel.from_user_id;
+
+    if !leaving && !is_group_owner(&db, &channel).await? {
+        err!(403, "Only the owner can remove other participants.")?;
+    }
+
+    if !channel.participants.contains(&path.user_id) {
+        err!(400, "That user is not in this group.")?;
+    }
+
+    channel.participants.retain(|p| *p != path.user_id);
+
+    let mut tx = db.pool.begin().await?;
+
+    sqlx::query!(
+        r#"DELETE FROM group_dm_participants WHERE channel_id = $1 AND user_id = $2"#,
+        channel.id,
+        path.user_id
+    )
+    .execute(&mut tx)
+    .await?;
+
+    match channel.participants.first() {
+        Some(longest_standing) => {
+            // hand the group over if the owner was the one removed
+            sqlx::query!(
+                r#"UPDATE dmchannels SET owner = $3, updated_at = now()
+                    WHERE id = $1 AND (owner = $2 OR owner IS NULL)"#,
+                channel.id,
+                path.user_id,
+                longest_standing
+            )
+            .execute(&mut tx)
+            .await?;
+        }
+        None => {
+            // nobody is left, so get rid of the group entirely
+            sqlx::query!("DELETE FROM messages WHERE channel_id = $1", channel.id)
+                .execute(&mut tx)
+                .await?;
+
+            sqlx::query!("DELETE FROM dmchannels WHERE id = $1", channel.id)
+                .execute(&mut tx)
+                .await?;
+        }
+    }
+
+    tx.commit().await?;
+
+    pubsub
+        .notify_group_dm_remove(&path.user_id, &channel.id)
+        .await;
+    pubsub.notify_group_dm_update(&channel).await;
+
+    Ok(HttpResponse::Ok().finish())
+}
@@ -0,0 +1,92 @@
+use actix_web::{
+    patch,
+    web::{Data, Json},
+    HttpResponse,
+};
+use serde::Deserialize;
+use utoipa::ToSchema;
+
+use crate::{
+    db::DB,
+    error::{macros::err, HResult},
+    friends::{
+        dmchannel::{DMChannel, GroupDMPath},
+        groups::{is_group_owner, validate_group_details},
+    },
+    media::storage::Storage,
+    realtime::pubsub::pubsub::PubSub,
+};
+
+#[derive(Deserialize, ToSchema)]
+pub struct UpdateGroupRequest {
+    #[schema(example = "Study Group")]
+    name: Option<String>,
+    /// ID of an image you uploaded through the media API
+    #[schema(example = "s6NIiu2oOh1FEL0Xfjc7n")]
+    icon: Option<String>,
+    /// Transfer ownership of the group to another participant
+    #[schema(example = "xoKM4W7NDqHjK_V0g9s3y")]
+    owner: Option<String>,
+}
+
+/// Update Group DM
+///
+/// Change the name or icon of a group DM, or transfer its ownership to another
+/// participant. Only the owner of the group can do this. Fields that are
+/// omitted are left unchanged.
+#[utoipa::path(
+    params(GroupDMPath),
+    responses(
+        (status = OK, description = "Group updated"),
+        (status = BAD_REQUEST, description = "Invalid name, icon or new owner"),
+        (status = FORBIDDEN, description = "You are not the owner of this group")
+    ),
+    tag = "DMs",
+    security(("token" = []))
+)]
+#[patch("/groups/{group_id}")]
+pub async fn update_group(
+    db: DB,
+    storage: Storage,
+    channel: DMChannel,
+    pubsub: Data<PubSub>,
+    req: Json<UpdateGroupRequest>,
+) -> HResult<HttpResponse> {
+    if !is_group_owner(&db, &channel).await? {
+        err!(403, "Only the owner can change this group.")?;
+    }
+
+    let icon = validate_group_details(
+        &db,
+        &storage,
+        &channel.from_user_id,
+        req.name.as_deref(),
+        req.icon.as_deref(),
+    )
+    .await?;
+
+    if let Some(ref owner) = req.owner {
+        if !channel.participants.contains(owner) {
+            err!(400, "The new owner must be in the group.")?;
+        }
+    }
+
+    sqlx::query!(
+        r#"UPDATE dmchannels
+            SET name = COALESCE($2, name),
+                icon = COALESCE($3, icon),
+                owner = COALESCE($4, owner),
+                updated_at = now()
+            WHERE id = $1"#,
+        channel.id,
+        req.name,
+        icon,
+        req.owner
+    )
+    .execute(&db.pool)
+    .await?;
+
+    pubsub.notify_group_dm_update(&channel).await;
+
+    Ok(HttpResponse::Ok().finish())
+}
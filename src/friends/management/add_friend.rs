@@ -26,11 +26,16 @@ pub enum AddFriendRequest {
 /// This endpoint supports two ways of adding a friend:
 /// - By ID: `POST /friends/requests { "id": "..." }`
 /// - By username: `POST /friends/requests { "username": "..." }`
+///
+/// Sending a new request respects the other user's friend request privacy
+/// setting. Accepting an incoming request is always allowed.
 #[utoipa::path(
     responses(
         (status = OK, description = "Friend request accepted", body = String),
         (status = OK, description = "Friend request sent", body = String),
         (status = NOT_FOUND, description = "User does not exist", body=String),
+        (status = FORBIDDEN, description = "That user is not accepting friend requests from you", body=String),
+        (status = BAD_REQUEST, description = "You have blocked that user", body=String),
         (status = BAD_REQUEST, description = "You are already friends with that user", body=String),
         (status = BAD_REQUEST, description = "An outgoing friend request to that user already exists", body=String)
     ),
@@ -73,6 +78,15 @@ pub async fn add_friend(
         }
     };
 
+    if db.is_user_blocked(&me.id, &user.id).await? {
+        err!(400, "You have blocked that user, unblock them first")?;
+    }
+
+    if db.is_user_blocked(&user.id, &me.id).await? {
+        // don't tell them that they're blocked
+        err!(403, "That user is not accepting friend requests from you")?;
+    }
+
     if db.is_user_friend(&me.id, &user.id).await? {
         err!(400, "You are already friends with that user")?;
     }
@@ -99,6 +113,10 @@ pub async fn add_friend(
     }
 
     // there is no incoming friend request, so we should create an outgoing one
+    if !db.can_user_send_friend_request_to(&me.id, &user.id).await? {
+        err!(403, "That user is not accepting friend requests from you")?;
+    }
+
     let rows_affected = sqlx::query!(
         r#"INSERT INTO friend_requests (from_user, to_user) VALUES ($1, $2) ON CONFLICT DO NOTHING"#,
         &me.id,
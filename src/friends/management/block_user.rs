@@ -0,0 +1,159 @@
+use actix_web::{
+    delete, get, post,
+    web::{Data, Json},
+};
+
+use crate::{
+    auth::{access_token::AccessToken, user::PublicUserInfo},
+    db::DB,
+    error::{macros::err, HResult},
+    friends::friend_request::{UserIdParams, UserIdPath},
+    realtime::pubsub::pubsub::PubSub,
+};
+
+/// Block a user
+///
+/// Blocks a user. This removes them from your friends list and deletes any
+/// pending friend requests between you. Blocked users cannot send you friend
+/// requests or direct messages, and their messages in guild channels are
+/// flagged with `authorBlocked` when you read message history.
+#[utoipa::path(
+    params(UserIdParams),
+    responses(
+        (status = OK, description = "User blocked", body = String),
+        (status = NOT_FOUND, description = "User does not exist", body = String),
+        (status = BAD_REQUEST, description = "You have already blocked that user", body = String)
+    ),
+    tag = "friends",
+    security(("token" = []))
+)]
+#[post("/friends/blocked/{user_id}")]
+pub async fn block_user(
+    db: DB,
+    pubsub: Data<PubSub>,
+    token: AccessToken,
+    path: UserIdPath,
+) -> HResult<Json<String>> {
+    if path.user_id == token.user_id {
+        err!(400, "You cannot block yourself")?;
+    }
+
+    if db.get_user_by_id(&path.user_id).await?.is_none() {
+        err!(404, "User not found")?;
+    }
+
+    let mut tx = db.pool.begin().await?;
+
+    let rows_affected = sqlx::query!(
+        r#"INSERT INTO blocks (blocker, blocked) VALUES ($1, $2) ON CONFLICT DO NOTHING"#,
+        token.user_id,
+        path.user_id
+    )
+    .execute(&mut tx)
+    .await?
+    .rows_affected();
+
+    if rows_affected == 0 {
+        err!(400, "You have already blocked that user")?;
+    }
+
+    let friends_removed = sqlx::query!(
+        r#"DELETE FROM friendships
+            WHERE (user_a = $1 AND user_b = $2)
+            OR (user_a = $2 AND user_b = $1)"#,
+        token.user_id,
+        path.user_id
+    )
+    .execute(&mut tx)
+    .await?
+    .rows_affected();
+
+    let requests_removed = sqlx::query!(
+        r#"DELETE FROM friend_requests
+            WHERE (from_user = $1 AND to_user = $2)
+            OR (from_user = $2 AND to_user = $1)"#,
+        token.user_id,
+        path.user_id
+    )
+    .execute(&mut tx)
+    .await?
+    .rows_affected();
+
+    tx.commit().await?;
+
+    // keep the other party's friend list consistent, this is the same thing
+    // they would see if we had just unfriended them
+    if friends_removed > 0 || requests_removed > 0 {
+        // note: this unwrap panics if authenticated user does not exist in db but this will rarely happen
+        let me: PublicUserInfo = db.get_user_by_id(&token.user_id).await?.unwrap().into();
+
+        if friends_removed > 0 {
+            pubsub.notify_friend_remove(&path.user_id, &me).await;
+        }
+
+        if requests_removed > 0 {
+            pubsub
+                .notify_friend_request_remove(&path.user_id, &me)
+                .await;
+        }
+    }
+
+    Ok(Json("User blocked".into()))
+}
+
+/// Unblock a user
+///
+/// Removes a user from your block list. This does not restore any friendship
+/// that was removed when they were blocked.
+#[utoipa::path(
+    params(UserIdParams),
+    responses(
+        (status = OK, description = "User unblocked", body = String),
+        (status = BAD_REQUEST, description = "You have not blocked that user", body = String)
+    ),
+    tag = "friends",
+    security(("token" = []))
+)]
+#[delete("/friends/blocked/{user_id}")]
+pub async fn unblock_user(db: DB, token: AccessToken, path: UserIdPath) -> HResult<Json<String>> {
+    let rows_affected = sqlx::query!(
+        r#"DELETE FROM blocks WHERE blocker = $1 AND blocked = $2"#,
+        token.user_id,
+        path.user_id
+    )
+    .execute(&db.pool)
+    .await?
+    .rows_affected();
+
+    if rows_affected == 0 {
+        err!(400, "You have not blocked that user")?;
+    }
+
+    Ok(Json("User unblocked".into()))
+}
+
+/// List blocked users
+///
+/// Lists all users that you have blocked.
+#[utoipa::path(
+    responses(
+        (status = OK, description = "Blocked users", body = Vec<PublicUserInfo>),
+    ),
+    tag = "friends",
+    security(("token" = []))
+)]
+#[get("/friends/blocked")]
+pub async fn list_blocked_users(db: DB, token: AccessToken) -> HResult<Json<Vec<PublicUserInfo>>> {
+    let result = sqlx::query_as!(
+        PublicUserInfo,
+        r#"SELECT users.id, users.name AS "username", users.avatar
+            FROM blocks, users
+            WHERE blocks.blocker = $1 AND users.id = blocks.blocked
+            ORDER BY blocks.created_at"#,
+        token.user_id
+    )
+    .fetch_all(&db.pool)
+    .await?;
+
+    Ok(Json(result))
+}
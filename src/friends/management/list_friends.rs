@@ -1,10 +1,7 @@
 use actix_web::{get, web::Json};
+use chrono::{DateTime, Utc};
 
-use crate::{
-    auth::{access_token::AccessToken, user::PublicUserInfo},
-    db::DB,
-    error::HResult,
-};
+use crate::{auth::access_token::AccessToken, db::DB, error::HResult, friends::friend::Friend};
 
 /// List Friends
 ///
@@ -12,24 +9,31 @@ use crate::{
 /// "friends" when a friend request is fully accepted on both sides.
 #[utoipa::path(
     responses(
-        (status = OK, description="Friends list", body=Vec<PublicUserInfo>),
+        (status = OK, description="Friends list", body=Vec<Friend>),
     ),
     tag="friends",
     security(("token" = []))
 )]
 #[get("/friends")]
-pub async fn list_friends(db: DB, token: AccessToken) -> HResult<Json<Vec<PublicUserInfo>>> {
-    let result = sqlx::query_as!(
-        PublicUserInfo,
-        r#"SELECT I.id,I.name as "username",I.avatar 
-            FROM users AS I 
-            JOIN users AS S 
-            ON I.id = ANY(S.friends) 
-            WHERE S.id = $1"#,
+pub async fn list_friends(db: DB, token: AccessToken) -> HResult<Json<Vec<Friend>>> {
+    let result = sqlx::query!(
+        r#"SELECT users.id, users.name AS "username", users.avatar, friendships.since
+            FROM friendships, users
+            WHERE (friendships.user_a = $1 AND users.id = friendships.user_b)
+            OR (friendships.user_b = $1 AND users.id = friendships.user_a)
+            ORDER BY friendships.since"#,
         token.user_id
     )
     .fetch_all(&db.pool)
-    .await?;
+    .await?
+    .into_iter()
+    .map(|record| Friend {
+        id: record.id,
+        username: record.username,
+        avatar: record.avatar,
+        since: DateTime::<Utc>::from_naive_utc_and_offset(record.since, Utc),
+    })
+    .collect();
 
     Ok(Json(result))
 }
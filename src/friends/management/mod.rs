@@ -1,11 +1,15 @@
 use utoipa::OpenApi;
 
 pub mod add_friend;
+pub mod block_user;
 pub mod list_friend_requests;
 pub mod list_friends;
 pub mod remove_friend;
 pub mod remove_friend_request;
-use crate::friends::friend_request::{FriendRequest, FriendRequestType};
+use crate::friends::{
+    friend::Friend,
+    friend_request::{FriendRequest, FriendRequestPrivacy, FriendRequestType},
+};
 
 use self::add_friend::AddFriendRequest;
 
@@ -15,6 +19,9 @@ pub fn configure_app(cfg: &mut actix_web::web::ServiceConfig) {
     cfg.service(remove_friend::remove_friend);
     cfg.service(add_friend::add_friend);
     cfg.service(remove_friend_request::remove_friend_request);
+    cfg.service(block_user::list_blocked_users);
+    cfg.service(block_user::block_user);
+    cfg.service(block_user::unblock_user);
 }
 
 #[derive(OpenApi)]
@@ -27,10 +34,15 @@ pub fn configure_app(cfg: &mut actix_web::web::ServiceConfig) {
         list_friend_requests::list_friend_requests,
         remove_friend::remove_friend,
         add_friend::add_friend,
-        remove_friend_request::remove_friend_request
+        remove_friend_request::remove_friend_request,
+        block_user::list_blocked_users,
+        block_user::block_user,
+        block_user::unblock_user
     ),
     components(schemas(
+        Friend,
         FriendRequest,
+        FriendRequestPrivacy,
         FriendRequestType,
         AddFriendRequest // as in "request for the add friend endpoint" not "add a friend request" 
     ))
@@ -0,0 +1,47 @@
+use actix_web::{post, HttpResponse};
+
+use crate::{
+    db::DB,
+    error::HResult,
+    friends::dmchannel::{DMChannel, DMPath, GroupDMPath},
+};
+
+/// Mark as read
+///
+/// Marks every message in your DM channel with the specified user as read, so
+/// that it no longer shows as unread in the DM list.
+#[utoipa::path(
+    tag = "DMs",
+    security(("token" = [])),
+    params(DMPath),
+    responses(
+        (status = OK, description = "Marked as read"),
+        (status = FORBIDDEN, description = "You are not friends with that user"),
+    )
+)]
+#[post("/friends/{user_id}/ack")]
+pub async fn ack(db: DB, channel: DMChannel) -> HResult<HttpResponse> {
+    db.mark_dm_read(&channel.id, &channel.from_user_id).await?;
+
+    Ok(HttpResponse::Ok().finish())
+}
+
+/// Mark group as read
+///
+/// Marks every message in a group DM as read, so that it no longer shows as
+/// unread in the DM list.
+#[utoipa::path(
+    tag = "DMs",
+    security(("token" = [])),
+    params(GroupDMPath),
+    responses(
+        (status = OK, description = "Marked as read"),
+        (status = FORBIDDEN, description = "You are not in this group"),
+    )
+)]
+#[post("/groups/{group_id}/ack")]
+pub async fn group_ack(db: DB, channel: DMChannel) -> HResult<HttpResponse> {
+    db.mark_dm_read(&channel.id, &channel.from_user_id).await?;
+
+    Ok(HttpResponse::Ok().finish())
+}
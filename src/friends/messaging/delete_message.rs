@@ -10,7 +10,7 @@ use crate::{
     auth::user::User,
     db::DB,
     error::{macros::err, HResult},
-    friends::dmchannel::{DMChannel, DMPath},
+    friends::dmchannel::{DMChannel, DMPath, GroupDMPath},
     realtime::pubsub::pubsub::PubSub,
 };
 
@@ -40,6 +40,41 @@ pub async fn delete_message(
     channel: DMChannel,
     pubsub: Data<PubSub>,
 ) -> HResult<HttpResponse> {
+    delete(db, user, message_path, channel, pubsub).await
+}
+
+/// Delete group message
+///
+/// Deletes one of your own messages from a group DM.
+#[utoipa::path(
+    tag = "DMs",
+    security(("token" = [])),
+    params(GroupDMPath, DeleteMessagePath),
+    responses(
+        (status = OK, description = "Message deleted successfully"),
+        (status = FORBIDDEN, description = "You are not in this group"),
+    )
+)]
+#[delete("/groups/{group_id}/messages/{message_id}")]
+pub async fn delete_group_message(
+    db: DB,
+    user: User,
+    message_path: Path<DeleteMessagePath>,
+    channel: DMChannel,
+    pubsub: Data<PubSub>,
+) -> HResult<HttpResponse> {
+    delete(db, user, message_path, channel, pubsub).await
+}
+
+async fn delete(
+    db: DB,
+    user: User,
+    message_path: Path<DeleteMessagePath>,
+    channel: DMChannel,
+    pubsub: Data<PubSub>,
+) -> HResult<HttpResponse> {
+    // attachments of the message are no longer referenced by it once it's
+    // gone, and will be garbage collected if nothing else uses them
     let messages_deleted = sqlx::query!(
         "DELETE FROM messages WHERE id = $1 AND channel_id = $2 AND user_id = $3",
         message_path.message_id,
@@ -63,7 +98,7 @@ pub async fn delete_message(
     }
 
     pubsub
-        .notify_dm_message_deleted(&channel.to_user_id, &user.id, &message_path.message_id)
+        .notify_dm_message_deleted(&channel, &message_path.message_id)
         .await;
 
     // TODO: standardize ok responses as json here
@@ -0,0 +1,226 @@
+use actix_web::{get, web::Query, HttpResponse};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use utoipa::{IntoParams, ToSchema};
+
+use crate::{
+    auth::{access_token::AccessToken, user::PublicUserInfo},
+    db::DB,
+    error::{macros::err, HResult},
+};
+
+#[derive(Deserialize, IntoParams)]
+pub struct ListDMsQuery {
+    #[param(style = Form, minimum = 1, maximum = 50)]
+    limit: Option<i64>,
+    /// Only list conversations with their last activity before this time. Use
+    /// the `lastActivity` of the last entry of a page to get the next page.
+    #[param(style = Form)]
+    before: Option<DateTime<Utc>>,
+    /// The `id` of the last entry of a page, so conversations with the same
+    /// `lastActivity` as it aren't skipped or listed twice
+    #[param(style = Form)]
+    before_id: Option<String>,
+}
+
+#[derive(Serialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct LastMessagePreview {
+    #[schema(example = "K1vqjuY8OqU0VO7oJlGpY")]
+    pub id: String,
+    #[schema(example = "xoKM4W7NDqHjK_V0g9s3y")]
+    pub author_id: String,
+    /// The start of the message content, cut off after 100 characters
+    #[schema(example = "Good morning!")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub content: Option<String>,
+    pub has_attachments: bool,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Serialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct DMChannelInfo {
+    #[schema(example = "rMBrzZ7FQk6ZImWlTiRPo")]
+    pub id: String,
+    pub is_group: bool,
+    /// The other user, only present for direct DMs
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub user: Option<PublicUserInfo>,
+    /// Name of the group, only present for named group DMs
+    #[schema(example = "Study Group")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    /// Icon of the group, only present for group DMs with an icon
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub icon: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub last_message: Option<LastMessagePreview>,
+    /// When the last message was sent, or when the conversation was created if
+    /// it has no messages
+    pub last_activity: DateTime<Utc>,
+    /// Whether someone else sent a message since you last read this conversation
+    pub unread: bool,
+}
+
+const MAX_DM_LIMIT: i64 = 50;
+const PREVIEW_LENGTH: usize = 100;
+
+/// List DMs
+///
+/// Lists your direct and group DM conversations, most recently active first.
+/// Each entry has a preview of its last message and whether it has unread
+/// messages. Conversations are marked as read with the `ack` endpoints.
+#[utoipa::path(
+    tag = "DMs",
+    security(("token" = [])),
+    params(ListDMsQuery),
+    responses(
+        (status = OK, description = "Listing succeeded, no more conversations to retreive", body = Vec<DMChannelInfo>),
+        (status = PARTIAL_CONTENT, description = "Listing succeeded, but there are more conversations beyond limit", body = Vec<DMChannelInfo>),
+        (status = BAD_REQUEST, description = "Invalid limit")
+    )
+)]
+#[get("/dms")]
+pub async fn list_dms(
+    db: DB,
+    token: AccessToken,
+    req: Query<ListDMsQuery>,
+) -> HResult<HttpResponse> {
+    let limit = req.limit.unwrap_or(MAX_DM_LIMIT);
+
+    if limit < 1 {
+        err!(400, "Limit cannot be less than 1.")?;
+    }
+
+    if limit > MAX_DM_LIMIT {
+        err!(400, format!("Limit cannot be more than {}.", MAX_DM_LIMIT))?
+    }
+
+    let records = sqlx::query!(
+        r#"
+        SELECT
+            dmchannels.id,
+            dmchannels.is_group,
+            dmchannels.name,
+            dmchannels.icon,
+            other.id AS "other_id?",
+            other.name AS "other_username?",
+            other.avatar AS "other_avatar?",
+            last_message.id AS "last_message_id?",
+            last_message.user_id AS "last_message_author_id?",
+            last_message.content AS "last_message_content?",
+            last_message.attachments AS "last_message_attachments?",
+            last_message.created_at AS "last_message_created_at?",
+            COALESCE(last_message.created_at, dmchannels.created_at) AS "last_activity!",
+            dm_read_states.last_read_at AS "last_read_at?"
+        FROM dmchannels
+        LEFT JOIN users AS other ON (
+            NOT dmchannels.is_group
+            AND other.id = (
+                CASE WHEN dmchannels.from_user = $1
+                THEN dmchannels.to_user
+                ELSE dmchannels.from_user END
+            )
+        )
+        LEFT JOIN LATERAL (
+            SELECT id, user_id, content, attachments, created_at
+            FROM messages
+            WHERE messages.channel_id = dmchannels.id
+            ORDER BY messages.created_at DESC
+            LIMIT 1
+        ) AS last_message ON true
+        LEFT JOIN dm_read_states ON (
+            dm_read_states.channel_id = dmchannels.id
+            AND dm_read_states.user_id = $1
+        )
+        WHERE (
+            dmchannels.from_user = $1
+            OR dmchannels.to_user = $1
+            OR EXISTS (
+                SELECT 1 FROM group_dm_participants
+                WHERE channel_id = dmchannels.id AND user_id = $1
+            )
+        )
+        AND (
+            COALESCE(last_message.created_at, dmchannels.created_at) < $3
+            OR (
+                COALESCE(last_message.created_at, dmchannels.created_at) = $3
+                AND dmchannels.id < $4
+            )
+        )
+        ORDER BY COALESCE(last_message.created_at, dmchannels.created_at) DESC, dmchannels.id DESC
+        LIMIT $2
+        "#,
+        token.user_id,
+        limit,
+        req.before.unwrap_or(Utc::now()).naive_utc(),
+        req.before_id.as_deref(),
+    )
+    .fetch_all(&db.pool)
+    .await?;
+
+    let dms: Vec<DMChannelInfo> = records
+        .into_iter()
+        .map(|record| {
+            let user = match (record.other_id, record.other_username, record.other_avatar) {
+                (Some(id), Some(username), Some(avatar)) => Some(PublicUserInfo {
+                    id,
+                    username,
+                    avatar,
+                }),
+                _ => None,
+            };
+
+            let last_message = match (
+                record.last_message_id,
+                record.last_message_author_id,
+                record.last_message_created_at,
+            ) {
+                (Some(id), Some(author_id), Some(created_at)) => Some(LastMessagePreview {
+                    id,
+                    author_id,
+                    content: record
+                        .last_message_content
+                        .map(|c| c.chars().take(PREVIEW_LENGTH).collect()),
+                    has_attachments: record
+                        .last_message_attachments
+                        .is_some_and(|a| a.as_array().is_some_and(|a| !a.is_empty())),
+                    created_at: DateTime::<Utc>::from_naive_utc_and_offset(created_at, Utc),
+                }),
+                _ => None,
+            };
+
+            let unread = match (&last_message, record.last_read_at) {
+                // your own messages never count as unread
+                (Some(message), _) if message.author_id == token.user_id => false,
+                (Some(message), Some(last_read_at)) => {
+                    message.created_at.naive_utc() > last_read_at
+                }
+                (Some(_), None) => true,
+                (None, _) => false,
+            };
+
+            DMChannelInfo {
+                id: record.id,
+                is_group: record.is_group,
+                user,
+                name: record.name,
+                icon: record.icon,
+                last_message,
+                last_activity: DateTime::<Utc>::from_naive_utc_and_offset(
+                    record.last_activity,
+                    Utc,
+                ),
+                unread,
+            }
+        })
+        .collect();
+
+    Ok(if dms.len() < limit as usize {
+        HttpResponse::Ok()
+    } else {
+        HttpResponse::PartialContent()
+    }
+    .json(dms))
+}
@@ -1,14 +1,23 @@
+pub mod ack;
 pub mod delete_message;
+pub mod list_dms;
 pub mod read_message_history;
 pub mod send_message;
 pub mod typing;
 use utoipa::OpenApi;
 
 pub fn configure_app(cfg: &mut actix_web::web::ServiceConfig) {
+    cfg.service(list_dms::list_dms);
+    cfg.service(ack::ack);
+    cfg.service(ack::group_ack);
     cfg.service(send_message::send_message);
     cfg.service(delete_message::delete_message);
     cfg.service(typing::typing);
     cfg.service(read_message_history::read_message_history);
+    cfg.service(send_message::send_group_message);
+    cfg.service(delete_message::delete_group_message);
+    cfg.service(typing::group_typing);
+    cfg.service(read_message_history::read_group_message_history);
 }
 
 #[derive(OpenApi)]
@@ -17,15 +26,24 @@ pub fn configure_app(cfg: &mut actix_web::web::ServiceConfig) {
         (name = "DMs"),
     ),
     paths(
+        list_dms::list_dms,
+        ack::ack,
+        ack::group_ack,
         send_message::send_message,
         delete_message::delete_message,
         typing::typing,
-        read_message_history::read_message_history
+        read_message_history::read_message_history,
+        send_message::send_group_message,
+        delete_message::delete_group_message,
+        typing::group_typing,
+        read_message_history::read_group_message_history
     ),
     components(
         schemas(
             send_message::SendDMRequest,
             send_message::SendDMResponse,
+            list_dms::DMChannelInfo,
+            list_dms::LastMessagePreview,
         )
     )
 )]
@@ -7,8 +7,9 @@ use crate::{
     auth::user::PublicUserInfo,
     db::DB,
     error::{macros::err, HResult},
-    friends::dmchannel::{DMChannel, DMPath},
-    messaging::message::Message,
+    friends::dmchannel::{DMChannel, DMPath, GroupDMPath},
+    media::signing,
+    messaging::{message::Message, unfurl},
 };
 
 #[derive(Deserialize, IntoParams)]
@@ -43,6 +44,38 @@ async fn read_message_history(
     db: DB,
     channel: DMChannel,
     req: Query<MessageHistoryQuery>,
+) -> HResult<HttpResponse> {
+    read(db, channel, req).await
+}
+
+/// Read group message history
+///
+/// Same as reading direct message history, but for a group DM that you are a
+/// participant of.
+#[utoipa::path(
+    tag = "DMs",
+    security(("token" = [])),
+    params(MessageHistoryQuery, GroupDMPath),
+    responses(
+        (status = OK, description = "Message listing succeeded, no more messages to retreive", body = Vec<Message>),
+        (status = PARTIAL_CONTENT, description = "Message listing succeeded, but there are more messages beyond limit", body = Vec<Message>),
+        (status = FORBIDDEN, description = "You are not in this group"),
+        (status = BAD_REQUEST, description = "Invalid message limit")
+    )
+)]
+#[get("/groups/{group_id}/messages")]
+async fn read_group_message_history(
+    db: DB,
+    channel: DMChannel,
+    req: Query<MessageHistoryQuery>,
+) -> HResult<HttpResponse> {
+    read(db, channel, req).await
+}
+
+async fn read(
+    db: DB,
+    channel: DMChannel,
+    req: Query<MessageHistoryQuery>,
 ) -> HResult<HttpResponse> {
     let limit = req.limit.unwrap_or(MAX_MESSAGE_LIMIT);
 
@@ -64,13 +97,16 @@ async fn read_message_history(
             messages.content, 
             messages.created_at,
             messages.attachments,
+            messages.embeds,
             users.name AS "author_username",
             users.avatar AS "author_avatar",
-            users.id AS "author_id"
-        FROM messages, users 
+            users.id AS "author_id",
+            blocks.blocked IS NOT NULL AS "author_blocked!"
+        FROM messages
+        INNER JOIN users ON users.id = messages.user_id
+        LEFT JOIN blocks ON blocks.blocker = $5 AND blocks.blocked = messages.user_id
         WHERE (
             messages.channel_id = $1 
-            AND users.id = messages.user_id
             AND messages.created_at < $3
             AND messages.created_at > $4
         )
@@ -81,6 +117,7 @@ async fn read_message_history(
         limit,
         req.before.unwrap_or(Utc::now()).naive_utc(),
         req.after.unwrap_or_default().naive_utc(), // unix epoch
+        channel.from_user_id,
     )
     .fetch_all(&db.pool)
     .await?;
@@ -89,21 +126,20 @@ async fn read_message_history(
         .iter()
         .rev()
         .map(|record| {
-            let attachments = match record.attachments.clone() {
-                Some(some) => serde_json::from_value(some).ok(),
-                None => None,
-            };
+            let attachments = signing::attachments_from_json(record.attachments.clone());
 
             Message {
                 id: record.id.clone(),
                 content: record.content.clone(),
                 attachments,
+                embeds: unfurl::embeds_from_json(record.embeds.clone()),
                 created_at: DateTime::<Utc>::from_naive_utc_and_offset(record.created_at, Utc),
                 author: PublicUserInfo {
                     id: record.author_id.clone(),
                     username: record.author_username.clone(),
                     avatar: record.author_avatar.clone(),
                 },
+                author_blocked: record.author_blocked,
             }
         })
         .collect();
@@ -13,9 +13,12 @@ use crate::{
     auth::user::User,
     db::DB,
     error::{macros::err, HResult},
-    friends::dmchannel::{DMChannel, DMPath},
-    media::routes::upload::UploadedFileInfo,
-    messaging::message::Message,
+    friends::dmchannel::{DMChannel, DMPath, GroupDMPath},
+    media::{attachments::resolve_attachments, signing::sign_file_info},
+    messaging::{
+        message::Message,
+        unfurl::{unfurl_message, MessageChannel, Unfurler},
+    },
     realtime::pubsub::pubsub::PubSub,
 };
 
@@ -23,7 +26,9 @@ use crate::{
 pub struct SendDMRequest {
     #[schema(example = "Hello from the API tester!")]
     content: Option<String>,
-    attachments: Option<Vec<UploadedFileInfo>>,
+    /// IDs of files you uploaded through the media API
+    #[schema(example = json!(["s6NIiu2oOh1FEL0Xfjc7n"]))]
+    attachments: Option<Vec<String>>,
 }
 
 #[derive(Serialize, ToSchema)]
@@ -44,7 +49,7 @@ pub struct SendDMResponse {
     responses(
         (status = OK, description = "Message sent", body = SendMessageResponse),
         (status = FORBIDDEN, description = "No permission to send message to user (not friends)"),
-        (status = BAD_REQUEST, description = "Invalid message (content_too_long, missing_content)")
+        (status = BAD_REQUEST, description = "Invalid message (content_too_long, missing_content, unknown attachment)")
     )
 )]
 #[post("/friends/{user_id}/messages")]
@@ -54,6 +59,43 @@ async fn send_message(
     req: Json<SendDMRequest>,
     channel: DMChannel,
     pubsub: Data<PubSub>,
+    unfurler: Data<Unfurler>,
+) -> HResult<Json<SendDMResponse>> {
+    send(db, user, req, channel, pubsub, unfurler).await
+}
+
+/// Send a group message
+///
+/// Sends a message in a group DM that you are a participant of
+#[utoipa::path(
+    tag = "DMs",
+    security(("token" = [])),
+    params(GroupDMPath),
+    responses(
+        (status = OK, description = "Message sent", body = SendMessageResponse),
+        (status = FORBIDDEN, description = "You are not in this group"),
+        (status = BAD_REQUEST, description = "Invalid message (content_too_long, missing_content, unknown attachment)")
+    )
+)]
+#[post("/groups/{group_id}/messages")]
+async fn send_group_message(
+    db: DB,
+    user: User,
+    req: Json<SendDMRequest>,
+    channel: DMChannel,
+    pubsub: Data<PubSub>,
+    unfurler: Data<Unfurler>,
+) -> HResult<Json<SendDMResponse>> {
+    send(db, user, req, channel, pubsub, unfurler).await
+}
+
+async fn send(
+    db: DB,
+    user: User,
+    req: Json<SendDMRequest>,
+    channel: DMChannel,
+    pubsub: Data<PubSub>,
+    unfurler: Data<Unfurler>,
 ) -> HResult<Json<SendDMResponse>> {
     // get inner value
     let req = req.0;
@@ -75,9 +117,19 @@ async fn send_message(
     }
 
     // No need to do a permission check, DMChannel extractor already does that
+    // for both direct and group DMs
 
-    // serialize attachments list back to json
+    // look up the attachments, making sure they were uploaded by the sender
     let attachments = match req.attachments {
+        Some(ref ids) if !ids.is_empty() => Some(resolve_attachments(&db, &user.id, ids).await?),
+        _ => None,
+    };
+
+    // remember which uploads are in use, so they don't get garbage collected
+    let attachment_ids: Vec<String> = attachments.iter().flatten().map(|a| a.id.clone()).collect();
+
+    // serialize attachments list to json
+    let attachments_json = match attachments {
         Some(ref atts) => serde_json::to_value(atts).unwrap(),
         None => Value::Null,
     };
@@ -89,7 +141,11 @@ async fn send_message(
             (id, channel_id, user_id, content, attachments) 
             VALUES ($1, $2, $3, $4, $5) 
             RETURNING messages.id, messages.created_at
-        ) 
+        ),
+        attached AS (
+            INSERT INTO message_attachments (message_id, media_id)
+            SELECT $1, UNNEST($6::text[])
+        )
         SELECT message.id, message.created_at, members.nickname AS "author_nickname" FROM message 
         LEFT JOIN members ON members.guild_id = $2 AND members.user_id = $4 
         "#,
@@ -97,7 +153,8 @@ async fn send_message(
         channel.id,
         user.id,
         req.content,
-        attachments
+        attachments_json,
+        &attachment_ids
     )
     .fetch_one(&db.pool)
     .await?;
@@ -105,19 +162,30 @@ async fn send_message(
     let message = Message {
         id: record.id.clone(),
         content: if is_content_empty { None } else { req.content },
-        attachments: if are_attachments_empty {
-            None
-        } else {
-            req.attachments
-        },
+        attachments: attachments.map(|atts| atts.into_iter().map(sign_file_info).collect()),
+        embeds: None,
         author: user.into(),
+        author_blocked: false,
         created_at: DateTime::<Utc>::from_naive_utc_and_offset(record.created_at, Utc),
     };
 
+    // you've obviously read everything up to your own message
+    db.mark_dm_read(&channel.id, &channel.from_user_id).await?;
+
     // tell people listening to this channel that there's a new message
-    pubsub
-        .notify_dm_new_message(&channel.to_user_id, &message)
-        .await;
+    pubsub.notify_dm_new_message(&channel, &message).await;
+
+    // previews of links are sent once they're ready
+    if let Some(ref content) = message.content {
+        unfurl_message(
+            unfurler,
+            db,
+            pubsub,
+            MessageChannel::Dm(channel),
+            message.id.clone(),
+            content,
+        );
+    }
 
     Ok(Json(SendDMResponse {
         id: message.id,
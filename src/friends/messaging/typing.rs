@@ -3,7 +3,7 @@ use actix_web::{post, web::Data, HttpResponse};
 use crate::{
     auth::user::User,
     error::HResult,
-    friends::dmchannel::{DMChannel, DMPath},
+    friends::dmchannel::{DMChannel, DMPath, GroupDMPath},
     realtime::pubsub::pubsub::PubSub,
 };
 
@@ -22,9 +22,32 @@ use crate::{
 )]
 #[post("/friends/{user_id}/typing")]
 pub async fn typing(channel: DMChannel, user: User, pubsub: Data<PubSub>) -> HResult<HttpResponse> {
-    pubsub
-        .send_dm_typing(&channel.to_user_id, &user.into())
-        .await;
+    pubsub.send_dm_typing(&channel, &user.into()).await;
+
+    Ok(HttpResponse::Ok().finish())
+}
+
+/// Group typing
+///
+/// Notify everyone else in a group DM that you are typing a message. Please
+/// call this endpoint every `4s` while the user is still typing to maintain
+/// their status.
+#[utoipa::path(
+    tag = "DMs",
+    security(("token" = [])),
+    params(GroupDMPath),
+    responses(
+        (status = OK, description = "Typing notification sent"),
+        (status = FORBIDDEN, description = "You are not in this group"),
+    )
+)]
+#[post("/groups/{group_id}/typing")]
+pub async fn group_typing(
+    channel: DMChannel,
+    user: User,
+    pubsub: Data<PubSub>,
+) -> HResult<HttpResponse> {
+    pubsub.send_dm_typing(&channel, &user.into()).await;
 
     Ok(HttpResponse::Ok().finish())
 }
@@ -1,4 +1,6 @@
 pub mod dmchannel;
+pub mod friend;
 pub mod friend_request;
+pub mod groups;
 pub mod management;
 pub mod messaging;
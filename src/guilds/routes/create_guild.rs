@@ -8,14 +8,15 @@ use crate::{
     channels::channel::ChannelType,
     db::DB,
     error::{macros::err, HResult},
-    security,
+    media::{attachments::resolve_square_image, storage::Storage},
 };
 
 #[derive(Deserialize, ToSchema)]
 pub struct CreateGuildRequest {
     #[schema(example = "My Cool Server")]
     name: String,
-    #[schema(example = "/media/s6NIiu2oOh1FEL0Xfjc7n/cat.jpg")]
+    /// ID of an image you uploaded through the media API
+    #[schema(example = "s6NIiu2oOh1FEL0Xfjc7n")]
     icon: Option<String>,
 }
 
@@ -46,19 +47,16 @@ pub struct CreateGuildResponse {
 #[post("/guilds")]
 pub async fn create_guild(
     db: DB,
+    storage: Storage,
     token: AccessToken,
     req: Json<CreateGuildRequest>,
 ) -> HResult<Json<CreateGuildResponse>> {
     let guild_id = nanoid!();
 
-    if let Some(ref icon) = req.icon {
-        if !security::validate_resource_origin(icon) {
-            err!(
-                400,
-                "Icon supplied must be a URL to an image hosted on this server."
-            )?;
-        }
-    }
+    let icon = match req.icon {
+        Some(ref icon) => Some(resolve_square_image(&db, &storage, &token.user_id, icon).await?),
+        None => None,
+    };
 
     // todo: validate guild name
 
@@ -75,7 +73,7 @@ pub async fn create_guild(
             guild_id,
             req.name,
             token.user_id,
-            req.icon
+            icon
         ),
         &mut tx,
     )
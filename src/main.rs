@@ -92,16 +92,34 @@ async fn main() -> std::io::Result<()> {
 
     // voice chat related
     // create a voice worker pool
-    let voice_worker_pool = VoiceWorkerPool::new(WorkerManager::new(), options::voice_ports());
+    let mut voice_worker_pool = VoiceWorkerPool::new(WorkerManager::new(), options::voice_ports());
+    let dead_voice_workers = voice_worker_pool.take_dead_workers().unwrap();
     // wrap it in Data and tokio Mutex
     let voice_worker_pool = Data::new(tokio::sync::Mutex::new(voice_worker_pool));
 
     let voice_clients: Data<VoiceClients> = Data::new(Mutex::new(HashMap::new()));
     let voice_channels: Data<VoiceChannels> = Data::new(Mutex::new(HashMap::new()));
 
+    // replace voice workers that crash and move everyone off them
+    actix_rt::spawn(voice::pool::recover_dead_workers(
+        dead_voice_workers,
+        Data::clone(&voice_worker_pool),
+        Data::clone(&voice_clients),
+        Data::clone(&voice_channels),
+    ));
+
     // pubsub
     let event_manager = Data::new(PubSub::new());
 
+    // where uploaded files are kept
+    let media_storage = media::storage::from_options();
+
+    // fetches link previews for messages
+    let unfurler = Data::new(messaging::unfurl::Unfurler::new());
+
+    // delete uploads nobody uses in the background
+    actix_rt::spawn(media::gc::run(Data::clone(&pool), Data::clone(&media_storage)));
+
     let mut server = HttpServer::new(move || {
         let oapi = apidocs::setup_oapi();
 
@@ -133,8 +151,10 @@ async fn main() -> std::io::Result<()> {
             .app_data(Data::clone(&event_manager))
             .service(realtime::pubsub::events::events_ws)
             // messaging
+            .app_data(Data::clone(&unfurler))
             .configure(messaging::routes::configure_app)
             // file uploads
+            .app_data(Data::clone(&media_storage))
             .configure(media::routes::configure_app)
             .configure(settings::routes::configure_app)
             // bots
@@ -142,6 +162,7 @@ async fn main() -> std::io::Result<()> {
             // friends
             .configure(friends::management::configure_app)
             .configure(friends::messaging::configure_app)
+            .configure(friends::groups::configure_app)
             // invites
             .configure(invites::routes::configure_app)
             // Default 404 response
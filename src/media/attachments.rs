@@ -0,0 +1,110 @@
+use actix_web::web;
+use chrono::Utc;
+use nanoid::nanoid;
+use sha2::{Digest, Sha256};
+
+use crate::{
+    db::DB,
+    error::{macros::err, HResult, IntoHandlerErrorResult},
+};
+
+use super::{
+    images,
+    record::Media,
+    routes::upload::{UploadedFileInfo, UploadedFileType},
+    storage::Storage,
+};
+
+/// The most attachments a single message can have
+pub const MAX_ATTACHMENTS: usize = 10;
+
+/// Turns a list of media ids sent by a client into the attachments to store
+/// on a message. Every id must be a file uploaded by `user_id`, otherwise the
+/// request is rejected, so clients can't attach other people's files or spoof
+/// the name and type of an attachment.
+pub async fn resolve_attachments(
+    db: &DB,
+    user_id: &str,
+    ids: &[String],
+) -> HResult<Vec<UploadedFileInfo>> {
+    if ids.len() > MAX_ATTACHMENTS {
+        err!(
+            400,
+            format!(
+                "A message cannot have more than {} attachments.",
+                MAX_ATTACHMENTS
+            )
+        )?;
+    }
+
+    let media = db.get_owned_media(user_id, ids).await?;
+
+    // keep the order the client sent the attachments in
+    let mut attachments = Vec::with_capacity(ids.len());
+    for id in ids {
+        match media.iter().find(|m| &m.id == id) {
+            Some(m) => attachments.push(m.file_info()),
+            None => err!(400, format!("Unknown attachment `{}`.", id))?,
+        }
+    }
+
+    Ok(attachments)
+}
+
+/// Looks up an image uploaded by `user_id` to be used as an avatar or icon and
+/// returns its URL. Images that aren't square are cropped, which is stored as
+/// a new file owned by the same user.
+pub async fn resolve_square_image(
+    db: &DB,
+    storage: &Storage,
+    user_id: &str,
+    id: &str,
+) -> HResult<String> {
+    let media = db.get_owned_media(user_id, &[id.to_string()]).await?;
+
+    let media = match media.into_iter().next() {
+        Some(m) if matches!(m.file_type(), UploadedFileType::Image) => m,
+        Some(_) => return err!(400, "The file supplied is not an image."),
+        None => return err!(400, "Unknown image."),
+    };
+
+    if !media.has_thumbnails() {
+        return err!(400, "The image supplied could not be read.");
+    }
+
+    if media.width == media.height {
+        return Ok(media.url());
+    }
+
+    let bytes = storage.read(&media.key()).await.or_err(500)?;
+    let mime_type = media.mime_type.clone();
+    let cropped = web::block(move || images::crop_square(&bytes, &mime_type))
+        .await
+        .or_err(500)?
+        .or_err(500)?;
+    let data = cropped.data.or_err(500)?;
+
+    // the cropped image may be in a different format than the original
+    let format = images::thumbnail_format(&media.mime_type);
+    let stem = media
+        .name
+        .rsplit_once('.')
+        .map_or(media.name.as_str(), |(stem, _)| stem);
+
+    let square = Media {
+        id: nanoid!(),
+        uploader: user_id.to_string(),
+        name: format!("{}.{}", stem, format.extensions_str()[0]),
+        size: data.len() as i64,
+        sha256: hex::encode(Sha256::digest(&data)),
+        mime_type: format.to_mime_type().to_string(),
+        width: Some(cropped.width as i32),
+        height: Some(cropped.height as i32),
+        created_at: Utc::now().naive_utc(),
+    };
+
+    storage.store(&square.key(), data).await.or_err(500)?;
+    db.insert_media(&square).await?;
+
+    Ok(square.url())
+}
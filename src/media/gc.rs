@@ -0,0 +1,80 @@
+use std::time::Duration;
+
+use log::{info, warn};
+
+use crate::{db::DB, options};
+
+use super::{images, record::Media, routes::chunked_upload::session_path, storage::Storage};
+
+/// Periodically deletes uploads that nothing refers to anymore, and chunked
+/// uploads that were never finished. Runs until the server stops.
+pub async fn run(db: DB, storage: Storage) {
+    let mut interval = actix_rt::time::interval(Duration::from_secs(
+        *options::MEDIA_GC_INTERVAL_MINUTES * 60,
+    ));
+
+    loop {
+        interval.tick().await;
+
+        if let Err(e) = collect_unused_media(&db, &storage).await {
+            warn!("collecting unused media failed: {}", e);
+        }
+
+        if let Err(e) = collect_expired_uploads(&db).await {
+            warn!("collecting expired uploads failed: {}", e);
+        }
+    }
+}
+
+/// Deletes uploads older than the grace period that aren't attached to a
+/// message, part of a voice recording or used as an avatar or icon.
+async fn collect_unused_media(db: &DB, storage: &Storage) -> Result<(), sqlx::Error> {
+    let unused = sqlx::query_as!(
+        Media,
+        r#"DELETE FROM media
+            WHERE created_at < now() - make_interval(hours => $1)
+            AND NOT EXISTS (SELECT 1 FROM message_attachments WHERE media_id = media.id)
+            AND NOT EXISTS (SELECT 1 FROM voice_recording_tracks WHERE media_id = media.id)
+            AND NOT EXISTS (SELECT 1 FROM users WHERE avatar = '/media/' || media.id || '/' || media.name)
+            AND NOT EXISTS (SELECT 1 FROM guilds WHERE icon = '/media/' || media.id || '/' || media.name)
+            AND NOT EXISTS (SELECT 1 FROM dmchannels WHERE icon = '/media/' || media.id || '/' || media.name)
+            RETURNING id, uploader, name, size, sha256, mime_type, width, height, created_at"#,
+        *options::MEDIA_GC_GRACE_HOURS as i32
+    )
+    .fetch_all(&db.pool)
+    .await?;
+
+    if unused.is_empty() {
+        return Ok(());
+    }
+
+    info!("Deleting {} unused uploads", unused.len());
+
+    for media in unused {
+        if let Err(e) = storage.delete(&media.key()).await {
+            warn!("deleting unused upload `{}` failed: {}", media.key(), e);
+        }
+
+        // thumbnails are made on demand, so try every size that may exist
+        let extension = images::thumbnail_format(&media.mime_type).extensions_str()[0];
+        for size in images::THUMBNAIL_SIZES {
+            let _ = storage.delete(&media.thumbnail_key(size, extension)).await;
+        }
+    }
+
+    Ok(())
+}
+
+async fn collect_expired_uploads(db: &DB) -> Result<(), sqlx::Error> {
+    let expired = sqlx::query!("DELETE FROM upload_sessions WHERE expires_at < now() RETURNING id")
+        .fetch_all(&db.pool)
+        .await?;
+
+    for session in expired {
+        if let Ok(path) = session_path(&session.id) {
+            let _ = tokio::fs::remove_file(path).await;
+        }
+    }
+
+    Ok(())
+}
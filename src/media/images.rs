@@ -0,0 +1,257 @@
+use std::io::Cursor;
+
+use image::{io::Limits, DynamicImage, ImageFormat, ImageOutputFormat};
+
+/// Thumbnails are only generated in these sizes, so a client can't make us
+/// store a thumbnail for every size imaginable. Requested sizes are rounded up
+/// to the next one.
+pub const THUMBNAIL_SIZES: [u32; 5] = [64, 128, 256, 512, 1024];
+
+/// The thumbnail size a requested size is rounded up to, which is the largest
+/// one for sizes bigger than all of them
+pub fn thumbnail_size(requested: u32) -> u32 {
+    THUMBNAIL_SIZES
+        .into_iter()
+        .find(|size| *size >= requested)
+        .unwrap_or(THUMBNAIL_SIZES[THUMBNAIL_SIZES.len() - 1])
+}
+
+/// Images bigger than this are stored as they are, without reading their
+/// dimensions, stripping metadata or generating thumbnails.
+pub const MAX_PROCESSED_IMAGE_SIZE: usize = 50 * 1_000_000; // 50 MB
+
+/// Largest width or height of an image we are willing to decode
+const MAX_IMAGE_DIMENSION: u32 = 16384;
+
+pub struct ProcessedImage {
+    pub width: u32,
+    pub height: u32,
+    /// The new contents of the file, if they had to be changed to get rid of
+    /// metadata
+    pub data: Option<Vec<u8>>,
+}
+
+/// Reads the dimensions of an uploaded image and strips EXIF metadata (which
+/// may contain GPS coordinates) from JPEGs. Returns `None` if the image can't
+/// be decoded.
+///
+/// This is slow, run it with `web::block`.
+pub fn process_upload(bytes: &[u8], mime_type: &str) -> Option<ProcessedImage> {
+    let format = ImageFormat::from_mime_type(mime_type)?;
+    let image = decode(bytes, format)?;
+
+    if format != ImageFormat::Jpeg {
+        return Some(ProcessedImage {
+            width: image.width(),
+            height: image.height(),
+            data: None,
+        });
+    }
+
+    // removing the EXIF data also removes the orientation, so rotated images
+    // have to be rotated for real and re-encoded
+    let image = match jpeg_orientation(bytes) {
+        Some(2) => image.fliph(),
+        Some(3) => image.rotate180(),
+        Some(4) => image.flipv(),
+        Some(5) => image.rotate90().fliph(),
+        Some(6) => image.rotate90(),
+        Some(7) => image.rotate270().fliph(),
+        Some(8) => image.rotate270(),
+        _ => {
+            return Some(ProcessedImage {
+                width: image.width(),
+                height: image.height(),
+                data: Some(strip_jpeg_metadata(bytes)?),
+            })
+        }
+    };
+
+    Some(ProcessedImage {
+        width: image.width(),
+        height: image.height(),
+        data: Some(encode(&image, format)?),
+    })
+}
+
+/// Makes a thumbnail that fits in a `size` by `size` box, keeping the aspect
+/// ratio. The thumbnail is encoded in the format given by [`thumbnail_format`].
+///
+/// This is slow, run it with `web::block`.
+pub fn make_thumbnail(bytes: &[u8], mime_type: &str, size: u32) -> Option<Vec<u8>> {
+    let format = ImageFormat::from_mime_type(mime_type)?;
+    let image = decode(bytes, format)?.thumbnail(size, size);
+
+    encode(&image, thumbnail_format(mime_type))
+}
+
+/// Cuts the biggest possible square out of the middle of an image, for
+/// avatars and icons. The square is encoded in the format given by
+/// [`thumbnail_format`].
+///
+/// This is slow, run it with `web::block`.
+pub fn crop_square(bytes: &[u8], mime_type: &str) -> Option<ProcessedImage> {
+    let format = ImageFormat::from_mime_type(mime_type)?;
+    let image = decode(bytes, format)?;
+
+    let side = image.width().min(image.height());
+    let x = (image.width() - side) / 2;
+    let y = (image.height() - side) / 2;
+    let image = image.crop_imm(x, y, side, side);
+
+    Some(ProcessedImage {
+        width: side,
+        height: side,
+        data: Some(encode(&image, thumbnail_format(mime_type))?),
+    })
+}
+
+/// The format thumbnails and cropped versions of an image are stored in.
+/// JPEGs stay JPEGs, everything else becomes a PNG so transparency is kept.
+pub fn thumbnail_format(mime_type: &str) -> ImageFormat {
+    match ImageFormat::from_mime_type(mime_type) {
+        Some(ImageFormat::Jpeg) => ImageFormat::Jpeg,
+        _ => ImageFormat::Png,
+    }
+}
+
+fn decode(bytes: &[u8], format: ImageFormat) -> Option<DynamicImage> {
+    let mut limits = Limits::default();
+    limits.max_image_width = Some(MAX_IMAGE_DIMENSION);
+    limits.max_image_height = Some(MAX_IMAGE_DIMENSION);
+
+    let mut reader = image::io::Reader::with_format(Cursor::new(bytes), format);
+    reader.limits(limits);
+    reader.decode().ok()
+}
+
+fn encode(image: &DynamicImage, format: ImageFormat) -> Option<Vec<u8>> {
+    let mut data = Vec::new();
+
+    let result = match format {
+        // JPEGs can't have an alpha channel
+        ImageFormat::Jpeg => DynamicImage::ImageRgb8(image.to_rgb8())
+            .write_to(&mut Cursor::new(&mut data), ImageOutputFormat::Jpeg(90)),
+        _ => image.write_to(&mut Cursor::new(&mut data), format),
+    };
+
+    result.ok().map(|_| data)
+}
+
+/// Splits a JPEG into its segments up to the start of the image data, calling
+/// `f` with the marker and the whole segment (including the marker). Returns
+/// the offset where the image data starts, or `None` if the file is broken.
+fn walk_jpeg_segments<'a>(bytes: &'a [u8], mut f: impl FnMut(u8, &'a [u8])) -> Option<usize> {
+    if bytes.get(0..2)? != [0xFF, 0xD8] {
+        return None;
+    }
+
+    let mut i = 2;
+    loop {
+        if *bytes.get(i)? != 0xFF {
+            return None;
+        }
+
+        // markers can be padded with any number of 0xFF bytes
+        while *bytes.get(i + 1)? == 0xFF {
+            i += 1;
+        }
+
+        let marker = bytes[i + 1];
+        match marker {
+            // start of scan, everything after this is image data
+            0xDA => return Some(i),
+            // markers without a length
+            0x01 | 0xD0..=0xD7 => {
+                f(marker, &bytes[i..i + 2]);
+                i += 2;
+            }
+            _ => {
+                let length = u16::from_be_bytes([*bytes.get(i + 2)?, *bytes.get(i + 3)?]) as usize;
+                let segment = bytes.get(i..i + 2 + length)?;
+                f(marker, segment);
+                i += 2 + length;
+            }
+        }
+    }
+}
+
+/// Removes EXIF, XMP, IPTC and comment segments from a JPEG without touching
+/// the image data itself.
+fn strip_jpeg_metadata(bytes: &[u8]) -> Option<Vec<u8>> {
+    let mut stripped = vec![0xFF, 0xD8];
+
+    let image_data = walk_jpeg_segments(bytes, |marker, segment| {
+        // APP1 is EXIF and XMP, APP13 is IPTC and 0xFE is a comment. other APP
+        // segments are kept, as they hold things like colour profiles
+        if !matches!(marker, 0xE1 | 0xED | 0xFE) {
+            stripped.extend_from_slice(segment);
+        }
+    })?;
+
+    stripped.extend_from_slice(&bytes[image_data..]);
+    Some(stripped)
+}
+
+/// Reads the orientation tag from the EXIF data of a JPEG.
+fn jpeg_orientation(bytes: &[u8]) -> Option<u16> {
+    let mut exif = None;
+    walk_jpeg_segments(bytes, |marker, segment| {
+        if marker == 0xE1 && exif.is_none() && segment.get(4..10) == Some(b"Exif\0\0") {
+            exif = Some(&segment[10..]);
+        }
+    })?;
+
+    // the EXIF data is a TIFF file, the orientation is in the first IFD
+    let tiff = exif?;
+    let read_u16 = |at: usize| -> Option<u16> {
+        let b = [*tiff.get(at)?, *tiff.get(at + 1)?];
+        match tiff.get(0..2)? {
+            b"II" => Some(u16::from_le_bytes(b)),
+            b"MM" => Some(u16::from_be_bytes(b)),
+            _ => None,
+        }
+    };
+    let read_u32 = |at: usize| -> Option<u32> {
+        let b = [
+            *tiff.get(at)?,
+            *tiff.get(at + 1)?,
+            *tiff.get(at + 2)?,
+            *tiff.get(at + 3)?,
+        ];
+        match tiff.get(0..2)? {
+            b"II" => Some(u32::from_le_bytes(b)),
+            b"MM" => Some(u32::from_be_bytes(b)),
+            _ => None,
+        }
+    };
+
+    let ifd = read_u32(4)? as usize;
+    let entries = read_u16(ifd)? as usize;
+
+    (0..entries)
+        .map(|n| ifd + 2 + n * 12)
+        .find(|&entry| read_u16(entry) == Some(0x0112))
+        .and_then(|entry| read_u16(entry + 8))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::thumbnail_size;
+
+    #[test]
+    fn rounds_thumbnail_sizes_up() {
+        assert_eq!(thumbnail_size(0), 64);
+        assert_eq!(thumbnail_size(1), 64);
+        assert_eq!(thumbnail_size(64), 64);
+        assert_eq!(thumbnail_size(65), 128);
+        assert_eq!(thumbnail_size(300), 512);
+        assert_eq!(thumbnail_size(1024), 1024);
+    }
+
+    #[test]
+    fn caps_thumbnail_sizes_at_the_largest() {
+        assert_eq!(thumbnail_size(1025), 1024);
+        assert_eq!(thumbnail_size(u32::MAX), 1024);
+    }
+}
@@ -1,7 +1,14 @@
 use lazy_static::lazy_static;
 use regex::Regex;
 
+pub mod attachments;
+pub mod gc;
+pub mod images;
+pub mod quota;
+pub mod record;
 pub mod routes;
+pub mod signing;
+pub mod storage;
 
 lazy_static! {
     pub static ref FILENAME_REGEX: Regex = Regex::new(r"^[a-zA-Z0-9_.-]+$").unwrap();
@@ -0,0 +1,91 @@
+use crate::{
+    db::DB,
+    error::{macros::err, HResult, IntoHandlerErrorResult},
+    media::record::Media,
+    options,
+};
+
+/// How many bytes each user can upload, `None` if there is no limit.
+pub fn quota() -> Option<i64> {
+    match *options::MEDIA_QUOTA {
+        0 => None,
+        quota => Some(quota as i64),
+    }
+}
+
+/// Fails if uploading `extra` more bytes would put a user over their quota.
+/// This is only a first check, `insert_media` makes sure of it.
+pub async fn check_quota(db: &DB, uploader: &str, extra: i64) -> HResult<()> {
+    let Some(quota) = quota() else {
+        return Ok(());
+    };
+
+    let (used, _) = db.get_media_usage(uploader).await?;
+
+    if used + extra > quota {
+        return over_quota(quota, used);
+    }
+
+    Ok(())
+}
+
+/// Records an upload, unless it puts the uploader over their quota. Uploads
+/// by the same user wait for each other here, so two of them can't both take
+/// the last of it.
+pub async fn insert_media(db: &DB, media: &Media) -> HResult<()> {
+    let Some(quota) = quota() else {
+        return Ok(db.insert_media(media).await?);
+    };
+
+    let mut tx = db.pool.begin().await?;
+
+    sqlx::query!(
+        r#"SELECT id FROM users WHERE id = $1 FOR UPDATE"#,
+        media.uploader
+    )
+    .fetch_optional(&mut tx)
+    .await?
+    .or_err(404)?;
+
+    let used = sqlx::query!(
+        r#"SELECT COALESCE(SUM(size), 0)::bigint AS "bytes!" FROM media WHERE uploader = $1"#,
+        media.uploader
+    )
+    .fetch_one(&mut tx)
+    .await?
+    .bytes;
+
+    if used + media.size > quota {
+        return over_quota(quota, used);
+    }
+
+    sqlx::query!(
+        r#"INSERT INTO media (id, uploader, name, size, sha256, mime_type, width, height, created_at)
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9)"#,
+        media.id,
+        media.uploader,
+        media.name,
+        media.size,
+        media.sha256,
+        media.mime_type,
+        media.width,
+        media.height,
+        media.created_at
+    )
+    .execute(&mut tx)
+    .await?;
+
+    tx.commit().await?;
+
+    Ok(())
+}
+
+fn over_quota(quota: i64, used: i64) -> HResult<()> {
+    err!(
+        413,
+        format!(
+            "This upload would exceed your storage quota of {} bytes ({} bytes used). Delete some messages with attachments to free up space.",
+            quota, used
+        )
+    )
+}
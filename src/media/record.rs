@@ -0,0 +1,60 @@
+use chrono::NaiveDateTime;
+
+use super::routes::upload::{UploadedFileInfo, UploadedFileType};
+
+/// A file uploaded through the media API, as it is stored in the `media`
+/// table.
+pub struct Media {
+    pub id: String,
+    pub uploader: String,
+    pub name: String,
+    pub size: i64,
+    /// Hex encoded SHA-256 hash of the file contents
+    pub sha256: String,
+    /// The MIME type detected from the file contents, not the one the client
+    /// claimed when uploading
+    pub mime_type: String,
+    /// Only set for images that could be decoded
+    pub width: Option<i32>,
+    pub height: Option<i32>,
+    pub created_at: NaiveDateTime,
+}
+
+impl Media {
+    pub fn url(&self) -> String {
+        format!("/media/{}/{}", self.id, self.name)
+    }
+
+    /// The key the file is stored under in media storage
+    pub fn key(&self) -> String {
+        format!("{}_{}", self.id, self.name)
+    }
+
+    /// The key a thumbnail of the file is stored under in media storage
+    pub fn thumbnail_key(&self, size: u32, extension: &str) -> String {
+        format!("thumbnails/{}_thumbnail_{}.{}", self.id, size, extension)
+    }
+
+    pub fn file_type(&self) -> UploadedFileType {
+        UploadedFileType::from_mime(&self.mime_type)
+    }
+
+    /// Whether thumbnails can be made of this file
+    pub fn has_thumbnails(&self) -> bool {
+        self.width.is_some() && self.height.is_some()
+    }
+
+    pub fn file_info(&self) -> UploadedFileInfo {
+        let url = self.url();
+
+        UploadedFileInfo {
+            id: self.id.clone(),
+            name: self.name.clone(),
+            thumbnail_url: self.has_thumbnails().then(|| format!("{}?size=256", url)),
+            url,
+            r#type: self.file_type(),
+            width: self.width.map(|w| w as u32),
+            height: self.height.map(|h| h as u32),
+        }
+    }
+}
@@ -0,0 +1,624 @@
+use std::{
+    collections::HashMap,
+    io::SeekFrom,
+    sync::{Arc, Mutex},
+};
+
+use actix_web::{
+    delete, get, post, put,
+    web::{Json, Path, Payload},
+    HttpRequest, HttpResponse,
+};
+use chrono::{DateTime, Duration, NaiveDateTime, Utc};
+use futures::TryStreamExt;
+use lazy_static::lazy_static;
+use log::warn;
+use nanoid::nanoid;
+use serde::{Deserialize, Serialize};
+use tokio::{
+    fs::{File, OpenOptions},
+    io::{AsyncSeekExt, AsyncWriteExt},
+    sync::OwnedMutexGuard,
+};
+use utoipa::{IntoParams, ToSchema};
+
+use crate::{
+    auth::access_token::AccessToken,
+    db::DB,
+    error::{macros::err, HResult, IntoHandlerErrorResult},
+    media::{
+        quota,
+        routes::upload::{
+            random_file_name, store_upload, SavedFile, UploadedFileInfo, MAX_FILE_SIZE,
+        },
+        signing::sign_file_info,
+        storage::{self, Storage},
+        util::clean_filename,
+        FILENAME_REGEX,
+    },
+};
+
+/// Largest piece of a file that can be sent in one request
+const MAX_CHUNK_SIZE: i64 = 16 * 1_000_000; // 16 MB
+
+/// How many unfinished uploads a user can have at the same time
+const MAX_OPEN_SESSIONS: i64 = 5;
+
+/// How long an upload can sit without any progress before it is thrown away
+fn session_lifetime() -> Duration {
+    Duration::hours(24)
+}
+
+lazy_static! {
+    static ref SESSION_LOCKS: Mutex<HashMap<String, Arc<tokio::sync::Mutex<()>>>> =
+        Mutex::new(HashMap::new());
+}
+
+/// Held while a request writes to, finishes or cancels an upload, so that
+/// only one of them touches its file at a time. Locking the session's row
+/// instead would keep a database connection busy for as long as a chunk takes
+/// to arrive.
+struct SessionLock {
+    upload_id: String,
+    guard: Option<OwnedMutexGuard<()>>,
+}
+
+impl SessionLock {
+    async fn acquire(upload_id: &str) -> Self {
+        let lock = SESSION_LOCKS
+            .lock()
+            .unwrap()
+            .entry(upload_id.to_string())
+            .or_default()
+            .clone();
+        // the locks map is released here
+
+        Self {
+            upload_id: upload_id.to_string(),
+            guard: Some(lock.lock_owned().await),
+        }
+    }
+}
+
+impl Drop for SessionLock {
+    fn drop(&mut self) {
+        drop(self.guard.take());
+
+        // forget the lock once nobody else is waiting for it
+        let mut locks = SESSION_LOCKS.lock().unwrap();
+        if locks
+            .get(&self.upload_id)
+            .is_some_and(|lock| Arc::strong_count(lock) == 1)
+        {
+            locks.remove(&self.upload_id);
+        }
+    }
+}
+
+#[derive(Deserialize, ToSchema)]
+pub struct CreateUploadRequest {
+    #[schema(example = "holiday.mp4")]
+    name: String,
+    /// Size of the whole file in bytes
+    #[schema(example = 104857600)]
+    size: i64,
+}
+
+#[derive(Serialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct UploadSession {
+    #[schema(example = "Xr2bXkGJDmvfuoUpGV4XL")]
+    id: String,
+    #[schema(example = "holiday.mp4")]
+    name: String,
+    #[schema(example = 104857600)]
+    size: i64,
+    /// How many bytes from the start of the file have been received so far.
+    /// Continue uploading from here.
+    #[schema(example = 16000000)]
+    received: i64,
+    expires_at: DateTime<Utc>,
+}
+
+impl UploadSession {
+    fn new(id: String, name: String, size: i64, received: i64, expires_at: NaiveDateTime) -> Self {
+        Self {
+            id,
+            name,
+            size,
+            received,
+            expires_at: DateTime::<Utc>::from_naive_utc_and_offset(expires_at, Utc),
+        }
+    }
+}
+
+#[derive(Deserialize, IntoParams)]
+pub struct UploadSessionPath {
+    upload_id: String,
+}
+
+/// Where the received parts of a chunked upload are kept
+pub fn session_path(upload_id: &str) -> HResult<String> {
+    let name = format!("session_{}", upload_id);
+
+    // path traversal prevention
+    if !FILENAME_REGEX.is_match(&name) {
+        return err!(404);
+    }
+
+    Ok(storage::spool_dir() + "/" + &name)
+}
+
+/// Start chunked upload
+///
+/// Starts uploading a file in pieces, which can be resumed if the connection
+/// drops. Send the pieces in order to `PUT /media/uploads/{upload_id}` and
+/// call `POST /media/uploads/{upload_id}/finish` once everything has been
+/// sent. Unfinished uploads are thrown away after 24 hours without progress.
+#[utoipa::path(
+    tag = "media",
+    security(("token" = [])),
+    request_body = CreateUploadRequest,
+    responses(
+        (status = OK, description = "Upload started", body = UploadSession),
+        (status = BAD_REQUEST, description = "Invalid size or name, or too many unfinished uploads"),
+        (status = PAYLOAD_TOO_LARGE, description = "The file exceeds the server's size limit or your storage quota")
+    )
+)]
+#[post("/media/uploads")]
+pub async fn create_upload(
+    db: DB,
+    token: AccessToken,
+    req: Json<CreateUploadRequest>,
+) -> HResult<Json<UploadSession>> {
+    if req.size < 1 {
+        err!(400, "Cannot upload an empty file.")?;
+    }
+
+    if req.size > MAX_FILE_SIZE as i64 {
+        err!(
+            413,
+            format!("File exceeds size limit of {} bytes", MAX_FILE_SIZE)
+        )?;
+    }
+
+    // unfinished uploads count towards the quota too, so that starting lots of
+    // them doesn't get around it
+    let pending = sqlx::query!(
+        r#"SELECT COALESCE(SUM(size), 0)::bigint AS "pending!" FROM upload_sessions
+            WHERE uploader = $1 AND expires_at > now()"#,
+        token.user_id
+    )
+    .fetch_one(&db.pool)
+    .await?
+    .pending;
+
+    quota::check_quota(&db, &token.user_id, pending + req.size).await?;
+
+    let open_sessions = sqlx::query!(
+        r#"SELECT COUNT(*) AS "count!" FROM upload_sessions WHERE uploader = $1 AND expires_at > now()"#,
+        token.user_id
+    )
+    .fetch_one(&db.pool)
+    .await?
+    .count;
+
+    if open_sessions >= MAX_OPEN_SESSIONS {
+        err!(
+            400,
+            format!(
+                "You cannot have more than {} unfinished uploads.",
+                MAX_OPEN_SESSIONS
+            )
+        )?;
+    }
+
+    let name = clean_filename(req.name.clone()).unwrap_or_else(random_file_name);
+    let id = nanoid!();
+    let expires_at = (Utc::now() + session_lifetime()).naive_utc();
+
+    sqlx::query!(
+        r#"INSERT INTO upload_sessions (id, uploader, name, size, expires_at) VALUES ($1, $2, $3, $4, $5)"#,
+        id,
+        token.user_id,
+        name,
+        req.size,
+        expires_at
+    )
+    .execute(&db.pool)
+    .await?;
+
+    Ok(Json(UploadSession::new(id, name, req.size, 0, expires_at)))
+}
+
+/// Get chunked upload
+///
+/// Check how much of a chunked upload has been received, eg. to find out
+/// where to continue from after losing connection.
+#[utoipa::path(
+    tag = "media",
+    security(("token" = [])),
+    params(UploadSessionPath),
+    responses(
+        (status = OK, description = "Upload progress", body = UploadSession),
+        (status = NOT_FOUND, description = "No such upload, or it has expired")
+    )
+)]
+#[get("/media/uploads/{upload_id}")]
+pub async fn get_upload(
+    db: DB,
+    token: AccessToken,
+    path: Path<UploadSessionPath>,
+) -> HResult<Json<UploadSession>> {
+    let session = sqlx::query!(
+        r#"SELECT id, name, size, received, expires_at FROM upload_sessions
+            WHERE id = $1 AND uploader = $2 AND expires_at > now()"#,
+        path.upload_id,
+        token.user_id
+    )
+    .fetch_optional(&db.pool)
+    .await?
+    .or_err(404)?;
+
+    Ok(Json(UploadSession::new(
+        session.id,
+        session.name,
+        session.size,
+        session.received,
+        session.expires_at,
+    )))
+}
+
+/// Parses a `Content-Range: bytes <start>-<end>/<size>` header
+fn parse_content_range(req: &HttpRequest) -> Option<(i64, i64, i64)> {
+    let value = req.headers().get("content-range")?.to_str().ok()?;
+    let (range, size) = value.strip_prefix("bytes ")?.split_once('/')?;
+    let (start, end) = range.split_once('-')?;
+
+    Some((start.parse().ok()?, end.parse().ok()?, size.parse().ok()?))
+}
+
+/// Writes a chunk into the spooled file at `start`, replacing everything
+/// after it
+async fn write_chunk(
+    file: &mut File,
+    payload: &mut Payload,
+    start: i64,
+    length: i64,
+    upload_id: &str,
+) -> HResult<()> {
+    file.set_len(start as u64).await.or_err(500)?;
+    file.seek(SeekFrom::Start(start as u64)).await.or_err(500)?;
+
+    let mut written = 0;
+    while let Some(chunk) = payload.try_next().await.or_err(400)? {
+        written += chunk.len() as i64;
+
+        // don't trust the client to stop where it said it would
+        if written > length {
+            err!(400, "Request body is longer than the Content-Range")?;
+        }
+
+        if let Err(e) = file.write_all(&chunk).await {
+            warn!("writing chunk of upload `{}` failed: {}", upload_id, e);
+            err!()?;
+        }
+    }
+
+    if written != length {
+        err!(400, "Request body is shorter than the Content-Range")?;
+    }
+
+    file.flush().await.or_err(500)
+}
+
+/// Upload chunk
+///
+/// Sends a piece of a file as the raw request body. Which piece it is must be
+/// given with a `Content-Range: bytes <start>-<end>/<size>` header, where
+/// `end` is inclusive. A piece can be at most 16MB.
+///
+/// Pieces must be sent in order. A piece may start before the end of what has
+/// already been received, in which case everything after its start is
+/// replaced, so a piece that failed halfway can simply be sent again. What was
+/// received before a failed piece may be thrown away with it, so continue from
+/// the `received` the upload reports afterwards.
+#[utoipa::path(
+    tag = "media",
+    security(("token" = [])),
+    params(UploadSessionPath),
+    request_body(content = Vec<u8>, content_type = "application/octet-stream"),
+    responses(
+        (status = OK, description = "Chunk received", body = UploadSession),
+        (status = BAD_REQUEST, description = "Invalid or missing Content-Range, or the body does not match it"),
+        (status = NOT_FOUND, description = "No such upload, or it has expired"),
+        (status = CONFLICT, description = "The chunk starts after the end of what has been received so far")
+    )
+)]
+#[put("/media/uploads/{upload_id}")]
+pub async fn upload_chunk(
+    db: DB,
+    token: AccessToken,
+    path: Path<UploadSessionPath>,
+    req: HttpRequest,
+    mut payload: Payload,
+) -> HResult<Json<UploadSession>> {
+    let (start, end, size) =
+        parse_content_range(&req).or_err_msg(400, "Invalid or missing Content-Range header")?;
+
+    if start < 0 || end < start {
+        err!(400, "Invalid Content-Range header")?;
+    }
+
+    let length = end - start + 1;
+    if length > MAX_CHUNK_SIZE {
+        err!(
+            413,
+            format!("Chunks cannot be larger than {} bytes", MAX_CHUNK_SIZE)
+        )?;
+    }
+
+    let file_path = session_path(&path.upload_id)?;
+
+    // chunks of the same file can't be written at the same time
+    let _lock = SessionLock::acquire(&path.upload_id).await;
+
+    let session = sqlx::query!(
+        r#"SELECT id, name, size, received FROM upload_sessions
+            WHERE id = $1 AND uploader = $2 AND expires_at > now()"#,
+        path.upload_id,
+        token.user_id
+    )
+    .fetch_optional(&db.pool)
+    .await?
+    .or_err(404)?;
+
+    if size != session.size || end >= session.size {
+        err!(400, "Content-Range does not match the size of the file")?;
+    }
+
+    if start > session.received {
+        err!(
+            409,
+            format!(
+                "Chunk starts after the end of what has been received ({} bytes).",
+                session.received
+            )
+        )?;
+    }
+
+    tokio::fs::create_dir_all(storage::spool_dir())
+        .await
+        .or_err(500)?;
+
+    let mut file = OpenOptions::new()
+        .create(true)
+        .write(true)
+        .truncate(false)
+        .open(&file_path)
+        .await
+        .or_err(500)?;
+
+    if let Err(e) = write_chunk(&mut file, &mut payload, start, length, &session.id).await {
+        // whatever was received after the start of the chunk may be gone now,
+        // so the chunk has to be sent again
+        sqlx::query!(
+            r#"UPDATE upload_sessions SET received = LEAST(received, $2) WHERE id = $1"#,
+            session.id,
+            start
+        )
+        .execute(&db.pool)
+        .await?;
+
+        return Err(e);
+    }
+
+    let expires_at = (Utc::now() + session_lifetime()).naive_utc();
+
+    let mut tx = db.pool.begin().await?;
+
+    // the session may have expired while the chunk was arriving
+    let received = sqlx::query!(
+        r#"SELECT received FROM upload_sessions
+            WHERE id = $1 AND expires_at > now()
+            FOR UPDATE"#,
+        session.id
+    )
+    .fetch_optional(&mut tx)
+    .await?
+    .or_err(404)?
+    .received;
+
+    if start > received {
+        err!(
+            409,
+            "The upload changed while the chunk was being received."
+        )?;
+    }
+
+    sqlx::query!(
+        r#"UPDATE upload_sessions SET received = $2, expires_at = $3 WHERE id = $1"#,
+        session.id,
+        end + 1,
+        expires_at
+    )
+    .execute(&mut tx)
+    .await?;
+
+    tx.commit().await?;
+
+    Ok(Json(UploadSession::new(
+        session.id,
+        session.name,
+        session.size,
+        end + 1,
+        expires_at,
+    )))
+}
+
+/// Finish chunked upload
+///
+/// Once the whole file has been received, this turns it into a normal upload
+/// that can be used like one from `/media/upload`.
+#[utoipa::path(
+    tag = "media",
+    security(("token" = [])),
+    params(UploadSessionPath),
+    responses(
+        (status = OK, description = "File uploaded", body = UploadedFileInfo),
+        (status = BAD_REQUEST, description = "The whole file has not been received yet"),
+        (status = NOT_FOUND, description = "No such upload, or it has expired")
+    )
+)]
+#[post("/media/uploads/{upload_id}/finish")]
+pub async fn finish_upload(
+    db: DB,
+    storage: Storage,
+    token: AccessToken,
+    path: Path<UploadSessionPath>,
+) -> HResult<Json<UploadedFileInfo>> {
+    let file_path = session_path(&path.upload_id)?;
+
+    // wait for a chunk that is still being written
+    let _lock = SessionLock::acquire(&path.upload_id).await;
+
+    let mut tx = db.pool.begin().await?;
+
+    let session = sqlx::query!(
+        r#"SELECT id, name, size, received FROM upload_sessions
+            WHERE id = $1 AND uploader = $2 AND expires_at > now()
+            FOR UPDATE"#,
+        path.upload_id,
+        token.user_id
+    )
+    .fetch_optional(&mut tx)
+    .await?
+    .or_err(404)?;
+
+    if session.received != session.size {
+        err!(
+            400,
+            format!(
+                "Only {} of {} bytes have been received.",
+                session.received, session.size
+            )
+        )?;
+    }
+
+    sqlx::query!("DELETE FROM upload_sessions WHERE id = $1", session.id)
+        .execute(&mut tx)
+        .await?;
+
+    tx.commit().await?;
+
+    let saved = match SavedFile::from_path(&file_path).await {
+        Ok(saved) if saved.size as i64 == session.size => saved,
+        result => {
+            if let Err(e) = result {
+                warn!("reading finished upload `{}` failed: {}", session.id, e);
+            }
+            let _ = tokio::fs::remove_file(&file_path).await;
+            return err!();
+        }
+    };
+
+    let media = store_upload(
+        &db,
+        &storage,
+        &token.user_id,
+        nanoid!(),
+        session.name,
+        &file_path,
+        saved,
+    )
+    .await?;
+
+    Ok(Json(sign_file_info(media.file_info())))
+}
+
+/// Cancel chunked upload
+///
+/// Throws away an unfinished upload and everything received for it.
+#[utoipa::path(
+    tag = "media",
+    security(("token" = [])),
+    params(UploadSessionPath),
+    responses(
+        (status = OK, description = "Upload cancelled"),
+        (status = NOT_FOUND, description = "No such upload")
+    )
+)]
+#[delete("/media/uploads/{upload_id}")]
+pub async fn cancel_upload(
+    db: DB,
+    token: AccessToken,
+    path: Path<UploadSessionPath>,
+) -> HResult<HttpResponse> {
+    let file_path = session_path(&path.upload_id)?;
+
+    let _lock = SessionLock::acquire(&path.upload_id).await;
+
+    let result = sqlx::query!(
+        "DELETE FROM upload_sessions WHERE id = $1 AND uploader = $2",
+        path.upload_id,
+        token.user_id
+    )
+    .execute(&db.pool)
+    .await?;
+
+    if result.rows_affected() == 0 {
+        err!(404)?;
+    }
+
+    let _ = tokio::fs::remove_file(&file_path).await;
+
+    Ok(HttpResponse::Ok().finish())
+}
+
+#[cfg(test)]
+mod tests {
+    use actix_web::test::TestRequest;
+
+    use super::parse_content_range;
+
+    fn content_range(value: &str) -> Option<(i64, i64, i64)> {
+        let req = TestRequest::default()
+            .insert_header(("Content-Range", value))
+            .to_http_request();
+        parse_content_range(&req)
+    }
+
+    #[test]
+    fn parses_content_range() {
+        assert_eq!(content_range("bytes 0-99/200"), Some((0, 99, 200)));
+        assert_eq!(content_range("bytes 100-199/200"), Some((100, 199, 200)));
+        assert_eq!(
+            content_range("bytes 0-16777215/1000000000"),
+            Some((0, 16777215, 1000000000))
+        );
+    }
+
+    #[test]
+    fn rejects_malformed_content_range() {
+        for value in [
+            "",
+            "0-99/200",
+            "items 0-99/200",
+            "bytes 0-99",
+            "bytes 0/200",
+            "bytes 0-99/*",
+            "bytes */200",
+            "bytes a-99/200",
+            "bytes 0-99/200 ",
+        ] {
+            assert_eq!(content_range(value), None, "{:?} was accepted", value);
+        }
+    }
+
+    #[test]
+    fn requires_content_range() {
+        let req = TestRequest::default().to_http_request();
+
+        assert_eq!(parse_content_range(&req), None);
+    }
+}
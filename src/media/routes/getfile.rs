@@ -1,14 +1,29 @@
-use std::fs::File;
+use std::io::ErrorKind;
 
-use actix_files::NamedFile;
-use actix_web::{get, web::Path};
+use actix_web::{
+    get,
+    http::{
+        header::{self, ETag, EntityTag, HeaderValue, IfNoneMatch},
+        StatusCode,
+    },
+    web::{Path, Query},
+    HttpMessage, HttpRequest, HttpResponse,
+};
+use log::warn;
 use serde::Deserialize;
 use utoipa::{IntoParams, ToSchema};
 
 use crate::{
+    auth::access_token::AccessToken,
+    db::DB,
     error::{macros::err, HResult, IntoHandlerErrorResult},
-    media::FILENAME_REGEX,
-    options,
+    media::{
+        images,
+        record::Media,
+        signing,
+        storage::{ServedFile, Storage},
+        FILENAME_REGEX,
+    },
 };
 
 #[derive(Deserialize, IntoParams)]
@@ -19,6 +34,27 @@ pub struct FileIdentifierPath {
     pub filename: String,
 }
 
+#[derive(Deserialize, IntoParams)]
+pub struct GetFileQuery {
+    /// Get a thumbnail of an image that fits in a square of this size instead
+    /// of the original file. Sizes are rounded up to 64, 128, 256, 512 or 1024.
+    #[param(style = Form, example = 256)]
+    pub size: Option<u32>,
+    /// When the signature expires, as a unix timestamp. Part of signed URLs.
+    #[param(style = Form, example = 1710000000)]
+    pub expires: Option<i64>,
+    /// Signature allowing the file to be downloaded without being logged in.
+    /// Part of signed URLs.
+    #[param(style = Form)]
+    pub sig: Option<String>,
+}
+
+/// Files never change once uploaded, so they can be cached for as long as
+/// caches are willing to keep them
+const PUBLIC_CACHE_CONTROL: &str = "public, max-age=31536000, immutable";
+/// Signed urls and files behind a token shouldn't end up in shared caches
+const PRIVATE_CACHE_CONTROL: &str = "private, max-age=31536000, immutable";
+
 // just a helper struct to describe what a file looks like to openapi
 #[derive(ToSchema)]
 #[schema(example = "(binary file body)")]
@@ -26,19 +62,40 @@ struct OApiFileResponse {}
 
 /// Download file
 ///
-/// Retreives the requested file's contents bit-for-bit as it was when uploaded.
-/// This endpoint does not currently require authentication, which means that
-/// external references to images hosted on Zling can be made.
+/// Retreives the requested file's contents bit-for-bit as it was when uploaded,
+/// apart from image metadata which is removed on upload. For images, a
+/// thumbnail can be requested with the `size` parameter.
+///
+/// Avatars and icons can be downloaded by anyone. Other files, like message
+/// attachments, need either a signature or a token of someone who can see a
+/// message they are attached to. Attachment URLs sent by the server are
+/// already signed, and stay valid for at least a day.
+///
+/// Only images, videos and audio are shown inline, anything else is sent as
+/// a download. Files never change, so responses come with an ETag and can be
+/// cached indefinitely. Ranges are supported for seeking in videos.
 #[utoipa::path(
     tag = "media",
-    params(FileIdentifierPath),
+    params(FileIdentifierPath, GetFileQuery),
     responses(
         (status = OK, description = "Requested file", content_type = "multipart", body = inline(OApiFileResponse)),
+        (status = PARTIAL_CONTENT, description = "Requested range of the file", content_type = "multipart", body = inline(OApiFileResponse)),
+        (status = NOT_MODIFIED, description = "The file matches the ETag in `If-None-Match`"),
+        (status = FOUND, description = "Redirect to a pre-signed URL the file can be downloaded from, if the server is set up to do so"),
+        (status = BAD_REQUEST, description = "A thumbnail was requested for a file that isn't an image"),
+        (status = FORBIDDEN, description = "Missing or expired signature, and no permission to see the file"),
         (status = NOT_FOUND, description = "File not found", example = "not_found")
     )
 )]
 #[get("/media/{id}/{filename}")]
-pub async fn getfile(req: Path<FileIdentifierPath>) -> HResult<NamedFile> {
+pub async fn getfile(
+    db: DB,
+    storage: Storage,
+    request: HttpRequest,
+    req: Path<FileIdentifierPath>,
+    query: Query<GetFileQuery>,
+    token: Option<AccessToken>,
+) -> HResult<HttpResponse> {
     let filename = req.id.clone() + "_" + &req.filename;
 
     // path traversal prevention
@@ -46,8 +103,239 @@ pub async fn getfile(req: Path<FileIdentifierPath>) -> HResult<NamedFile> {
         return err!(404);
     }
 
-    let path = (*options::MEDIA_PATH).to_string() + "/" + &filename;
+    let media = db.get_media(&req.id).await?;
+    if media.as_ref().is_some_and(|m| m.name != req.filename) {
+        return err!(404);
+    }
+
+    let visibility = match media {
+        Some(ref media) => visibility(&db, media, &query, token.as_ref()).await?,
+        None => legacy_visibility(&db, &req, &query, token.as_ref()).await?,
+    };
+
+    let cache_control = match visibility {
+        Some(Visibility::Public) => PUBLIC_CACHE_CONTROL,
+        Some(Visibility::Private) => PRIVATE_CACHE_CONTROL,
+        None => return err!(403),
+    };
+
+    if let Some(size) = query.size {
+        let media = media.or_err(404)?;
+        return get_thumbnail(&storage, &request, &media, size, cache_control).await;
+    }
+
+    // files uploaded before media was recorded in the database don't have a
+    // known type or hash, the storage guesses the type from the name instead
+    let etag = media
+        .as_ref()
+        .map(|m| EntityTag::new_strong(m.sha256.clone()));
+    let file = ServedFile {
+        key: &filename,
+        filename: &req.filename,
+        content_type: media.as_ref().map(|m| m.mime_type.as_str()),
+        size: media.as_ref().map(|m| m.size as u64),
+        etag: etag.as_ref().map(|e| e.tag()),
+        cache_control,
+    };
+
+    serve(&storage, &request, &file, etag.as_ref()).await
+}
+
+enum Visibility {
+    /// Anyone can download the file, so shared caches may keep it
+    Public,
+    Private,
+}
+
+/// Who can see the file, or `None` if the requester can't download it
+async fn visibility(
+    db: &DB,
+    media: &Media,
+    query: &GetFileQuery,
+    token: Option<&AccessToken>,
+) -> HResult<Option<Visibility>> {
+    if db.is_media_public(media).await? {
+        return Ok(Some(Visibility::Public));
+    }
+
+    if let (Some(expires), Some(sig)) = (query.expires, &query.sig) {
+        if signing::verify(&media.id, expires, sig) {
+            return Ok(Some(Visibility::Private));
+        }
+    }
+
+    let can_access = match token {
+        Some(token) => db.can_user_access_media(&token.user_id, media).await?,
+        None => false,
+    };
+
+    Ok(can_access.then_some(Visibility::Private))
+}
+
+/// Who can see a file uploaded before media was recorded in the database.
+/// Avatars and icons stay public, anything else can only be seen by those who
+/// can see a message it is attached to.
+async fn legacy_visibility(
+    db: &DB,
+    file: &FileIdentifierPath,
+    query: &GetFileQuery,
+    token: Option<&AccessToken>,
+) -> HResult<Option<Visibility>> {
+    let url = format!("/media/{}/{}", file.id, file.filename);
+    if db.is_media_url_public(&url).await? {
+        return Ok(Some(Visibility::Public));
+    }
+
+    if let (Some(expires), Some(sig)) = (query.expires, &query.sig) {
+        if signing::verify(&file.id, expires, sig) {
+            return Ok(Some(Visibility::Private));
+        }
+    }
+
+    let can_access = match token {
+        Some(token) => {
+            db.can_user_access_legacy_attachment(&token.user_id, &file.id)
+                .await?
+        }
+        None => false,
+    };
+
+    Ok(can_access.then_some(Visibility::Private))
+}
+
+async fn get_thumbnail(
+    storage: &Storage,
+    request: &HttpRequest,
+    media: &Media,
+    size: u32,
+    cache_control: &str,
+) -> HResult<HttpResponse> {
+    if !media.has_thumbnails() {
+        return err!(400, "Thumbnails are only available for images.");
+    }
+
+    let size = images::thumbnail_size(size);
+
+    // no point in making a thumbnail bigger than the image
+    let largest_side = media.width.max(media.height).unwrap_or(0) as u32;
+    if largest_side <= size {
+        let etag = EntityTag::new_strong(media.sha256.clone());
+        let file = ServedFile {
+            key: &media.key(),
+            filename: &media.name,
+            content_type: Some(&media.mime_type),
+            size: Some(media.size as u64),
+            etag: Some(etag.tag()),
+            cache_control,
+        };
+
+        return serve(storage, request, &file, Some(&etag)).await;
+    }
+
+    // a thumbnail of a certain size is always made from the same original
+    let etag = EntityTag::new_strong(format!("{}-{}", media.sha256, size));
+    if let Some(response) = not_modified(request, &etag, cache_control) {
+        return Ok(response);
+    }
+
+    let format = images::thumbnail_format(&media.mime_type);
+    let extension = format.extensions_str()[0];
+    let thumbnail_name = format!("thumbnail_{}.{}", size, extension);
+    let thumbnail_key = media.thumbnail_key(size, extension);
+
+    // thumbnails are made the first time they are requested and kept around
+    if !storage.exists(&thumbnail_key).await.or_err(500)? {
+        let bytes = storage.read(&media.key()).await.or_err(404)?;
+        let mime_type = media.mime_type.clone();
+        let thumbnail =
+            actix_web::web::block(move || images::make_thumbnail(&bytes, &mime_type, size))
+                .await
+                .or_err(500)?;
+
+        let Some(thumbnail) = thumbnail else {
+            warn!("making thumbnail of `{}` failed", media.id);
+            return err!();
+        };
+
+        storage.store(&thumbnail_key, thumbnail).await.or_err(500)?;
+    }
+
+    let file = ServedFile {
+        key: &thumbnail_key,
+        filename: &thumbnail_name,
+        content_type: Some(format.to_mime_type()),
+        size: None,
+        etag: Some(etag.tag()),
+        cache_control,
+    };
+
+    serve(storage, request, &file, Some(&etag)).await
+}
+
+/// Responds with 304 Not Modified if the client already has this version of
+/// the file
+fn not_modified(
+    request: &HttpRequest,
+    etag: &EntityTag,
+    cache_control: &str,
+) -> Option<HttpResponse> {
+    let matches = match request.get_header::<IfNoneMatch>()? {
+        IfNoneMatch::Any => true,
+        IfNoneMatch::Items(ref items) => items.iter().any(|item| item.weak_eq(etag)),
+    };
+
+    matches.then(|| {
+        HttpResponse::NotModified()
+            .insert_header(ETag(etag.clone()))
+            .insert_header((header::CACHE_CONTROL, cache_control))
+            .finish()
+    })
+}
+
+async fn serve(
+    storage: &Storage,
+    request: &HttpRequest,
+    file: &ServedFile<'_>,
+    etag: Option<&EntityTag>,
+) -> HResult<HttpResponse> {
+    if let Some(etag) = etag {
+        if let Some(response) = not_modified(request, etag, file.cache_control) {
+            return Ok(response);
+        }
+    }
+
+    let mut response = match storage.serve(request, file).await {
+        Ok(response) => response,
+        Err(e) if e.kind() == ErrorKind::NotFound => return err!(404),
+        Err(e) => {
+            warn!("serving usermedia `{}` failed: {}", file.key, e);
+            return err!();
+        }
+    };
+
+    // the file a redirect leads to gets its headers from wherever it's
+    // served
+    if response.status() == StatusCode::FOUND {
+        return Ok(response);
+    }
+
+    let headers = response.headers_mut();
+
+    // files live at the same url forever, and are never changed
+    headers.insert(
+        header::CACHE_CONTROL,
+        HeaderValue::from_str(file.cache_control).or_err(500)?,
+    );
+    if let Some(etag) = etag {
+        headers.insert(
+            header::ETAG,
+            HeaderValue::from_str(&etag.to_string()).or_err(500)?,
+        );
+    }
+    headers.insert(
+        header::X_CONTENT_TYPE_OPTIONS,
+        HeaderValue::from_static("nosniff"),
+    );
 
-    let file = File::open(path).or_err(404)?;
-    NamedFile::from_file(file, &req.filename).or_err(404)
+    Ok(response)
 }
@@ -2,11 +2,22 @@ use utoipa::OpenApi;
 
 use self::upload::{UploadedFileInfo, UploadedFileType};
 
+pub mod chunked_upload;
 pub mod getfile;
 pub mod upload;
+pub mod usage;
 
 pub fn configure_app(cfg: &mut actix_web::web::ServiceConfig) {
-    cfg.service(getfile::getfile).service(upload::upload);
+    // chunked uploads go first, `/media/uploads/{upload_id}` would otherwise
+    // be taken for a file by getfile
+    cfg.service(chunked_upload::create_upload)
+        .service(chunked_upload::get_upload)
+        .service(chunked_upload::upload_chunk)
+        .service(chunked_upload::finish_upload)
+        .service(chunked_upload::cancel_upload)
+        .service(getfile::getfile)
+        .service(upload::upload)
+        .service(usage::get_usage);
 }
 
 #[derive(OpenApi)]
@@ -16,11 +27,20 @@ pub fn configure_app(cfg: &mut actix_web::web::ServiceConfig) {
     ),
     paths(
         getfile::getfile,
-        upload::upload
+        upload::upload,
+        chunked_upload::create_upload,
+        chunked_upload::get_upload,
+        chunked_upload::upload_chunk,
+        chunked_upload::finish_upload,
+        chunked_upload::cancel_upload,
+        usage::get_usage
     ),
     components(schemas(
         UploadedFileInfo,
-        UploadedFileType
+        UploadedFileType,
+        chunked_upload::CreateUploadRequest,
+        chunked_upload::UploadSession,
+        usage::MediaUsage
     ))
 )]
 pub struct MediaApiDocs;
@@ -2,21 +2,36 @@ use std::io::{self, ErrorKind};
 
 use actix_multipart::{Field, Multipart};
 use actix_web::{post, web::Json, HttpRequest};
+use chrono::Utc;
 use futures::TryStreamExt;
 use log::warn;
 use nanoid::nanoid;
 use serde::{Deserialize, Serialize};
-use tokio::{fs::OpenOptions, io::AsyncWriteExt};
+use sha2::{Digest, Sha256};
+use tokio::{
+    fs::OpenOptions,
+    io::{AsyncReadExt, AsyncWriteExt},
+};
 use utoipa::ToSchema;
 
 use crate::{
     auth::access_token::AccessToken,
+    db::DB,
     error::{macros::err, HResult, IntoHandlerErrorResult},
-    media::{util::clean_filename, FILENAME_REGEX},
-    options,
+    media::{
+        images, quota,
+        record::Media,
+        signing::sign_file_info,
+        storage::{self, Storage},
+        util::clean_filename,
+        FILENAME_REGEX,
+    },
 };
 
-const MAX_FILE_SIZE: usize = 250 * 1_000_000; // 250 MB
+pub const MAX_FILE_SIZE: usize = 250 * 1_000_000; // 250 MB
+
+/// How much of the start of a file is kept around to detect its type from
+const SNIFF_LENGTH: usize = 8192;
 
 #[derive(Serialize, Deserialize, ToSchema)]
 #[serde(rename_all = "camelCase")]
@@ -28,6 +43,17 @@ pub struct UploadedFileInfo {
     #[schema(example = "/media/s6NIiu2oOh1FEL0Xfjc7n/cat.jpg")]
     pub url: String,
     pub r#type: UploadedFileType,
+    /// A smaller version of the image, only present for images. Change the
+    /// `size` query parameter to get a different size.
+    #[schema(example = "/media/s6NIiu2oOh1FEL0Xfjc7n/cat.jpg?size=256")]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub thumbnail_url: Option<String>,
+    #[schema(example = 1920)]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub width: Option<u32>,
+    #[schema(example = 1080)]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub height: Option<u32>,
 }
 
 #[derive(Serialize, Deserialize, ToSchema)]
@@ -41,6 +67,23 @@ pub enum UploadedFileType {
     Text,
 }
 
+impl UploadedFileType {
+    pub fn from_mime(mime_type: &str) -> Self {
+        use UploadedFileType::*;
+
+        match mime_type.parse::<mime::Mime>() {
+            Ok(m) => match m.type_() {
+                mime::TEXT => Text,
+                mime::IMAGE => Image,
+                mime::AUDIO => Audio,
+                mime::VIDEO => Video,
+                _ => Blob,
+            },
+            Err(_) => Blob,
+        }
+    }
+}
+
 // this struct isn't actually used in useful code, just the openapi definition
 #[derive(ToSchema)]
 pub struct UploadFormRequest {
@@ -54,6 +97,13 @@ pub struct UploadFormRequest {
 /// server. If your file name is not ASCII alphanumeric, it will be cleaned
 /// first before it is saved. You must not exceed the Zling server's filesize
 /// limit, which is usually around 250MB.
+///
+/// The type of the file is detected from its contents, the content type sent
+/// by the client is ignored. Use the returned `id` to attach the file to a
+/// message or to set it as an avatar or icon.
+///
+/// For big files on unreliable connections, use a chunked upload starting at
+/// `POST /media/uploads` instead.
 #[utoipa::path(
     tag = "media",
     security(("token" = [])),
@@ -65,12 +115,14 @@ pub struct UploadFormRequest {
     responses(
         (status = OK, description = "File uploaded", body = UploadedFileInfo),
         (status = BAD_REQUEST, description = "Invalid file supplied (eg. name could not be cleaned)"),
-        (status = PAYLOAD_TOO_LARGE, description = "The file exceeds the server's size limit")
+        (status = PAYLOAD_TOO_LARGE, description = "The file exceeds the server's size limit or your storage quota")
     )
 )]
 #[post("/media/upload")]
 pub async fn upload(
-    _token: AccessToken,
+    db: DB,
+    storage: Storage,
+    token: AccessToken,
     mut payload: Multipart,
     request: HttpRequest,
 ) -> HResult<Json<UploadedFileInfo>> {
@@ -90,7 +142,10 @@ pub async fn upload(
         )?;
     }
 
-    while let Some(field) = payload.try_next().await.unwrap() {
+    // the form around the file makes this a little more than the file itself
+    quota::check_quota(&db, &token.user_id, payload_size as i64).await?;
+
+    while let Some(field) = payload.try_next().await.or_err(400)? {
         let content_disposition = field.content_disposition();
         let field_name = content_disposition.get_name().unwrap();
 
@@ -105,68 +160,225 @@ pub async fn upload(
             None => random_file_name(),
         };
 
-        // get the file type
-        use UploadedFileType::*;
-        let r#type: UploadedFileType = match field.content_type() {
-            Some(t) => match t.type_() {
-                mime::TEXT => Text,
-                mime::IMAGE => Image,
-                mime::AUDIO => Audio,
-                mime::VIDEO => Video,
-                _ => Blob,
-            },
-            None => Blob,
-        };
-
         let id = nanoid!();
-        {
-            let filename = id.clone() + "_" + &filename;
+        let stored_name = id.clone() + "_" + &filename;
 
-            // final check for file name validity, just to be sure...
-            if !FILENAME_REGEX.is_match(&filename) {
-                warn!("filename invalid after cleaning: `{}`", filename);
-                return err!(400, "Invalid file name.");
-            }
+        // final check for file name validity, just to be sure...
+        if !FILENAME_REGEX.is_match(&stored_name) {
+            warn!("filename invalid after cleaning: `{}`", stored_name);
+            return err!(400, "Invalid file name.");
+        }
 
-            let path = (*options::MEDIA_PATH).to_string() + "/" + &filename;
+        // the file is written to the local disk first and only handed over to
+        // storage once it is complete and processed
+        let spool_dir = storage::spool_dir();
+        tokio::fs::create_dir_all(&spool_dir).await.or_err(500)?;
+        let path = spool_dir + "/" + &stored_name;
 
-            let result = save_file(&path, field).await;
-            if let Err(e) = result {
-                warn!("saving usermedia `{}` failed: {}", filename, e);
+        let saved = match save_file(&path, field).await {
+            Ok(saved) => saved,
+            Err(e) if e.kind() == ErrorKind::InvalidData => {
+                let _ = tokio::fs::remove_file(&path).await;
+                return err!(
+                    413,
+                    format!("File exceeds size limit of {} bytes", MAX_FILE_SIZE)
+                );
+            }
+            Err(e) => {
+                warn!("saving usermedia `{}` failed: {}", stored_name, e);
+                let _ = tokio::fs::remove_file(&path).await;
                 return err!();
             }
-        }
+        };
+
+        let media = store_upload(&db, &storage, &token.user_id, id, filename, &path, saved).await?;
 
-        let url = format!("/media/{}/{}", id, filename);
-        return Ok(Json(UploadedFileInfo {
-            id,
-            name: filename,
-            url,
-            r#type,
-        }));
+        return Ok(Json(sign_file_info(media.file_info())));
     }
 
     err!(400, "The form submitted is missing the 'file' field.")?
 }
 
-async fn save_file(path: &str, mut field: Field) -> io::Result<()> {
+/// Records a file that has been completely written to the spool directory at
+/// `path`, processing it if it is an image and handing it over to storage.
+/// The spool file is gone afterwards, whether this succeeds or not.
+pub async fn store_upload(
+    db: &DB,
+    storage: &Storage,
+    uploader: &str,
+    id: String,
+    filename: String,
+    path: &str,
+    saved: SavedFile,
+) -> HResult<Media> {
+    if let Err(e) = quota::check_quota(db, uploader, saved.size as i64).await {
+        let _ = tokio::fs::remove_file(path).await;
+        return Err(e);
+    }
+
+    let mut media = Media {
+        id,
+        uploader: uploader.to_string(),
+        name: filename,
+        size: saved.size as i64,
+        sha256: saved.sha256,
+        mime_type: detect_mime_type(&saved.head),
+        width: None,
+        height: None,
+        created_at: Utc::now().naive_utc(),
+    };
+
+    if matches!(media.file_type(), UploadedFileType::Image)
+        && saved.size <= images::MAX_PROCESSED_IMAGE_SIZE
+    {
+        if let Err(e) = process_image(&mut media, path).await {
+            warn!("processing image `{}` failed: {}", media.key(), e);
+            let _ = tokio::fs::remove_file(path).await;
+            return err!();
+        }
+    }
+
+    if let Err(e) = storage.store_file(&media.key(), path).await {
+        warn!("storing usermedia `{}` failed: {}", media.key(), e);
+        let _ = tokio::fs::remove_file(path).await;
+        return err!();
+    }
+
+    // other uploads may have used up the quota in the meantime
+    if let Err(e) = quota::insert_media(db, &media).await {
+        // don't leave behind files that nobody can ever reference
+        let _ = storage.delete(&media.key()).await;
+        return Err(e);
+    }
+
+    Ok(media)
+}
+
+pub struct SavedFile {
+    pub size: usize,
+    pub sha256: String,
+    /// The first few bytes of the file
+    pub head: Vec<u8>,
+}
+
+impl SavedFile {
+    /// Reads back a file that was written in pieces, eg. through a chunked
+    /// upload.
+    pub async fn from_path(path: &str) -> io::Result<Self> {
+        let mut file = tokio::fs::File::open(path).await?;
+
+        let mut size = 0;
+        let mut hasher = Sha256::new();
+        let mut head = Vec::with_capacity(SNIFF_LENGTH);
+        let mut buf = vec![0; 64 * 1024];
+
+        loop {
+            let read = file.read(&mut buf).await?;
+            if read == 0 {
+                break;
+            }
+
+            let chunk = &buf[..read];
+            size += read;
+
+            if head.len() < SNIFF_LENGTH {
+                let missing = SNIFF_LENGTH - head.len();
+                head.extend_from_slice(&chunk[..missing.min(read)]);
+            }
+
+            hasher.update(chunk);
+        }
+
+        Ok(Self {
+            size,
+            sha256: hex::encode(hasher.finalize()),
+            head,
+        })
+    }
+}
+
+async fn save_file(path: &str, mut field: Field) -> io::Result<SavedFile> {
     let mut file = OpenOptions::new()
-        .create(true)
-        .append(true)
+        .create_new(true)
+        .write(true)
         .open(path)
         .await?;
 
+    let mut size = 0;
+    let mut hasher = Sha256::new();
+    let mut head = Vec::with_capacity(SNIFF_LENGTH);
+
     // actually save the file
     while let Ok(chunk) = field.try_next().await {
-        if chunk.is_none() {
-            return Ok(()); // end of stream
+        let Some(chunk) = chunk else {
+            // end of stream
+            return Ok(SavedFile {
+                size,
+                sha256: hex::encode(hasher.finalize()),
+                head,
+            });
+        };
+
+        // the content length header can't be trusted, so count for ourselves
+        size += chunk.len();
+        if size > MAX_FILE_SIZE {
+            return Err(io::Error::new(ErrorKind::InvalidData, "file_too_large"));
+        }
+
+        if head.len() < SNIFF_LENGTH {
+            let missing = SNIFF_LENGTH - head.len();
+            head.extend_from_slice(&chunk[..missing.min(chunk.len())]);
         }
-        file.write_all(&chunk.unwrap()).await?;
+
+        hasher.update(&chunk);
+        file.write_all(&chunk).await?;
     }
 
     Err(io::Error::new(ErrorKind::Other, "error_polling_next_chunk"))
 }
 
+/// Reads the dimensions of an uploaded image and strips its metadata,
+/// rewriting the file at `path` if needed. Images that can't be decoded are
+/// left alone.
+async fn process_image(media: &mut Media, path: &str) -> io::Result<()> {
+    let bytes = tokio::fs::read(path).await?;
+    let mime_type = media.mime_type.clone();
+
+    let processed = actix_web::web::block(move || images::process_upload(&bytes, &mime_type))
+        .await
+        .map_err(|e| io::Error::new(ErrorKind::Other, e.to_string()))?;
+
+    let Some(processed) = processed else {
+        return Ok(());
+    };
+
+    if let Some(data) = processed.data {
+        tokio::fs::write(path, &data).await?;
+        media.size = data.len() as i64;
+        media.sha256 = hex::encode(Sha256::digest(&data));
+    }
+
+    media.width = Some(processed.width as i32);
+    media.height = Some(processed.height as i32);
+
+    Ok(())
+}
+
+/// Works out the MIME type of a file from its first few bytes.
+fn detect_mime_type(head: &[u8]) -> String {
+    if let Some(kind) = infer::get(head) {
+        return kind.mime_type().to_string();
+    }
+
+    // infer doesn't know about plain text, so treat anything that looks like
+    // UTF-8 as text. the head may cut a character in half, which is fine.
+    match std::str::from_utf8(head) {
+        Ok(_) => "text/plain".to_string(),
+        Err(e) if e.error_len().is_none() => "text/plain".to_string(),
+        Err(_) => mime::APPLICATION_OCTET_STREAM.to_string(),
+    }
+}
+
 pub fn random_file_name() -> String {
     format!("file-{}", nanoid!(6))
 }
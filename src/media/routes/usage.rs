@@ -0,0 +1,42 @@
+use actix_web::{get, web::Json};
+use serde::Serialize;
+use utoipa::ToSchema;
+
+use crate::{auth::access_token::AccessToken, db::DB, error::HResult, media::quota};
+
+#[derive(Serialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct MediaUsage {
+    /// Bytes taken up by your uploads
+    #[schema(example = 52428800)]
+    used: i64,
+    /// How many bytes you can upload in total, not present if there is no limit
+    #[schema(example = 1000000000)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    quota: Option<i64>,
+    #[schema(example = 12)]
+    files: i64,
+}
+
+/// Get storage usage
+///
+/// Shows how much of your upload quota you are using. Uploads that aren't
+/// used in a message, avatar or icon are deleted after a while and stop
+/// counting towards it, as do attachments of deleted messages.
+#[utoipa::path(
+    tag = "media",
+    security(("token" = [])),
+    responses(
+        (status = OK, description = "Storage usage", body = MediaUsage)
+    )
+)]
+#[get("/media/usage")]
+pub async fn get_usage(db: DB, token: AccessToken) -> HResult<Json<MediaUsage>> {
+    let (used, files) = db.get_media_usage(&token.user_id).await?;
+
+    Ok(Json(MediaUsage {
+        used,
+        quota: quota::quota(),
+        files,
+    }))
+}
@@ -0,0 +1,130 @@
+use chrono::Utc;
+
+use crate::{crypto, options::TOKEN_SIGNING_KEY};
+
+use super::routes::upload::UploadedFileInfo;
+
+/// How long a signed media URL can be used for, at least
+const SIGNED_URL_VALIDITY: i64 = 24 * 60 * 60; // 1 day
+
+/// Expiry times are rounded up to this, so that the same file gets the same
+/// URL for a while and browsers can cache it.
+const SIGNED_URL_GRANULARITY: i64 = 60 * 60; // 1 hour
+
+fn signature(media_id: &str, expires: i64) -> String {
+    // prefixed so that a media signature can never be passed off as a token
+    let payload = format!("media.{}.{}", media_id, expires);
+    base64_url::encode(&crypto::sign(&*TOKEN_SIGNING_KEY, payload.as_bytes()))
+}
+
+/// Adds an expiring signature to a media URL, which allows downloading the
+/// file without being logged in.
+pub fn sign_url(url: &str, media_id: &str) -> String {
+    let now = Utc::now().timestamp() + SIGNED_URL_VALIDITY;
+    let expires = now + SIGNED_URL_GRANULARITY - now % SIGNED_URL_GRANULARITY;
+
+    let separator = if url.contains('?') { '&' } else { '?' };
+    format!(
+        "{}{}expires={}&sig={}",
+        url,
+        separator,
+        expires,
+        signature(media_id, expires)
+    )
+}
+
+/// Checks the `expires` and `sig` query parameters of a media URL.
+pub fn verify(media_id: &str, expires: i64, sig: &str) -> bool {
+    if expires < Utc::now().timestamp() {
+        return false;
+    }
+
+    let Ok(sig) = base64_url::decode(sig) else {
+        return false;
+    };
+
+    let payload = format!("media.{}.{}", media_id, expires);
+    crypto::verify_signature(&*TOKEN_SIGNING_KEY, payload.as_bytes(), &sig)
+}
+
+/// Signs the URLs of an attachment so whoever it's sent to can download it.
+pub fn sign_file_info(mut info: UploadedFileInfo) -> UploadedFileInfo {
+    info.url = sign_url(&info.url, &info.id);
+    info.thumbnail_url = info.thumbnail_url.map(|url| sign_url(&url, &info.id));
+    info
+}
+
+/// Reads the attachments of a message as they are stored in the database and
+/// signs their URLs.
+pub fn attachments_from_json(value: Option<serde_json::Value>) -> Option<Vec<UploadedFileInfo>> {
+    let attachments: Vec<UploadedFileInfo> = serde_json::from_value(value?).ok()?;
+
+    Some(attachments.into_iter().map(sign_file_info).collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The `expires` and `sig` query parameters of a signed URL
+    fn query(url: &str) -> (i64, String) {
+        let (_, query) = url.rsplit_once("expires=").unwrap();
+        let (expires, sig) = query.split_once("&sig=").unwrap();
+        (expires.parse().unwrap(), sig.to_string())
+    }
+
+    #[test]
+    fn verifies_signed_urls() {
+        let (expires, sig) = query(&sign_url("/media/abc/cat.png", "abc"));
+
+        assert!(verify("abc", expires, &sig));
+    }
+
+    #[test]
+    fn signed_urls_last_at_least_a_day() {
+        let (expires, _) = query(&sign_url("/media/abc/cat.png", "abc"));
+        let now = Utc::now().timestamp();
+
+        assert!(expires >= now + SIGNED_URL_VALIDITY);
+        assert!(expires <= now + SIGNED_URL_VALIDITY + SIGNED_URL_GRANULARITY);
+        assert_eq!(expires % SIGNED_URL_GRANULARITY, 0);
+    }
+
+    #[test]
+    fn keeps_existing_query_parameters() {
+        let url = sign_url("/media/abc/cat.png?size=64", "abc");
+
+        assert!(url.starts_with("/media/abc/cat.png?size=64&expires="));
+    }
+
+    #[test]
+    fn rejects_signatures_of_other_media() {
+        let (expires, sig) = query(&sign_url("/media/abc/cat.png", "abc"));
+
+        assert!(!verify("abd", expires, &sig));
+    }
+
+    #[test]
+    fn rejects_changed_expiry() {
+        let (expires, sig) = query(&sign_url("/media/abc/cat.png", "abc"));
+
+        assert!(!verify("abc", expires + SIGNED_URL_GRANULARITY, &sig));
+    }
+
+    #[test]
+    fn rejects_expired_signatures() {
+        let expires = Utc::now().timestamp() - 1;
+        let sig = signature("abc", expires);
+
+        assert!(!verify("abc", expires, &sig));
+    }
+
+    #[test]
+    fn rejects_malformed_signatures() {
+        let expires = Utc::now().timestamp() + SIGNED_URL_VALIDITY;
+
+        assert!(!verify("abc", expires, ""));
+        assert!(!verify("abc", expires, "not base64!"));
+        assert!(!verify("abc", expires, "c2lnbmF0dXJl"));
+    }
+}
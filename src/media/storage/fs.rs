@@ -0,0 +1,96 @@
+use std::io;
+
+use actix_files::NamedFile;
+use actix_web::{
+    http::header::{ContentDisposition, DispositionParam, DispositionType},
+    HttpRequest, HttpResponse,
+};
+use async_trait::async_trait;
+
+use super::{is_inline, MediaStorage, ServedFile};
+
+/// Keeps media in a directory on the local disk.
+pub struct FilesystemStorage {
+    root: String,
+}
+
+impl FilesystemStorage {
+    pub fn new(root: String) -> Self {
+        Self { root }
+    }
+
+    fn path(&self, key: &str) -> io::Result<String> {
+        // keys are made by us, but better safe than sorry
+        if key
+            .split('/')
+            .any(|part| part.is_empty() || part == "." || part == "..")
+        {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "invalid_key"));
+        }
+
+        Ok(format!("{}/{}", self.root, key))
+    }
+
+    async fn create_parent(&self, path: &str) -> io::Result<()> {
+        if let Some((parent, _)) = path.rsplit_once('/') {
+            tokio::fs::create_dir_all(parent).await?;
+        }
+
+        Ok(())
+    }
+}
+
+#[async_trait(?Send)]
+impl MediaStorage for FilesystemStorage {
+    async fn store_file(&self, key: &str, local_path: &str) -> io::Result<()> {
+        let path = self.path(key)?;
+        self.create_parent(&path).await?;
+
+        // the spool directory is inside the media directory, so this is cheap
+        tokio::fs::rename(local_path, path).await
+    }
+
+    async fn store(&self, key: &str, data: Vec<u8>) -> io::Result<()> {
+        let path = self.path(key)?;
+        self.create_parent(&path).await?;
+
+        tokio::fs::write(path, data).await
+    }
+
+    async fn read(&self, key: &str) -> io::Result<Vec<u8>> {
+        tokio::fs::read(self.path(key)?).await
+    }
+
+    async fn exists(&self, key: &str) -> io::Result<bool> {
+        tokio::fs::try_exists(self.path(key)?).await
+    }
+
+    async fn delete(&self, key: &str) -> io::Result<()> {
+        tokio::fs::remove_file(self.path(key)?).await
+    }
+
+    async fn serve(&self, req: &HttpRequest, file: &ServedFile<'_>) -> io::Result<HttpResponse> {
+        let opened = std::fs::File::open(self.path(file.key)?)?;
+
+        let mut named = NamedFile::from_file(opened, file.filename)?.use_etag(file.etag.is_none());
+
+        if let Some(content_type) = file.content_type.and_then(|c| c.parse().ok()) {
+            named = named.set_content_type(content_type);
+        }
+
+        // the type has been guessed from the name by now if we didn't know it
+        let disposition = if is_inline(named.content_type().essence_str()) {
+            DispositionType::Inline
+        } else {
+            DispositionType::Attachment
+        };
+
+        let named = named.set_content_disposition(ContentDisposition {
+            disposition,
+            parameters: vec![DispositionParam::Filename(file.filename.to_string())],
+        });
+
+        // NamedFile takes care of range requests
+        Ok(named.into_response(req))
+    }
+}
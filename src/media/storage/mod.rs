@@ -0,0 +1,87 @@
+use std::{io, sync::Arc};
+
+use actix_web::{web::Data, HttpRequest, HttpResponse};
+use async_trait::async_trait;
+
+use crate::options::{self, StorageBackend};
+
+pub mod fs;
+pub mod object_store;
+
+pub type Storage = Data<dyn MediaStorage>;
+
+/// Somewhere uploaded media is kept. Files are identified by a key, which is
+/// always generated by the server and looks like a relative file path, eg.
+/// `s6NIiu2oOh1FEL0Xfjc7n_cat.jpg` or `thumbnails/s6NIiu2oOh1FEL0Xfjc7n_thumbnail_256.jpg`.
+///
+/// Missing files are reported as [`io::ErrorKind::NotFound`].
+// not `Send`, as serving a file needs the request, which can't leave the
+// thread it arrived on
+#[async_trait(?Send)]
+pub trait MediaStorage: Send + Sync {
+    /// Stores a file that has been written to the local disk, removing the
+    /// local copy.
+    async fn store_file(&self, key: &str, local_path: &str) -> io::Result<()>;
+
+    async fn store(&self, key: &str, data: Vec<u8>) -> io::Result<()>;
+
+    async fn read(&self, key: &str) -> io::Result<Vec<u8>>;
+
+    async fn exists(&self, key: &str) -> io::Result<bool>;
+
+    async fn delete(&self, key: &str) -> io::Result<()>;
+
+    /// Responds to a request to download a file, either by sending it directly
+    /// or by redirecting to somewhere it can be downloaded from. Range
+    /// requests should be supported.
+    async fn serve(&self, req: &HttpRequest, file: &ServedFile<'_>) -> io::Result<HttpResponse>;
+}
+
+/// A file being downloaded
+pub struct ServedFile<'a> {
+    pub key: &'a str,
+    /// The name the file is downloaded as
+    pub filename: &'a str,
+    /// `None` if the type isn't known, in which case it may be guessed from the
+    /// file name
+    pub content_type: Option<&'a str>,
+    pub size: Option<u64>,
+    /// When set, the storage shouldn't send an ETag of its own
+    pub etag: Option<&'a str>,
+    /// For storages that redirect downloads elsewhere, the Cache-Control the
+    /// file should be sent with there
+    pub cache_control: &'a str,
+}
+
+/// Whether a file of a certain type can be shown in the browser. Anything
+/// that isn't plain media, like HTML or SVG, could run scripts on our origin
+/// and must be downloaded instead.
+pub fn is_inline(content_type: &str) -> bool {
+    let Ok(mime) = content_type.parse::<mime::Mime>() else {
+        return false;
+    };
+
+    match mime.type_() {
+        mime::IMAGE => mime.subtype() != mime::SVG,
+        mime::VIDEO | mime::AUDIO => true,
+        _ => false,
+    }
+}
+
+/// Creates the storage backend configured with `MEDIA_STORAGE`.
+pub fn from_options() -> Data<dyn MediaStorage> {
+    let storage: Arc<dyn MediaStorage> = match *options::MEDIA_STORAGE {
+        StorageBackend::Filesystem => {
+            Arc::new(fs::FilesystemStorage::new(options::MEDIA_PATH.clone()))
+        }
+        StorageBackend::S3 => Arc::new(object_store::S3Storage::from_options()),
+    };
+
+    Data::from(storage)
+}
+
+/// Directory where uploads are written to before they are handed to the
+/// storage backend.
+pub fn spool_dir() -> String {
+    format!("{}/uploads", *options::MEDIA_PATH)
+}
@@ -0,0 +1,250 @@
+use std::{collections::HashMap, io};
+
+use actix_files::HttpRange;
+use actix_web::{
+    http::header::{self, ContentDisposition, DispositionParam, DispositionType},
+    HttpRequest, HttpResponse,
+};
+use async_trait::async_trait;
+use futures::StreamExt;
+use log::error;
+use s3::{
+    command::Command,
+    creds::Credentials,
+    error::S3Error,
+    request::{tokio_backend::Reqwest as S3Request, Request},
+    Bucket, Region,
+};
+
+use crate::options;
+
+use super::{is_inline, MediaStorage, ServedFile};
+
+/// Keeps media in an S3 compatible object store, such as AWS S3 or MinIO.
+pub struct S3Storage {
+    bucket: Bucket,
+    /// Redirect downloads to pre-signed URLs instead of sending the file
+    /// through this server
+    presign: bool,
+}
+
+impl S3Storage {
+    pub fn from_options() -> Self {
+        let region = Region::Custom {
+            region: options::S3_REGION.clone(),
+            endpoint: options::S3_ENDPOINT.clone(),
+        };
+
+        let credentials = Credentials::new(
+            Some(options::S3_ACCESS_KEY.as_str()),
+            Some(options::S3_SECRET_KEY.as_str()),
+            None,
+            None,
+            None,
+        )
+        .unwrap_or_else(|e| {
+            error!("Invalid S3 credentials: {}", e);
+            std::process::exit(1);
+        });
+
+        let bucket =
+            Bucket::new(options::S3_BUCKET.as_str(), region, credentials).unwrap_or_else(|e| {
+                error!("Invalid S3 bucket configuration: {}", e);
+                std::process::exit(1);
+            });
+
+        // most self hosted object stores only support path style urls
+        let bucket = if *options::S3_PATH_STYLE {
+            bucket.with_path_style()
+        } else {
+            bucket
+        };
+
+        Self {
+            bucket,
+            presign: *options::S3_PRESIGN_DOWNLOADS,
+        }
+    }
+}
+
+fn to_io_error(e: S3Error) -> io::Error {
+    io::Error::new(io::ErrorKind::Other, e.to_string())
+}
+
+/// Turns an unsuccessful status code into an error
+fn check_status(code: u16) -> io::Result<()> {
+    match code {
+        200..=299 => Ok(()),
+        404 => Err(io::ErrorKind::NotFound.into()),
+        _ => Err(io::Error::new(
+            io::ErrorKind::Other,
+            format!("s3 responded with status {}", code),
+        )),
+    }
+}
+
+#[async_trait(?Send)]
+impl MediaStorage for S3Storage {
+    async fn store_file(&self, key: &str, local_path: &str) -> io::Result<()> {
+        let mut file = tokio::fs::File::open(local_path).await?;
+
+        let code = self
+            .bucket
+            .put_object_stream(&mut file, key)
+            .await
+            .map_err(to_io_error)?;
+        check_status(code)?;
+
+        tokio::fs::remove_file(local_path).await
+    }
+
+    async fn store(&self, key: &str, data: Vec<u8>) -> io::Result<()> {
+        let response = self
+            .bucket
+            .put_object(key, &data)
+            .await
+            .map_err(to_io_error)?;
+
+        check_status(response.status_code())
+    }
+
+    async fn read(&self, key: &str) -> io::Result<Vec<u8>> {
+        let response = self.bucket.get_object(key).await.map_err(to_io_error)?;
+        check_status(response.status_code())?;
+
+        Ok(response.bytes().to_vec())
+    }
+
+    async fn exists(&self, key: &str) -> io::Result<bool> {
+        let (_, code) = self.bucket.head_object(key).await.map_err(to_io_error)?;
+
+        match check_status(code) {
+            Ok(()) => Ok(true),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(false),
+            Err(e) => Err(e),
+        }
+    }
+
+    async fn delete(&self, key: &str) -> io::Result<()> {
+        let response = self.bucket.delete_object(key).await.map_err(to_io_error)?;
+
+        check_status(response.status_code())
+    }
+
+    async fn serve(&self, req: &HttpRequest, file: &ServedFile<'_>) -> io::Result<HttpResponse> {
+        let content_type = match file.content_type {
+            Some(content_type) => content_type.to_string(),
+            None => guess_content_type(file.filename),
+        };
+
+        let disposition = if is_inline(&content_type) {
+            DispositionType::Inline
+        } else {
+            DispositionType::Attachment
+        };
+        let disposition = ContentDisposition {
+            disposition,
+            parameters: vec![DispositionParam::Filename(file.filename.to_string())],
+        };
+
+        if self.presign {
+            if !self.exists(file.key).await? {
+                return Err(io::ErrorKind::NotFound.into());
+            }
+
+            // objects are stored without metadata, so have the store send the
+            // right headers
+            let queries = HashMap::from([
+                ("response-content-type".to_string(), content_type),
+                (
+                    "response-content-disposition".to_string(),
+                    disposition.to_string(),
+                ),
+                (
+                    "response-cache-control".to_string(),
+                    file.cache_control.to_string(),
+                ),
+            ]);
+
+            let url = self
+                .bucket
+                .presign_get(file.key, *options::S3_PRESIGN_EXPIRY, Some(queries))
+                .map_err(to_io_error)?;
+
+            return Ok(HttpResponse::Found()
+                .insert_header((header::LOCATION, url))
+                .finish());
+        }
+
+        let range = req
+            .headers()
+            .get(header::RANGE)
+            .and_then(|range| range.to_str().ok());
+
+        // only single ranges are supported, which is what browsers use to seek
+        // in videos. anything else gets the whole file.
+        if let (Some(range), Some(size)) = (range, file.size) {
+            let Ok(ranges) = HttpRange::parse(range, size) else {
+                return Ok(HttpResponse::RangeNotSatisfiable()
+                    .insert_header((header::CONTENT_RANGE, format!("bytes */{}", size)))
+                    .finish());
+            };
+
+            if let [range] = ranges.as_slice() {
+                let end = range.start + range.length - 1;
+
+                // the bucket can only stream whole objects, so the ranged
+                // request is made the same way it makes its own
+                let command = Command::GetObjectRange {
+                    start: range.start,
+                    end: Some(end),
+                };
+                let response = S3Request::new(&self.bucket, file.key, command)
+                    .map_err(to_io_error)?
+                    .response_data_to_stream()
+                    .await
+                    .map_err(to_io_error)?;
+                check_status(response.status_code)?;
+
+                return Ok(HttpResponse::PartialContent()
+                    .content_type(content_type)
+                    .insert_header(disposition)
+                    .insert_header((header::ACCEPT_RANGES, "bytes"))
+                    .insert_header((
+                        header::CONTENT_RANGE,
+                        format!("bytes {}-{}/{}", range.start, end, size),
+                    ))
+                    .no_chunking(range.length)
+                    .streaming(response.bytes.map(Ok::<_, io::Error>)));
+            }
+        }
+
+        // stream the object through instead of holding all of it in memory
+        let response = self
+            .bucket
+            .get_object_stream(file.key)
+            .await
+            .map_err(to_io_error)?;
+        check_status(response.status_code)?;
+
+        let mut builder = HttpResponse::Ok();
+        builder
+            .content_type(content_type)
+            .insert_header(disposition)
+            .insert_header((header::ACCEPT_RANGES, "bytes"));
+
+        if let Some(size) = file.size {
+            builder.no_chunking(size);
+        }
+
+        Ok(builder.streaming(response.bytes.map(Ok::<_, io::Error>)))
+    }
+}
+
+/// Guesses the type of a file from its extension, like the filesystem storage
+/// does
+fn guess_content_type(filename: &str) -> String {
+    let extension = filename.rsplit_once('.').map_or("", |(_, ext)| ext);
+
+    actix_files::file_extension_to_mime(extension).to_string()
+}
@@ -2,7 +2,9 @@ use chrono::{DateTime, Utc};
 use serde::Serialize;
 use utoipa::ToSchema;
 
-use crate::{auth::user::PublicUserInfo, media::routes::upload::UploadedFileInfo};
+use crate::{
+    auth::user::PublicUserInfo, media::routes::upload::UploadedFileInfo, messaging::unfurl::Embed,
+};
 
 #[derive(Serialize, ToSchema)]
 #[serde(rename_all = "camelCase")]
@@ -14,26 +16,17 @@ pub struct Message {
     pub content: Option<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub attachments: Option<Vec<UploadedFileInfo>>,
+    /// Previews of links in the content. These are added shortly after the
+    /// message is sent, with a `messageUpdate` event.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub embeds: Option<Vec<Embed>>,
     pub created_at: DateTime<Utc>,
     pub author: PublicUserInfo,
+    /// Set when the user reading this message has blocked its author. Clients
+    /// should hide the content behind a "blocked message" notice.
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub author_blocked: bool,
 }
 
 // TODO: add nicknames
 // pub type MessageAuthor = PublicUserInfo;
-
-// #[derive(Serialize, Deserialize)]
-// #[serde(tag = "type", rename_all = "camelCase")]
-// pub enum Attachment {
-//     Image {
-//         url: String,
-//         #[serde(skip_serializing_if = "Option::is_none")]
-//         thumbnail_url: Option<String>,
-//         // width: usize,
-//         // height: usize
-//     },
-//     Blob {
-//         url: String,
-//         filename: String,
-//         size: usize
-//     }
-// }
@@ -1,2 +1,3 @@
 pub mod message;
 pub mod routes;
+pub mod unfurl;
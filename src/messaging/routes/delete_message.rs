@@ -63,7 +63,8 @@ pub async fn delete_message(
             }
         }
 
-        // delete the message from the db
+        // delete the message from the db, its attachments are no longer
+        // referenced by it and will be garbage collected if nothing else uses them
         sqlx::query!("DELETE FROM messages WHERE id = $1", message.id)
             .execute(&db.pool)
             .await?;
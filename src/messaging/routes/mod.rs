@@ -4,7 +4,7 @@ use crate::auth::user::PublicUserInfo;
 
 use self::send_message::{SendMessageRequest, SendMessageResponse};
 
-use super::message::Message;
+use super::{message::Message, unfurl::Embed};
 
 pub mod delete_message;
 pub mod edit_message;
@@ -31,7 +31,7 @@ pub fn configure_app(cfg: &mut actix_web::web::ServiceConfig) {
         typing::typing
     ),
     components(schemas(
-        Message, SendMessageResponse, SendMessageRequest, PublicUserInfo
+        Message, Embed, SendMessageResponse, SendMessageRequest, PublicUserInfo
     ))
 )]
 pub struct MessagingApiDocs;
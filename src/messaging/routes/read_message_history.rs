@@ -11,7 +11,8 @@ use crate::{
     auth::{access_token::AccessToken, user::PublicUserInfo},
     db::DB,
     error::{macros::err, HResult},
-    messaging::message::Message,
+    media::signing,
+    messaging::{message::Message, unfurl},
 };
 
 #[derive(Deserialize, IntoParams)]
@@ -81,13 +82,16 @@ async fn read_message_history(
             messages.content, 
             messages.created_at,
             messages.attachments,
+            messages.embeds,
             users.name AS "author_username",
             users.avatar AS "author_avatar",
-            users.id AS "author_id"
-        FROM messages, users 
+            users.id AS "author_id",
+            blocks.blocked IS NOT NULL AS "author_blocked!"
+        FROM messages
+        INNER JOIN users ON users.id = messages.user_id
+        LEFT JOIN blocks ON blocks.blocker = $5 AND blocks.blocked = messages.user_id
         WHERE (
             messages.channel_id = $1 
-            AND users.id = messages.user_id
             AND messages.created_at < $3
             AND messages.created_at > $4
         )
@@ -99,7 +103,8 @@ async fn read_message_history(
         req.before.unwrap_or(Utc::now()).naive_utc(),
         req.after
             .unwrap_or_default() // unix epoch
-            .naive_utc()
+            .naive_utc(),
+        token.user_id
     )
     .fetch_all(&db.pool)
     .await?;
@@ -108,21 +113,20 @@ async fn read_message_history(
         .iter()
         .rev()
         .map(|record| {
-            let attachments = match record.attachments.clone() {
-                Some(some) => serde_json::from_value(some).ok(),
-                None => None,
-            };
+            let attachments = signing::attachments_from_json(record.attachments.clone());
 
             Message {
                 id: record.id.clone(),
                 content: record.content.clone(),
                 attachments,
+                embeds: unfurl::embeds_from_json(record.embeds.clone()),
                 created_at: DateTime::<Utc>::from_naive_utc_and_offset(record.created_at, Utc),
                 author: PublicUserInfo {
                     id: record.author_id.clone(),
                     username: record.author_username.clone(),
                     avatar: record.author_avatar.clone(),
                 },
+                author_blocked: record.author_blocked,
             }
         })
         .collect();
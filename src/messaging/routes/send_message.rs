@@ -13,8 +13,11 @@ use crate::{
     auth::user::User,
     db::DB,
     error::{macros::err, HResult},
-    media::routes::upload::UploadedFileInfo,
-    messaging::message::Message,
+    media::{attachments::resolve_attachments, signing::sign_file_info},
+    messaging::{
+        message::Message,
+        unfurl::{unfurl_message, MessageChannel, Unfurler},
+    },
     realtime::pubsub::pubsub::PubSub,
 };
 
@@ -22,7 +25,9 @@ use crate::{
 pub struct SendMessageRequest {
     #[schema(example = "Hello from the API tester!")]
     content: Option<String>,
-    attachments: Option<Vec<UploadedFileInfo>>,
+    /// IDs of files you uploaded through the media API
+    #[schema(example = json!(["s6NIiu2oOh1FEL0Xfjc7n"]))]
+    attachments: Option<Vec<String>>,
 }
 
 #[derive(Serialize, ToSchema)]
@@ -41,6 +46,8 @@ struct SendMessagePath {
 /// Send message
 ///
 /// Sends a message with text `content` and with optional attachments.
+/// Previews of links in the content are made in the background and arrive
+/// with a `messageUpdate` event.
 #[utoipa::path(
     tag = "messaging",
     security(("token" = [])),
@@ -48,7 +55,7 @@ struct SendMessagePath {
     responses(
         (status = OK, description = "Message sent", body = SendMessageResponse),
         (status = FORBIDDEN, description = "No permission to send message in channel"),
-        (status = BAD_REQUEST, description = "Invalid message (content_too_long, missing_content)")
+        (status = BAD_REQUEST, description = "Invalid message (content_too_long, missing_content, unknown attachment)")
     )
 )]
 #[post("/channels/{channel_id}/messages")]
@@ -58,6 +65,7 @@ async fn send_message(
     req: Json<SendMessageRequest>,
     path: Path<SendMessagePath>,
     pubsub: Data<PubSub>,
+    unfurler: Data<Unfurler>,
 ) -> HResult<Json<SendMessageResponse>> {
     // get inner value
     let req = req.0;
@@ -88,8 +96,17 @@ async fn send_message(
         err!(403)?;
     }
 
-    // serialize attachments list back to json
+    // look up the attachments, making sure they were uploaded by the sender
     let attachments = match req.attachments {
+        Some(ref ids) if !ids.is_empty() => Some(resolve_attachments(&db, &user.id, ids).await?),
+        _ => None,
+    };
+
+    // remember which uploads are in use, so they don't get garbage collected
+    let attachment_ids: Vec<String> = attachments.iter().flatten().map(|a| a.id.clone()).collect();
+
+    // serialize attachments list to json
+    let attachments_json = match attachments {
         Some(ref atts) => serde_json::to_value(atts).unwrap(),
         None => Value::Null,
     };
@@ -103,7 +120,11 @@ async fn send_message(
                 ($1, $2, $3, $4, $5) 
             RETURNING 
                 id, created_at
-        ) 
+        ),
+        attached AS (
+            INSERT INTO message_attachments (message_id, media_id)
+            SELECT $1, UNNEST($6::text[])
+        )
         SELECT 
             message.id, 
             message.created_at, 
@@ -118,7 +139,8 @@ async fn send_message(
         path.channel_id,
         user.id,
         req.content,
-        attachments
+        attachments_json,
+        &attachment_ids
     )
     .fetch_one(&db.pool)
     .await?;
@@ -126,18 +148,28 @@ async fn send_message(
     let message = Message {
         id: record.id.clone(),
         content: if is_content_empty { None } else { req.content },
-        attachments: if are_attachments_empty {
-            None
-        } else {
-            req.attachments
-        },
+        attachments: attachments.map(|atts| atts.into_iter().map(sign_file_info).collect()),
+        embeds: None,
         author: user.into(),
+        author_blocked: false,
         created_at: DateTime::<Utc>::from_naive_utc_and_offset(record.created_at, Utc),
     };
 
     // tell people listening to this channel that there's a new message
     pubsub.notify_new_message(&path.channel_id, &message).await;
 
+    // previews of links are sent once they're ready
+    if let Some(ref content) = message.content {
+        unfurl_message(
+            unfurler,
+            db,
+            pubsub,
+            MessageChannel::Guild(path.channel_id.clone()),
+            message.id.clone(),
+            content,
+        );
+    }
+
     Ok(Json(SendMessageResponse {
         id: message.id,
         created_at: message.created_at,
@@ -0,0 +1,176 @@
+use std::{net::IpAddr, sync::Arc, time::Duration};
+
+use hyper::client::connect::dns::Name;
+use reqwest::{
+    dns::{Addrs, Resolve, Resolving},
+    header, redirect, Client, Response, Url,
+};
+
+use crate::{options, security::is_public_ip};
+
+const MAX_REDIRECTS: usize = 5;
+
+// some sites only send their opengraph tags to things that look like browsers
+// or known bots
+const USER_AGENT: &str = concat!(
+    "Mozilla/5.0 (compatible; Zlingbot/",
+    env!("CARGO_PKG_VERSION"),
+    "; link previews)"
+);
+
+/// A page or file that was fetched
+pub struct Fetched {
+    /// Where the content ended up being fetched from, after redirects
+    pub url: Url,
+    pub content_type: Option<String>,
+    /// Only the first `LINK_PREVIEW_MAX_SIZE` bytes of the body
+    pub body: Vec<u8>,
+}
+
+/// Which addresses may be connected to
+pub type AddressFilter = fn(IpAddr) -> bool;
+
+/// Fetches pages and files, refusing to connect to any address the filter
+/// doesn't allow.
+pub struct Fetcher {
+    client: Client,
+    allow: AddressFilter,
+}
+
+impl Fetcher {
+    /// Only connects to the public internet
+    pub fn new() -> Self {
+        Self::with_address_filter(is_public_ip)
+    }
+
+    pub fn with_address_filter(allow: AddressFilter) -> Self {
+        let client = Client::builder()
+            .user_agent(USER_AGENT)
+            .timeout(Duration::from_secs(*options::LINK_PREVIEW_TIMEOUT))
+            .redirect(redirect::Policy::custom(move |attempt| {
+                if attempt.previous().len() >= MAX_REDIRECTS {
+                    attempt.error("too many redirects")
+                } else if !is_allowed_url(attempt.url(), allow) {
+                    attempt.stop()
+                } else {
+                    attempt.follow()
+                }
+            }))
+            // a proxy would do the dns lookups instead of our resolver
+            .no_proxy()
+            .dns_resolver(Arc::new(FilteringResolver { allow }))
+            .build()
+            .expect("link preview http client should build");
+
+        Self { client, allow }
+    }
+
+    /// Fetches a url, reading at most `LINK_PREVIEW_MAX_SIZE` bytes of it.
+    /// Bodies of types that `wants_body` says no to aren't downloaded at all.
+    pub async fn fetch(
+        &self,
+        url: Url,
+        wants_body: impl Fn(Option<&str>) -> bool,
+    ) -> Option<Fetched> {
+        if !is_allowed_url(&url, self.allow) {
+            return None;
+        }
+
+        let mut response = self.client.get(url).send().await.ok()?;
+
+        if !response.status().is_success() {
+            return None;
+        }
+
+        let content_type = content_type(&response);
+        let url = response.url().clone();
+
+        let body = if wants_body(content_type.as_deref()) {
+            read_limited(&mut response, *options::LINK_PREVIEW_MAX_SIZE).await?
+        } else {
+            vec![]
+        };
+
+        Some(Fetched {
+            url,
+            content_type,
+            body,
+        })
+    }
+}
+
+/// Whether a url may be fetched. Hostnames are checked when they're resolved,
+/// but addresses written directly in the url never get resolved, so they're
+/// checked here.
+fn is_allowed_url(url: &Url, allow: AddressFilter) -> bool {
+    if url.scheme() != "http" && url.scheme() != "https" {
+        return false;
+    }
+
+    let Some(host) = url.host_str() else {
+        return false;
+    };
+
+    // ipv6 hosts are in brackets
+    let host = host.trim_start_matches('[').trim_end_matches(']');
+
+    match host.parse::<IpAddr>() {
+        Ok(ip) => allow(ip),
+        Err(_) => true,
+    }
+}
+
+/// The type of the response without any parameters like the charset
+fn content_type(response: &Response) -> Option<String> {
+    let value = response
+        .headers()
+        .get(header::CONTENT_TYPE)?
+        .to_str()
+        .ok()?;
+    let essence = value.split(';').next()?.trim().to_ascii_lowercase();
+
+    Some(essence)
+}
+
+/// Reads the body up to a limit, anything past it is ignored
+async fn read_limited(response: &mut Response, limit: usize) -> Option<Vec<u8>> {
+    let mut body = Vec::new();
+
+    while let Some(chunk) = response.chunk().await.ok()? {
+        let remaining = limit - body.len();
+        body.extend_from_slice(&chunk[..chunk.len().min(remaining)]);
+
+        if body.len() >= limit {
+            break;
+        }
+    }
+
+    Some(body)
+}
+
+/// Resolves hostnames, leaving out any addresses that aren't allowed. Doing
+/// this in the resolver means the address that gets checked is the same one
+/// that gets connected to, so a hostname can't resolve to something public
+/// when it's checked and something private when it's fetched.
+struct FilteringResolver {
+    allow: AddressFilter,
+}
+
+impl Resolve for FilteringResolver {
+    fn resolve(&self, name: Name) -> Resolving {
+        let allow = self.allow;
+
+        Box::pin(async move {
+            let addrs: Vec<_> = tokio::net::lookup_host((name.as_str(), 0))
+                .await?
+                .filter(|addr| allow(addr.ip()))
+                .collect();
+
+            if addrs.is_empty() {
+                return Err(format!("{} has no allowed addresses", name.as_str()).into());
+            }
+
+            Ok(Box::new(addrs.into_iter()) as Addrs)
+        })
+    }
+}
@@ -0,0 +1,239 @@
+use std::collections::HashMap;
+
+use lazy_static::lazy_static;
+use regex::Regex;
+
+lazy_static! {
+    static ref TAG_REGEX: Regex = Regex::new(r"(?is)<(meta|link)\b([^>]*)>").unwrap();
+    static ref ATTRIBUTE_REGEX: Regex =
+        Regex::new(r#"(?is)([a-z:_-]+)\s*=\s*(?:"([^"]*)"|'([^']*)'|([^\s"'>]+))"#).unwrap();
+    static ref TITLE_REGEX: Regex = Regex::new(r"(?is)<title[^>]*>(.*?)</title>").unwrap();
+    static ref NUMERIC_ENTITY_REGEX: Regex =
+        Regex::new(r"&#(?:[xX]([0-9a-fA-F]+)|([0-9]+));").unwrap();
+}
+
+/// What a page says about itself in its `<head>`, through OpenGraph, Twitter
+/// cards or plain html
+#[derive(Default)]
+pub struct PageInfo {
+    pub title: Option<String>,
+    pub description: Option<String>,
+    pub site_name: Option<String>,
+    pub author: Option<String>,
+    /// Possibly relative to the page
+    pub image: Option<String>,
+    /// Where to find the page's oEmbed information, possibly relative to the
+    /// page
+    pub oembed: Option<String>,
+}
+
+impl PageInfo {
+    pub fn is_empty(&self) -> bool {
+        self.title.is_none() && self.description.is_none() && self.image.is_none()
+    }
+}
+
+/// Picks the preview information out of a page. This isn't a real html
+/// parser, but meta tags are simple enough that it doesn't need to be.
+pub fn parse(html: &str) -> PageInfo {
+    // everything we're interested in is in the head
+    let head = match html.find("</head") {
+        Some(end) => &html[..end],
+        None => html,
+    };
+
+    // first one wins, like it does for browsers
+    let mut meta = HashMap::new();
+    let mut oembed = None;
+
+    for tag in TAG_REGEX.captures_iter(head) {
+        let attributes = attributes(&tag[2]);
+
+        if tag[1].eq_ignore_ascii_case("link") {
+            let is_oembed = attributes
+                .get("type")
+                .is_some_and(|t| t.eq_ignore_ascii_case("application/json+oembed"));
+
+            if is_oembed && oembed.is_none() {
+                oembed = attributes.get("href").cloned();
+            }
+
+            continue;
+        }
+
+        let key = attributes.get("property").or(attributes.get("name"));
+        if let (Some(key), Some(content)) = (key, attributes.get("content")) {
+            meta.entry(key.to_ascii_lowercase())
+                .or_insert_with(|| content.clone());
+        }
+    }
+
+    let get = |keys: &[&str]| {
+        keys.iter()
+            .find_map(|key| meta.get(*key))
+            .map(|value| decode_entities(value.trim()))
+            .filter(|value| !value.is_empty())
+    };
+
+    let title = get(&["og:title", "twitter:title"]).or_else(|| {
+        TITLE_REGEX
+            .captures(head)
+            .map(|title| decode_entities(title[1].trim()))
+            .filter(|title| !title.is_empty())
+    });
+
+    PageInfo {
+        title,
+        description: get(&["og:description", "twitter:description", "description"]),
+        site_name: get(&["og:site_name", "application-name"]),
+        author: get(&["author", "article:author"]),
+        image: get(&[
+            "og:image:secure_url",
+            "og:image:url",
+            "og:image",
+            "twitter:image",
+            "twitter:image:src",
+        ]),
+        oembed: oembed.map(|href| decode_entities(href.trim())),
+    }
+}
+
+fn attributes(tag: &str) -> HashMap<String, String> {
+    ATTRIBUTE_REGEX
+        .captures_iter(tag)
+        .map(|attribute| {
+            let value = attribute
+                .get(2)
+                .or(attribute.get(3))
+                .or(attribute.get(4))
+                .map_or("", |v| v.as_str());
+
+            (attribute[1].to_ascii_lowercase(), value.to_string())
+        })
+        .collect()
+}
+
+/// Decodes the entities that commonly show up in titles and descriptions
+fn decode_entities(text: &str) -> String {
+    let text = NUMERIC_ENTITY_REGEX.replace_all(text, |entity: &regex::Captures| {
+        let code = match entity.get(1) {
+            Some(hex) => u32::from_str_radix(hex.as_str(), 16),
+            None => entity[2].parse(),
+        };
+
+        code.ok()
+            .and_then(char::from_u32)
+            .map_or(String::new(), String::from)
+    });
+
+    text.replace("&quot;", "\"")
+        .replace("&apos;", "'")
+        .replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&nbsp;", " ")
+        // last, so that "&amp;lt;" becomes "&lt;" and not "<"
+        .replace("&amp;", "&")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::parse;
+
+    #[test]
+    fn parses_opengraph() {
+        let info = parse(
+            r#"<head>
+                <meta property="og:title" content="Cats">
+                <meta property="og:description" content="Pictures of cats">
+                <meta property="og:site_name" content="Example">
+                <meta property="og:image" content="https://example.com/cat.jpg">
+                <meta name="author" content="Someone">
+            </head>"#,
+        );
+
+        assert_eq!(info.title.as_deref(), Some("Cats"));
+        assert_eq!(info.description.as_deref(), Some("Pictures of cats"));
+        assert_eq!(info.site_name.as_deref(), Some("Example"));
+        assert_eq!(info.image.as_deref(), Some("https://example.com/cat.jpg"));
+        assert_eq!(info.author.as_deref(), Some("Someone"));
+    }
+
+    #[test]
+    fn prefers_opengraph_over_twitter_and_plain_html() {
+        let info = parse(
+            r#"<head>
+                <title>Plain</title>
+                <meta name="description" content="Plain description">
+                <meta name="twitter:title" content="Twitter">
+                <meta property="og:title" content="OpenGraph">
+                <meta name="twitter:description" content="Twitter description">
+            </head>"#,
+        );
+
+        assert_eq!(info.title.as_deref(), Some("OpenGraph"));
+        assert_eq!(info.description.as_deref(), Some("Twitter description"));
+    }
+
+    #[test]
+    fn falls_back_to_title_tag() {
+        let info = parse("<head><title>  Just a title </title></head>");
+
+        assert_eq!(info.title.as_deref(), Some("Just a title"));
+        assert!(!info.is_empty());
+    }
+
+    #[test]
+    fn first_tag_wins() {
+        let info = parse(
+            r#"<meta property="og:title" content="First">
+            <meta property="og:title" content="Second">"#,
+        );
+
+        assert_eq!(info.title.as_deref(), Some("First"));
+    }
+
+    #[test]
+    fn handles_attribute_quoting_and_case() {
+        let info = parse(
+            r#"<META CONTENT='Single quoted' PROPERTY="og:title">
+            <meta property=og:description content=unquoted>"#,
+        );
+
+        assert_eq!(info.title.as_deref(), Some("Single quoted"));
+        assert_eq!(info.description.as_deref(), Some("unquoted"));
+    }
+
+    #[test]
+    fn decodes_entities() {
+        let info = parse(
+            r#"<meta property="og:title" content="Tom &amp; Jerry &#39;s &#x263A; &amp;lt;">"#,
+        );
+
+        assert_eq!(info.title.as_deref(), Some("Tom & Jerry 's \u{263a} &lt;"));
+    }
+
+    #[test]
+    fn ignores_tags_after_head() {
+        let info =
+            parse(r#"<head></head><body><meta property="og:title" content="In the body"></body>"#);
+
+        assert!(info.is_empty());
+    }
+
+    #[test]
+    fn finds_oembed_link() {
+        let info = parse(
+            r#"<link rel="alternate" type="text/xml+oembed" href="/oembed.xml">
+            <link rel="alternate" type="application/json+oembed" href="/oembed?url=a&amp;format=json">"#,
+        );
+
+        assert_eq!(info.oembed.as_deref(), Some("/oembed?url=a&format=json"));
+    }
+
+    #[test]
+    fn ignores_empty_values() {
+        let info = parse(r#"<meta property="og:title" content="   "><title>Fallback</title>"#);
+
+        assert_eq!(info.title.as_deref(), Some("Fallback"));
+    }
+}
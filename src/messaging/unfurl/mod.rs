@@ -0,0 +1,299 @@
+use std::time::Duration;
+
+use actix_web::web::Data;
+use futures::future::join_all;
+use lazy_static::lazy_static;
+use log::warn;
+use regex::Regex;
+use reqwest::Url;
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use crate::{db::DB, friends::dmchannel::DMChannel, options, realtime::pubsub::pubsub::PubSub};
+
+use self::fetch::Fetcher;
+
+mod fetch;
+mod html;
+#[cfg(test)]
+mod tests;
+
+/// Most links that are previewed in one message
+pub const MAX_EMBEDS: usize = 5;
+const MAX_URL_LENGTH: usize = 2048;
+const MAX_TITLE_LENGTH: usize = 256;
+const MAX_DESCRIPTION_LENGTH: usize = 1024;
+
+lazy_static! {
+    static ref URL_REGEX: Regex = Regex::new(r"https?://[^\s<>]+").unwrap();
+}
+
+/// A preview of a link in a message
+#[derive(Serialize, Deserialize, ToSchema, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct Embed {
+    /// The link as it was written in the message
+    #[schema(example = "https://example.com/cats")]
+    pub url: String,
+    #[schema(example = "Cats")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub title: Option<String>,
+    #[schema(example = "Pictures of cats")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    #[schema(example = "Example")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub site_name: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub author_name: Option<String>,
+    /// An image to show with the preview. If the link itself points to an
+    /// image, this is where it ended up after redirects.
+    #[schema(example = "https://example.com/cat.jpg")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub image_url: Option<String>,
+}
+
+/// Where a message was sent, so its embeds can be announced to the right
+/// people
+pub enum MessageChannel {
+    Guild(String),
+    Dm(DMChannel),
+}
+
+/// Fetches link previews for messages.
+pub struct Unfurler {
+    fetcher: Fetcher,
+}
+
+#[derive(Deserialize, Default)]
+struct OEmbed {
+    title: Option<String>,
+    author_name: Option<String>,
+    provider_name: Option<String>,
+    thumbnail_url: Option<String>,
+}
+
+impl Unfurler {
+    pub fn new() -> Self {
+        Self {
+            fetcher: Fetcher::new(),
+        }
+    }
+
+    /// An unfurler that may connect to other addresses than public ones, for
+    /// fetching from a server on localhost
+    #[cfg(test)]
+    fn with_address_filter(allow: fetch::AddressFilter) -> Self {
+        Self {
+            fetcher: Fetcher::with_address_filter(allow),
+        }
+    }
+
+    /// Makes a preview for a link, or `None` if there is nothing to show or
+    /// fetching the link failed or took too long.
+    async fn unfurl(&self, url: &str) -> Option<Embed> {
+        let timeout = Duration::from_secs(*options::LINK_PREVIEW_TIMEOUT);
+
+        actix_rt::time::timeout(timeout, self.make_embed(url))
+            .await
+            .ok()
+            .flatten()
+    }
+
+    async fn make_embed(&self, url: &str) -> Option<Embed> {
+        let parsed = Url::parse(url).ok()?;
+
+        // images don't need to be downloaded, the client shows them directly
+        let page = self
+            .fetcher
+            .fetch(parsed, |content_type| content_type == Some("text/html"))
+            .await?;
+
+        let content_type = page.content_type.as_deref().unwrap_or_default();
+
+        if content_type.starts_with("image/") {
+            return Some(Embed {
+                url: url.to_string(),
+                title: None,
+                description: None,
+                site_name: None,
+                author_name: None,
+                image_url: Some(page.url.to_string()),
+            });
+        }
+
+        if content_type != "text/html" {
+            return None;
+        }
+
+        let info = html::parse(&String::from_utf8_lossy(&page.body));
+
+        // oEmbed fills in what the page itself doesn't say
+        let oembed = match info.oembed {
+            Some(ref oembed_url) => self.fetch_oembed(&page.url, oembed_url).await,
+            None => None,
+        };
+
+        if info.is_empty() && oembed.as_ref().map_or(true, |o| o.title.is_none()) {
+            return None;
+        }
+
+        let oembed = oembed.unwrap_or_default();
+
+        let image_url = info
+            .image
+            .or(oembed.thumbnail_url)
+            .and_then(|image| resolve(&page.url, &image));
+
+        Some(Embed {
+            url: url.to_string(),
+            title: info
+                .title
+                .or(oembed.title)
+                .map(|t| truncate(t, MAX_TITLE_LENGTH)),
+            description: info
+                .description
+                .map(|d| truncate(d, MAX_DESCRIPTION_LENGTH)),
+            site_name: info
+                .site_name
+                .or(oembed.provider_name)
+                .map(|s| truncate(s, MAX_TITLE_LENGTH)),
+            author_name: oembed
+                .author_name
+                .or(info.author)
+                .map(|a| truncate(a, MAX_TITLE_LENGTH)),
+            image_url,
+        })
+    }
+
+    async fn fetch_oembed(&self, page_url: &Url, oembed_url: &str) -> Option<OEmbed> {
+        let oembed_url = page_url.join(oembed_url).ok()?;
+        let response = self.fetcher.fetch(oembed_url, |_| true).await?;
+
+        serde_json::from_slice(&response.body).ok()
+    }
+}
+
+/// Finds the links in a message that should be previewed. Links wrapped in
+/// `<>` are left alone, so people can choose not to have a preview.
+pub fn extract_urls(content: &str) -> Vec<String> {
+    let mut urls: Vec<String> = vec![];
+
+    for found in URL_REGEX.find_iter(content) {
+        if content[..found.start()].ends_with('<') && content[found.end()..].starts_with('>') {
+            continue;
+        }
+
+        // punctuation right after a link is usually part of the sentence
+        let mut url = found
+            .as_str()
+            .trim_end_matches(['.', ',', ':', ';', '!', '?', '\'', '"']);
+        if url.ends_with(')') && !url.contains('(') {
+            url = &url[..url.len() - 1];
+        }
+
+        if url.len() > MAX_URL_LENGTH || urls.iter().any(|u| u == url) {
+            continue;
+        }
+
+        urls.push(url.to_string());
+
+        if urls.len() == MAX_EMBEDS {
+            break;
+        }
+    }
+
+    urls
+}
+
+/// Looks for links in a message that was just sent and previews them in the
+/// background. Once the previews are ready, they are saved with the message
+/// and everyone who can see it is told about them.
+pub fn unfurl_message(
+    unfurler: Data<Unfurler>,
+    db: DB,
+    pubsub: Data<PubSub>,
+    channel: MessageChannel,
+    message_id: String,
+    content: &str,
+) {
+    if !*options::LINK_PREVIEWS {
+        return;
+    }
+
+    let urls = extract_urls(content);
+    if urls.is_empty() {
+        return;
+    }
+
+    actix_rt::spawn(async move {
+        let embeds: Vec<Embed> = join_all(urls.iter().map(|url| unfurler.unfurl(url)))
+            .await
+            .into_iter()
+            .flatten()
+            .collect();
+
+        if embeds.is_empty() {
+            return;
+        }
+
+        let updated = sqlx::query!(
+            "UPDATE messages SET embeds = $2 WHERE id = $1",
+            message_id,
+            serde_json::to_value(&embeds).unwrap()
+        )
+        .execute(&db.pool)
+        .await;
+
+        match updated {
+            Ok(result) if result.rows_affected() > 0 => {}
+            // the message was deleted in the meantime
+            Ok(_) => return,
+            Err(e) => {
+                warn!("saving embeds of message `{}` failed: {}", message_id, e);
+                return;
+            }
+        }
+
+        match channel {
+            MessageChannel::Guild(channel_id) => {
+                pubsub
+                    .notify_message_update(&channel_id, &message_id, &embeds)
+                    .await
+            }
+            MessageChannel::Dm(channel) => {
+                pubsub
+                    .notify_dm_message_update(&channel, &message_id, &embeds)
+                    .await
+            }
+        }
+    });
+}
+
+/// Reads the embeds of a message as they are stored in the database.
+pub fn embeds_from_json(value: Option<serde_json::Value>) -> Option<Vec<Embed>> {
+    serde_json::from_value(value?).ok()
+}
+
+/// Makes a possibly relative link absolute, as long as it's a web link
+fn resolve(base: &Url, link: &str) -> Option<String> {
+    let url = base.join(link).ok()?;
+
+    if url.scheme() != "http" && url.scheme() != "https" {
+        return None;
+    }
+
+    Some(url.to_string())
+}
+
+fn truncate(mut text: String, max_length: usize) -> String {
+    if text.len() > max_length {
+        let mut end = max_length;
+        while !text.is_char_boundary(end) {
+            end -= 1;
+        }
+        text.truncate(end);
+    }
+
+    text
+}
@@ -0,0 +1,214 @@
+use std::net::{IpAddr, Ipv4Addr, TcpListener};
+
+use actix_web::{http::header, web, App, HttpRequest, HttpResponse, HttpServer};
+
+use super::{extract_urls, Unfurler, MAX_EMBEDS};
+
+#[test]
+fn extracts_urls() {
+    assert_eq!(
+        extract_urls("look at https://example.com/cats and http://example.org"),
+        vec!["https://example.com/cats", "http://example.org"]
+    );
+}
+
+#[test]
+fn skips_urls_in_angle_brackets() {
+    assert_eq!(
+        extract_urls("<https://example.com/quiet> but https://example.com/loud"),
+        vec!["https://example.com/loud"]
+    );
+}
+
+#[test]
+fn leaves_out_punctuation_after_urls() {
+    assert_eq!(
+        extract_urls("have you seen https://example.com/cats? (https://example.com/dogs)."),
+        vec!["https://example.com/cats", "https://example.com/dogs"]
+    );
+}
+
+#[test]
+fn keeps_parentheses_that_are_part_of_urls() {
+    assert_eq!(
+        extract_urls("https://en.wikipedia.org/wiki/Rust_(programming_language)"),
+        vec!["https://en.wikipedia.org/wiki/Rust_(programming_language)"]
+    );
+}
+
+#[test]
+fn extracts_each_url_once() {
+    assert_eq!(
+        extract_urls("https://example.com https://example.com"),
+        vec!["https://example.com"]
+    );
+}
+
+#[test]
+fn extracts_at_most_max_embeds_urls() {
+    let content = (0..MAX_EMBEDS + 3)
+        .map(|i| format!("https://example.com/{}", i))
+        .collect::<Vec<_>>()
+        .join(" ");
+
+    assert_eq!(extract_urls(&content).len(), MAX_EMBEDS);
+}
+
+#[test]
+fn ignores_other_schemes() {
+    assert!(extract_urls("ftp://example.com javascript:alert(1) example.com").is_empty());
+}
+
+const PAGE: &str = r#"<!doctype html>
+<html>
+<head>
+    <title>Not this title</title>
+    <meta property="og:title" content="Cats">
+    <meta property="og:description" content="Pictures of cats">
+    <meta property="og:site_name" content="Example">
+    <meta property="og:image" content="/cat.jpg">
+    <link rel="alternate" type="application/json+oembed" href="/oembed.json">
+</head>
+<body></body>
+</html>"#;
+
+const OEMBED: &str = r#"{"author_name": "Someone", "title": "Not this title either"}"#;
+
+/// Only 127.0.0.1 itself, so that the stand-in server is also reachable at an
+/// address that isn't allowed
+fn only_localhost(ip: IpAddr) -> bool {
+    ip == IpAddr::V4(Ipv4Addr::LOCALHOST)
+}
+
+fn any_loopback(ip: IpAddr) -> bool {
+    ip.is_loopback()
+}
+
+/// Starts a stand-in for a website on 127.0.0.1 and 127.0.0.2, and returns
+/// the port it listens on
+fn start_server() -> u16 {
+    let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, 0)).unwrap();
+    let port = listener.local_addr().unwrap().port();
+    let other_listener = TcpListener::bind((Ipv4Addr::new(127, 0, 0, 2), port)).unwrap();
+
+    let server = HttpServer::new(|| {
+        App::new()
+            .route(
+                "/page",
+                web::get().to(|| async { HttpResponse::Ok().content_type("text/html").body(PAGE) }),
+            )
+            .route(
+                "/oembed.json",
+                web::get().to(|| async {
+                    HttpResponse::Ok()
+                        .content_type("application/json")
+                        .body(OEMBED)
+                }),
+            )
+            .route(
+                "/cat.png",
+                web::get().to(|| async {
+                    HttpResponse::Ok()
+                        .content_type("image/png")
+                        .body(vec![0; 16])
+                }),
+            )
+            .route(
+                "/redirect",
+                web::get().to(|req: HttpRequest| async move {
+                    let port = req.app_config().local_addr().port();
+                    HttpResponse::Found()
+                        .insert_header((
+                            header::LOCATION,
+                            format!("http://127.0.0.2:{}/page", port),
+                        ))
+                        .finish()
+                }),
+            )
+    })
+    .workers(1)
+    .listen(listener)
+    .unwrap()
+    .listen(other_listener)
+    .unwrap()
+    .run();
+
+    actix_rt::spawn(server);
+
+    port
+}
+
+#[actix_rt::test]
+async fn unfurls_page() {
+    let port = start_server();
+    let unfurler = Unfurler::with_address_filter(only_localhost);
+
+    let url = format!("http://127.0.0.1:{}/page", port);
+    let embed = unfurler.unfurl(&url).await.unwrap();
+
+    assert_eq!(embed.url, url);
+    assert_eq!(embed.title.as_deref(), Some("Cats"));
+    assert_eq!(embed.description.as_deref(), Some("Pictures of cats"));
+    assert_eq!(embed.site_name.as_deref(), Some("Example"));
+    assert_eq!(embed.author_name.as_deref(), Some("Someone"));
+    assert_eq!(
+        embed.image_url,
+        Some(format!("http://127.0.0.1:{}/cat.jpg", port))
+    );
+}
+
+#[actix_rt::test]
+async fn unfurls_image() {
+    let port = start_server();
+    let unfurler = Unfurler::with_address_filter(only_localhost);
+
+    let url = format!("http://127.0.0.1:{}/cat.png", port);
+    let embed = unfurler.unfurl(&url).await.unwrap();
+
+    assert_eq!(embed.title, None);
+    assert_eq!(embed.image_url, Some(url));
+}
+
+#[actix_rt::test]
+async fn follows_redirect_to_allowed_address() {
+    let port = start_server();
+    let unfurler = Unfurler::with_address_filter(any_loopback);
+
+    let url = format!("http://127.0.0.1:{}/redirect", port);
+    let embed = unfurler.unfurl(&url).await.unwrap();
+
+    assert_eq!(embed.title.as_deref(), Some("Cats"));
+}
+
+#[actix_rt::test]
+async fn refuses_redirect_to_disallowed_address() {
+    let port = start_server();
+    let unfurler = Unfurler::with_address_filter(only_localhost);
+
+    let url = format!("http://127.0.0.1:{}/redirect", port);
+
+    assert!(unfurler.unfurl(&url).await.is_none());
+}
+
+#[actix_rt::test]
+async fn refuses_disallowed_address() {
+    let port = start_server();
+    let unfurler = Unfurler::with_address_filter(only_localhost);
+
+    let url = format!("http://127.0.0.2:{}/page", port);
+
+    assert!(unfurler.unfurl(&url).await.is_none());
+}
+
+#[actix_rt::test]
+async fn refuses_private_addresses_by_default() {
+    let port = start_server();
+    let unfurler = Unfurler::new();
+
+    let url = format!("http://127.0.0.1:{}/page", port);
+    assert!(unfurler.unfurl(&url).await.is_none());
+
+    // hostnames are checked once they are resolved
+    let url = format!("http://localhost:{}/page", port);
+    assert!(unfurler.unfurl(&url).await.is_none());
+}
@@ -4,16 +4,18 @@ use std::{
     net::{IpAddr, Ipv4Addr, SocketAddr},
     num::{NonZeroU32, NonZeroU8},
     str::FromStr,
+    time::Duration,
 };
 
 use lazy_static::lazy_static;
 use log::{error, info, warn};
 use mediasoup::{
-    data_structures::Protocol,
+    data_structures::{IceCandidate, Protocol, SocketFlags},
+    plain_transport::PlainTransportOptions,
     prelude::ListenInfo,
     router::RouterOptions,
     rtp_parameters::{
-        MimeTypeAudio, MimeTypeVideo, RtcpFeedback, RtpCodecCapability,
+        MimeTypeAudio, MimeTypeVideo, RtcpFeedback, RtpCapabilities, RtpCodecCapability,
         RtpCodecParametersParameters,
     },
     webrtc_server::{WebRtcServer, WebRtcServerListenInfos, WebRtcServerOptions},
@@ -54,6 +56,36 @@ fn parse_range(range: &str) -> (u16, u16) {
     (min, max)
 }
 
+// get and parse a comma separated list from an environment variable
+fn var_list<T>(name: &str, default: &str) -> Vec<T>
+where
+    T: FromStr,
+    <T as FromStr>::Err: std::fmt::Debug,
+{
+    let given: String = var(name, default);
+    given
+        .split(',')
+        .map(str::trim)
+        .filter(|item| !item.is_empty())
+        .map(|item| match item.parse() {
+            Ok(parsed) => parsed,
+            Err(e) => {
+                error!(
+                    "Invalid config option `{}={}`: `{}` {:?} ({}'s default is usually {})",
+                    name, given, item, e, name, default
+                );
+                std::process::exit(1);
+            }
+        })
+        .collect()
+}
+
+// a port range that is only used if set
+fn optional_range(name: &str) -> Option<(u16, u16)> {
+    let range: String = var(name, "");
+    (!range.is_empty()).then(|| parse_range(&range))
+}
+
 lazy_static! {
     pub static ref NUM_WEB_WORKERS: usize = var("NUM_WEB_WORKERS", "4");
 
@@ -62,7 +94,20 @@ lazy_static! {
         parse_range(&ports)
     };
 
-    static ref ANNOUNCE_IP: IpAddr = IpAddr::V4(var("WRTC_ANNOUNCE_IP", "127.0.0.1"));
+    static ref WRTC_PLAIN_PORT_RANGE: (u16, u16) = {
+        let ports: String = var("WRTC_PLAIN_PORTS", "40000-40099");
+        parse_range(&ports)
+    };
+
+    static ref WRTC_LISTEN_IPS: Vec<IpAddr> = var_list("WRTC_LISTEN_IPS", "0.0.0.0");
+    static ref ANNOUNCE_ADDRESSES: Vec<AnnouncedAddress> = var_list("WRTC_ANNOUNCE_IP", "127.0.0.1");
+    static ref WRTC_ANNOUNCE_UDP_PORTS: Option<(u16, u16)> = optional_range("WRTC_ANNOUNCE_UDP_PORTS");
+    static ref WRTC_ANNOUNCE_TCP_PORTS: Option<(u16, u16)> = optional_range("WRTC_ANNOUNCE_TCP_PORTS");
+
+    static ref WRTC_LISTEN: Vec<ListenAddress> = WRTC_LISTEN_IPS
+        .iter()
+        .map(|&ip| ListenAddress::new(ip, &ANNOUNCE_ADDRESSES))
+        .collect();
 
     static ref WRTC_ENABLE_UDP: bool = var("WRTC_ENABLE_UDP", "true");
     static ref WRTC_ENABLE_TCP: bool = var("WRTC_ENABLE_TCP", "true");
@@ -76,6 +121,8 @@ lazy_static! {
     };
 
     static ref INITIAL_AVAILABLE_OUTGOING_BITRATE: u32 = var("WRTC_INITIAL_AVAILABLE_OUTGOING_BITRATE", "600000");
+    pub static ref WRTC_SPREAD_CONSUMERS: usize = var("WRTC_SPREAD_CONSUMERS", "500");
+    pub static ref VOICE_RECONNECT_GRACE: Duration = Duration::from_secs(var("VOICE_RECONNECT_GRACE", "15"));
 
     static ref DB_HOST: String = var("DB_HOST", "127.0.0.1");
     static ref DB_PORT: u16 = var("DB_PORT", "5432");
@@ -109,6 +156,32 @@ lazy_static! {
         path
     };
 
+    pub static ref MEDIA_QUOTA: u64 = var("MEDIA_QUOTA", "1000000000");
+    pub static ref MEDIA_GC_GRACE_HOURS: u32 = var("MEDIA_GC_GRACE_HOURS", "24");
+    pub static ref MEDIA_GC_INTERVAL_MINUTES: u64 = var("MEDIA_GC_INTERVAL_MINUTES", "60");
+
+    pub static ref MEDIA_STORAGE: StorageBackend = match var::<String>("MEDIA_STORAGE", "fs").to_ascii_lowercase().as_str() {
+        "fs" => StorageBackend::Filesystem,
+        "s3" => StorageBackend::S3,
+        _ => {
+            error!("Invalid value for MEDIA_STORAGE, must be 'fs' or 's3'");
+            std::process::exit(1);
+        }
+    };
+
+    pub static ref S3_ENDPOINT: String = var("S3_ENDPOINT", "http://127.0.0.1:9000");
+    pub static ref S3_REGION: String = var("S3_REGION", "us-east-1");
+    pub static ref S3_BUCKET: String = var("S3_BUCKET", "zling-media");
+    pub static ref S3_ACCESS_KEY: String = var("S3_ACCESS_KEY", "");
+    pub static ref S3_SECRET_KEY: String = var("S3_SECRET_KEY", "");
+    pub static ref S3_PATH_STYLE: bool = var("S3_PATH_STYLE", "true");
+    pub static ref S3_PRESIGN_DOWNLOADS: bool = var("S3_PRESIGN_DOWNLOADS", "false");
+    pub static ref S3_PRESIGN_EXPIRY: u32 = var("S3_PRESIGN_EXPIRY", "3600");
+
+    pub static ref LINK_PREVIEWS: bool = var("LINK_PREVIEWS", "true");
+    pub static ref LINK_PREVIEW_TIMEOUT: u64 = var("LINK_PREVIEW_TIMEOUT", "5");
+    pub static ref LINK_PREVIEW_MAX_SIZE: usize = var("LINK_PREVIEW_MAX_SIZE", "1000000");
+
     pub static ref TOKEN_SIGNING_KEY: [u8; 32] = {
         let tsk: String = var("TOKEN_SIGNING_KEY", "");
 
@@ -128,6 +201,108 @@ lazy_static! {
     };
 }
 
+/// An address voice clients are told to connect to
+#[derive(Debug, PartialEq)]
+pub enum AnnouncedAddress {
+    Ip(IpAddr),
+    Hostname(String),
+}
+
+impl FromStr for AnnouncedAddress {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if let Ok(ip) = s.parse() {
+            return Ok(Self::Ip(ip));
+        }
+
+        let is_hostname = s.len() <= 253
+            && s.split('.').all(|label| {
+                !label.is_empty()
+                    && label.len() <= 63
+                    && !label.starts_with('-')
+                    && !label.ends_with('-')
+                    && label.chars().all(|c| c.is_ascii_alphanumeric() || c == '-')
+            });
+
+        if is_hostname {
+            Ok(Self::Hostname(s.to_ascii_lowercase()))
+        } else {
+            Err("is neither an IP address nor a hostname".into())
+        }
+    }
+}
+
+impl std::fmt::Display for AnnouncedAddress {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Ip(ip) => ip.fmt(f),
+            Self::Hostname(hostname) => hostname.fmt(f),
+        }
+    }
+}
+
+/// An IP the voice servers listen on, and the addresses clients are told to
+/// reach it at. mediasoup only announces the first one, the others are added
+/// to the ICE candidates of each transport.
+struct ListenAddress {
+    ip: IpAddr,
+    announced: Vec<String>,
+}
+
+impl ListenAddress {
+    fn new(ip: IpAddr, addresses: &[AnnouncedAddress]) -> Self {
+        // IPs of the same family come first, so mediasoup announces an IP
+        // unless there are only hostnames
+        let mut announced: Vec<String> = addresses
+            .iter()
+            .filter(|address| match address {
+                AnnouncedAddress::Ip(announced) => announced.is_ipv4() == ip.is_ipv4(),
+                AnnouncedAddress::Hostname(_) => false,
+            })
+            .map(ToString::to_string)
+            .collect();
+
+        announced.extend(
+            addresses
+                .iter()
+                .filter(|address| matches!(address, AnnouncedAddress::Hostname(_)))
+                .map(ToString::to_string),
+        );
+
+        // a specific IP can be announced as is
+        if announced.is_empty() && !ip.is_unspecified() {
+            announced.push(ip.to_string());
+        }
+
+        Self { ip, announced }
+    }
+
+    fn listen_info(&self, port: Option<u16>, protocol: Protocol) -> ListenInfo {
+        // without this, listening on :: would take the port on 0.0.0.0 as well
+        let ipv6_only = self.ip.is_ipv6() && WRTC_LISTEN_IPS.iter().any(IpAddr::is_ipv4);
+
+        ListenInfo {
+            ip: self.ip,
+            announced_address: self.announced.first().cloned(),
+            port,
+            recv_buffer_size: None,
+            send_buffer_size: None,
+            flags: ipv6_only.then_some(SocketFlags {
+                ipv6_only: true,
+                udp_reuse_port: false,
+            }),
+            protocol,
+        }
+    }
+}
+
+#[derive(PartialEq)]
+pub enum StorageBackend {
+    Filesystem,
+    S3,
+}
+
 pub fn media_codecs() -> Vec<RtpCodecCapability> {
     vec![
         RtpCodecCapability::Audio {
@@ -138,26 +313,53 @@ pub fn media_codecs() -> Vec<RtpCodecCapability> {
             parameters: RtpCodecParametersParameters::from([("useinbandfec", 1_u32.into())]),
             rtcp_feedback: vec![RtcpFeedback::TransportCc],
         },
+        // vp8 and h264 are what browsers simulcast with
+        RtpCodecCapability::Video {
+            mime_type: MimeTypeVideo::Vp8,
+            preferred_payload_type: None,
+            clock_rate: NonZeroU32::new(90000).unwrap(),
+            parameters: RtpCodecParametersParameters::default(),
+            rtcp_feedback: video_rtcp_feedback(),
+        },
+        // vp9 can use svc layers instead
         RtpCodecCapability::Video {
             mime_type: MimeTypeVideo::Vp9,
             preferred_payload_type: None,
             clock_rate: NonZeroU32::new(90000).unwrap(),
             parameters: RtpCodecParametersParameters::default(),
-            rtcp_feedback: vec![
-                RtcpFeedback::Nack,
-                RtcpFeedback::NackPli,
-                RtcpFeedback::CcmFir,
-                RtcpFeedback::GoogRemb,
-                RtcpFeedback::TransportCc,
-            ],
+            rtcp_feedback: video_rtcp_feedback(),
         },
+        // constrained baseline, which every h264 implementation can decode
+        RtpCodecCapability::Video {
+            mime_type: MimeTypeVideo::H264,
+            preferred_payload_type: None,
+            clock_rate: NonZeroU32::new(90000).unwrap(),
+            parameters: RtpCodecParametersParameters::from([
+                ("packetization-mode", 1_u32.into()),
+                ("profile-level-id", "42e01f".into()),
+                ("level-asymmetry-allowed", 1_u32.into()),
+            ]),
+            rtcp_feedback: video_rtcp_feedback(),
+        },
+    ]
+}
+
+fn video_rtcp_feedback() -> Vec<RtcpFeedback> {
+    vec![
+        RtcpFeedback::Nack,
+        RtcpFeedback::NackPli,
+        RtcpFeedback::CcmFir,
+        RtcpFeedback::GoogRemb,
+        RtcpFeedback::TransportCc,
     ]
 }
 
 pub fn worker_settings() -> WorkerSettings {
     let mut worker_settings = WorkerSettings::default();
     worker_settings.log_level = WorkerLogLevel::Warn;
-    worker_settings.rtc_ports_range = (WRTC_PORT_RANGE.0)..=(WRTC_PORT_RANGE.1);
+    // webrtc transports go through the webrtc servers, so these ports are
+    // only used by plain transports
+    worker_settings.rtc_ports_range = (WRTC_PLAIN_PORT_RANGE.0)..=(WRTC_PLAIN_PORT_RANGE.1);
     worker_settings
 }
 
@@ -177,30 +379,68 @@ pub fn webrtc_transport_options(webrtc_server: WebRtcServer) -> WebRtcTransportO
     opts
 }
 
-pub fn webrtc_server_options(port: u16) -> WebRtcServerOptions {
-    let mut info = ListenInfo {
-        ip: IpAddr::V4(Ipv4Addr::UNSPECIFIED),
-        announced_address: Some((*ANNOUNCE_IP).to_string()),
-        port: Some(port),
+/// Plain RTP transports for bots. With `comedia` the transport sends back to
+/// wherever the first RTP packet came from, so it doesn't need to be connected.
+/// They can only have one address, so they listen on the first of
+/// `WRTC_LISTEN_IPS` and announce its first address.
+pub fn plain_transport_options(comedia: bool) -> PlainTransportOptions {
+    let mut opts = PlainTransportOptions::new(WRTC_LISTEN[0].listen_info(None, Protocol::Udp));
+
+    opts.rtcp_mux = true;
+    opts.comedia = comedia;
+
+    opts
+}
+
+/// Plain RTP transports that recordings are received over, from the worker to
+/// the server itself
+pub fn recording_transport_options() -> PlainTransportOptions {
+    let mut opts = PlainTransportOptions::new(ListenInfo {
+        ip: IpAddr::V4(Ipv4Addr::LOCALHOST),
+        announced_address: None,
+        port: None,
         recv_buffer_size: None,
         send_buffer_size: None,
         flags: None,
         protocol: Protocol::Udp,
-    };
+    });
 
-    let mut listen_infos = Vec::with_capacity(2);
+    opts.rtcp_mux = true;
 
-    if *WRTC_ENABLE_UDP {
-        listen_infos.push(info.clone());
+    opts
+}
+
+/// What recordings can receive, which is only Opus
+pub fn recording_rtp_capabilities() -> RtpCapabilities {
+    RtpCapabilities {
+        codecs: vec![RtpCodecCapability::Audio {
+            mime_type: MimeTypeAudio::Opus,
+            preferred_payload_type: Some(100),
+            clock_rate: NonZeroU32::new(48000).unwrap(),
+            channels: NonZeroU8::new(2).unwrap(),
+            parameters: RtpCodecParametersParameters::default(),
+            rtcp_feedback: vec![],
+        }],
+        header_extensions: vec![],
     }
+}
+
+pub fn webrtc_server_options(port: u16) -> WebRtcServerOptions {
+    let mut listen_infos = Vec::with_capacity(WRTC_LISTEN.len() * 2);
 
-    if *WRTC_ENABLE_TCP {
-        info.protocol = Protocol::Tcp;
-        listen_infos.push(info);
+    for listen in WRTC_LISTEN.iter() {
+        if *WRTC_ENABLE_UDP {
+            listen_infos.push(listen.listen_info(Some(port), Protocol::Udp));
+        }
+
+        if *WRTC_ENABLE_TCP {
+            listen_infos.push(listen.listen_info(Some(port), Protocol::Tcp));
+        }
     }
 
-    let mut wrtc_listen_infos = WebRtcServerListenInfos::new(listen_infos.pop().unwrap());
-    if let Some(listen_info) = listen_infos.pop() {
+    let mut listen_infos = listen_infos.into_iter();
+    let mut wrtc_listen_infos = WebRtcServerListenInfos::new(listen_infos.next().unwrap());
+    for listen_info in listen_infos {
         // god I hate mediasoup's rust API sometimes
         wrtc_listen_infos = wrtc_listen_infos.insert(listen_info);
     }
@@ -208,6 +448,66 @@ pub fn webrtc_server_options(port: u16) -> WebRtcServerOptions {
     WebRtcServerOptions::new(wrtc_listen_infos)
 }
 
+/// The ICE candidates clients get for a WebRTC transport. mediasoup only
+/// announces one address per listen IP, and the ports it listens on, so this
+/// adds the other announced addresses and swaps in the announced ports.
+pub fn announced_ice_candidates(candidates: &[IceCandidate]) -> Vec<IceCandidate> {
+    announce_candidates(candidates, &WRTC_LISTEN, announced_port)
+}
+
+fn announce_candidates(
+    candidates: &[IceCandidate],
+    listen: &[ListenAddress],
+    announced_port: fn(Protocol, u16) -> u16,
+) -> Vec<IceCandidate> {
+    let mut announced = Vec::with_capacity(candidates.len());
+
+    for candidate in candidates {
+        let port = announced_port(candidate.protocol, candidate.port);
+
+        let other_addresses = listen
+            .iter()
+            .find(|listen| listen.announced.first() == Some(&candidate.address))
+            .map_or(&[][..], |listen| &listen.announced[1..]);
+
+        announced.push(IceCandidate {
+            port,
+            ..candidate.clone()
+        });
+
+        for (i, address) in other_addresses.iter().enumerate() {
+            announced.push(IceCandidate {
+                foundation: format!("{}{}", candidate.foundation, i + 1),
+                // the first address is still tried first
+                priority: candidate.priority.saturating_sub(i as u32 + 1),
+                address: address.clone(),
+                port,
+                ..candidate.clone()
+            });
+        }
+    }
+
+    announced
+}
+
+fn announced_port(protocol: Protocol, port: u16) -> u16 {
+    let announced_range = match protocol {
+        Protocol::Udp => *WRTC_ANNOUNCE_UDP_PORTS,
+        Protocol::Tcp => *WRTC_ANNOUNCE_TCP_PORTS,
+    };
+
+    offset_port(port, WRTC_PORT_RANGE.0, announced_range)
+}
+
+/// Moves a port from the range starting at `listen_min` to the same place in
+/// the announced range
+fn offset_port(port: u16, listen_min: u16, announced_range: Option<(u16, u16)>) -> u16 {
+    match announced_range {
+        Some((min, _)) if port >= listen_min => min + (port - listen_min),
+        _ => port,
+    }
+}
+
 pub fn voice_ports() -> Vec<u16> {
     (WRTC_PORT_RANGE.0..=WRTC_PORT_RANGE.1).rev().collect()
 }
@@ -272,8 +572,109 @@ pub fn initialize_all() {
         std::process::exit(1);
     }
 
-    lazy_static::initialize(&ANNOUNCE_IP);
+    lazy_static::initialize(&WRTC_PLAIN_PORT_RANGE);
+
+    if WRTC_PLAIN_PORT_RANGE.0 > WRTC_PLAIN_PORT_RANGE.1 {
+        error!("Minimum plain RTP port cannot be greater than maximum plain RTP port");
+        std::process::exit(1);
+    }
+
+    if WRTC_PLAIN_PORT_RANGE.0 <= WRTC_PORT_RANGE.1 && WRTC_PORT_RANGE.0 <= WRTC_PLAIN_PORT_RANGE.1 {
+        error!("WRTC_PLAIN_PORTS cannot overlap with WRTC_PORTS");
+        std::process::exit(1);
+    }
+
+    lazy_static::initialize(&WRTC_LISTEN_IPS);
+    lazy_static::initialize(&ANNOUNCE_ADDRESSES);
+    lazy_static::initialize(&WRTC_LISTEN);
+
+    if WRTC_LISTEN_IPS.is_empty() {
+        error!("WRTC_LISTEN_IPS needs at least one IP to listen on");
+        std::process::exit(1);
+    }
+
+    for (i, ip) in WRTC_LISTEN_IPS.iter().enumerate() {
+        if WRTC_LISTEN_IPS[..i].contains(ip) {
+            error!("WRTC_LISTEN_IPS has {} more than once", ip);
+            std::process::exit(1);
+        }
+
+        // 0.0.0.0 and :: already listen on every IP of their family
+        if let Some(unspecified) = WRTC_LISTEN_IPS
+            .iter()
+            .find(|other| *other != ip && other.is_unspecified() && other.is_ipv4() == ip.is_ipv4())
+        {
+            error!(
+                "WRTC_LISTEN_IPS cannot have {} next to {}, which already listens on it",
+                ip, unspecified
+            );
+            std::process::exit(1);
+        }
+    }
+
+    for listen in WRTC_LISTEN.iter() {
+        if listen.announced.is_empty() {
+            error!(
+                "WRTC_ANNOUNCE_IP needs an IPv{} address or a hostname for clients to reach {} at",
+                if listen.ip.is_ipv4() { 4 } else { 6 },
+                listen.ip
+            );
+            std::process::exit(1);
+        }
+    }
+
+    for (i, address) in ANNOUNCE_ADDRESSES.iter().enumerate() {
+        if ANNOUNCE_ADDRESSES[..i].contains(address) {
+            error!("WRTC_ANNOUNCE_IP has {} more than once", address);
+            std::process::exit(1);
+        }
+
+        if let AnnouncedAddress::Ip(ip) = address {
+            if !WRTC_LISTEN_IPS
+                .iter()
+                .any(|listen| listen.is_ipv4() == ip.is_ipv4())
+            {
+                error!(
+                    "WRTC_ANNOUNCE_IP has {}, but WRTC_LISTEN_IPS doesn't listen on any IPv{} address",
+                    ip,
+                    if ip.is_ipv4() { 4 } else { 6 }
+                );
+                std::process::exit(1);
+            }
+        }
+    }
+
+    lazy_static::initialize(&WRTC_ANNOUNCE_UDP_PORTS);
+    lazy_static::initialize(&WRTC_ANNOUNCE_TCP_PORTS);
+
+    for (name, range) in [
+        ("WRTC_ANNOUNCE_UDP_PORTS", *WRTC_ANNOUNCE_UDP_PORTS),
+        ("WRTC_ANNOUNCE_TCP_PORTS", *WRTC_ANNOUNCE_TCP_PORTS),
+    ] {
+        let Some((min, max)) = range else {
+            continue;
+        };
+
+        if min > max {
+            error!(
+                "Minimum port of {} cannot be greater than its maximum port",
+                name
+            );
+            std::process::exit(1);
+        }
+
+        if max - min != WRTC_PORT_RANGE.1 - WRTC_PORT_RANGE.0 {
+            error!(
+                "{} needs as many ports as WRTC_PORTS, one for each WebRTC server",
+                name
+            );
+            std::process::exit(1);
+        }
+    }
+
     lazy_static::initialize(&INITIAL_AVAILABLE_OUTGOING_BITRATE);
+    lazy_static::initialize(&WRTC_SPREAD_CONSUMERS);
+    lazy_static::initialize(&VOICE_RECONNECT_GRACE);
 
     lazy_static::initialize(&WRTC_ENABLE_UDP);
     lazy_static::initialize(&WRTC_ENABLE_TCP);
@@ -309,6 +710,30 @@ pub fn initialize_all() {
     lazy_static::initialize(&TOKEN_SIGNING_KEY);
 
     lazy_static::initialize(&MEDIA_PATH);
+    lazy_static::initialize(&MEDIA_STORAGE);
+    lazy_static::initialize(&MEDIA_QUOTA);
+    lazy_static::initialize(&MEDIA_GC_GRACE_HOURS);
+    lazy_static::initialize(&MEDIA_GC_INTERVAL_MINUTES);
+
+    if *MEDIA_GC_INTERVAL_MINUTES == 0 {
+        error!("MEDIA_GC_INTERVAL_MINUTES must be at least 1");
+        std::process::exit(1);
+    }
+
+    if *MEDIA_STORAGE == StorageBackend::S3 {
+        lazy_static::initialize(&S3_ENDPOINT);
+        lazy_static::initialize(&S3_REGION);
+        lazy_static::initialize(&S3_BUCKET);
+        lazy_static::initialize(&S3_ACCESS_KEY);
+        lazy_static::initialize(&S3_SECRET_KEY);
+        lazy_static::initialize(&S3_PATH_STYLE);
+        lazy_static::initialize(&S3_PRESIGN_DOWNLOADS);
+        lazy_static::initialize(&S3_PRESIGN_EXPIRY);
+    }
+
+    lazy_static::initialize(&LINK_PREVIEWS);
+    lazy_static::initialize(&LINK_PREVIEW_TIMEOUT);
+    lazy_static::initialize(&LINK_PREVIEW_MAX_SIZE);
 }
 
 pub fn print_all() {
@@ -339,9 +764,28 @@ pub fn print_all() {
         }
     }
 
-    info!("config: WebRTC Announce IP: {}", *ANNOUNCE_IP);
+    for listen in WRTC_LISTEN.iter() {
+        info!(
+            "config: WebRTC Listen IP: {} (announced as {})",
+            listen.ip,
+            listen.announced.join(", ")
+        );
+    }
+
+    if let Some((min, max)) = *WRTC_ANNOUNCE_UDP_PORTS {
+        info!("config: WebRTC Announced UDP Ports: {}-{}", min, max);
+    }
 
-    if ANNOUNCE_IP.is_loopback() {
+    if let Some((min, max)) = *WRTC_ANNOUNCE_TCP_PORTS {
+        info!("config: WebRTC Announced TCP Ports: {}-{}", min, max);
+    }
+
+    let only_loopback = ANNOUNCE_ADDRESSES.iter().all(|address| match address {
+        AnnouncedAddress::Ip(ip) => ip.is_loopback(),
+        AnnouncedAddress::Hostname(hostname) => hostname == "localhost",
+    });
+
+    if only_loopback {
         warn!("WRTC_ANNOUNCE_IP is set to a loopback address, voice clients will probably not be able to connect! Set it to your server's public IP!")
     }
 
@@ -370,5 +814,187 @@ pub fn print_all() {
         *DB_NAME, *DB_HOST, *DB_PORT, *DB_POOL_MAX_CONNS
     );
 
-    info!("config: Uploaded media stored in: {}", *MEDIA_PATH);
+    match *MEDIA_STORAGE {
+        StorageBackend::Filesystem => {
+            info!("config: Uploaded media stored in: {}", *MEDIA_PATH);
+        }
+        StorageBackend::S3 => {
+            info!(
+                "config: Uploaded media stored in S3 bucket `{}` at {} (presigned downloads: {})",
+                *S3_BUCKET, *S3_ENDPOINT, *S3_PRESIGN_DOWNLOADS
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use mediasoup::data_structures::IceCandidateType;
+
+    use super::*;
+
+    fn addresses(addresses: &[&str]) -> Vec<AnnouncedAddress> {
+        addresses.iter().map(|a| a.parse().unwrap()).collect()
+    }
+
+    fn candidate(address: &str, protocol: Protocol, port: u16) -> IceCandidate {
+        IceCandidate {
+            foundation: "udpcandidate".to_string(),
+            priority: 1076302079,
+            address: address.to_string(),
+            protocol,
+            port,
+            r#type: IceCandidateType::Host,
+            tcp_type: None,
+        }
+    }
+
+    fn unchanged_port(_: Protocol, port: u16) -> u16 {
+        port
+    }
+
+    #[test]
+    fn parses_announced_ips() {
+        assert_eq!(
+            "203.0.113.1".parse::<AnnouncedAddress>(),
+            Ok(AnnouncedAddress::Ip("203.0.113.1".parse().unwrap()))
+        );
+        assert_eq!(
+            "2001:db8::1".parse::<AnnouncedAddress>(),
+            Ok(AnnouncedAddress::Ip("2001:db8::1".parse().unwrap()))
+        );
+    }
+
+    #[test]
+    fn parses_announced_hostnames() {
+        assert_eq!(
+            "Voice.Example.com".parse::<AnnouncedAddress>(),
+            Ok(AnnouncedAddress::Hostname("voice.example.com".to_string()))
+        );
+        assert_eq!(
+            "voice-1".parse::<AnnouncedAddress>(),
+            Ok(AnnouncedAddress::Hostname("voice-1".to_string()))
+        );
+    }
+
+    #[test]
+    fn rejects_invalid_announced_addresses() {
+        let long_label = "a".repeat(64);
+        let long_hostname = ["a"; 128].join(".");
+
+        for invalid in [
+            "",
+            "voice..example.com",
+            "-voice.example.com",
+            "voice-.example.com",
+            "voice_1.example.com",
+            "voice.example.com:10000",
+            "http://voice.example.com",
+            long_label.as_str(),
+            long_hostname.as_str(),
+        ] {
+            assert!(
+                invalid.parse::<AnnouncedAddress>().is_err(),
+                "{:?} was accepted",
+                invalid
+            );
+        }
+    }
+
+    #[test]
+    fn announces_ips_of_the_same_family_first() {
+        let announced = addresses(&["voice.example.com", "2001:db8::1", "203.0.113.1"]);
+
+        let v4 = ListenAddress::new(Ipv4Addr::UNSPECIFIED.into(), &announced);
+        assert_eq!(v4.announced, vec!["203.0.113.1", "voice.example.com"]);
+
+        let v6 = ListenAddress::new("::".parse().unwrap(), &announced);
+        assert_eq!(v6.announced, vec!["2001:db8::1", "voice.example.com"]);
+    }
+
+    #[test]
+    fn announces_a_specific_listen_ip_as_is() {
+        let listen = ListenAddress::new("192.0.2.1".parse().unwrap(), &[]);
+        assert_eq!(listen.announced, vec!["192.0.2.1"]);
+
+        let listen = ListenAddress::new(Ipv4Addr::UNSPECIFIED.into(), &[]);
+        assert!(listen.announced.is_empty());
+    }
+
+    #[test]
+    fn offsets_announced_ports() {
+        assert_eq!(offset_port(10000, 10000, Some((443, 443))), 443);
+        assert_eq!(offset_port(10002, 10000, Some((20000, 20009))), 20002);
+        assert_eq!(offset_port(10002, 10000, None), 10002);
+        // not one of the webrtc server ports
+        assert_eq!(offset_port(9999, 10000, Some((20000, 20009))), 9999);
+    }
+
+    #[test]
+    fn adds_candidates_for_the_other_announced_addresses() {
+        let listen = [ListenAddress::new(
+            Ipv4Addr::UNSPECIFIED.into(),
+            &addresses(&["203.0.113.1", "198.51.100.1", "voice.example.com"]),
+        )];
+        let candidates = [candidate("203.0.113.1", Protocol::Udp, 10000)];
+
+        let announced = announce_candidates(&candidates, &listen, unchanged_port);
+
+        let addresses: Vec<&str> = announced.iter().map(|c| c.address.as_str()).collect();
+        assert_eq!(
+            addresses,
+            vec!["203.0.113.1", "198.51.100.1", "voice.example.com"]
+        );
+
+        // each candidate is unique and ranked below the one before it
+        assert_eq!(announced[0].foundation, "udpcandidate");
+        assert_eq!(announced[1].foundation, "udpcandidate1");
+        assert_eq!(announced[2].foundation, "udpcandidate2");
+        assert!(announced[0].priority > announced[1].priority);
+        assert!(announced[1].priority > announced[2].priority);
+    }
+
+    #[test]
+    fn announces_ports_of_every_candidate() {
+        let listen = [ListenAddress::new(
+            Ipv4Addr::UNSPECIFIED.into(),
+            &addresses(&["203.0.113.1", "voice.example.com"]),
+        )];
+        let candidates = [
+            candidate("203.0.113.1", Protocol::Udp, 10000),
+            candidate("203.0.113.1", Protocol::Tcp, 10000),
+        ];
+
+        let announced =
+            announce_candidates(&candidates, &listen, |protocol, port| match protocol {
+                Protocol::Udp => port + 1,
+                Protocol::Tcp => 443,
+            });
+
+        let ports: Vec<(Protocol, u16)> = announced.iter().map(|c| (c.protocol, c.port)).collect();
+        assert_eq!(
+            ports,
+            vec![
+                (Protocol::Udp, 10001),
+                (Protocol::Udp, 10001),
+                (Protocol::Tcp, 443),
+                (Protocol::Tcp, 443),
+            ]
+        );
+    }
+
+    #[test]
+    fn leaves_candidates_of_unknown_addresses_alone() {
+        let listen = [ListenAddress::new(
+            Ipv4Addr::UNSPECIFIED.into(),
+            &addresses(&["203.0.113.1", "voice.example.com"]),
+        )];
+        let candidates = [candidate("192.0.2.1", Protocol::Udp, 10000)];
+
+        let announced = announce_candidates(&candidates, &listen, unchanged_port);
+
+        assert_eq!(announced.len(), 1);
+        assert_eq!(announced[0].address, "192.0.2.1");
+        assert_eq!(announced[0].foundation, "udpcandidate");
+    }
 }
@@ -4,8 +4,10 @@ use serde_json::json;
 
 use crate::{
     auth::user::{PublicUserInfo, User},
-    messaging::message::Message,
+    friends::dmchannel::DMChannel,
+    messaging::{message::Message, unfurl::Embed},
     realtime::socket::Socket,
+    voice::state::VoiceMemberState,
 };
 
 use super::{
@@ -19,7 +21,7 @@ pub struct PubSub {
     map: RwLock<PubSubMap>,
 }
 
-#[derive(Serialize)]
+#[derive(Serialize, Clone)]
 #[serde(tag = "type", rename_all = "camelCase")]
 pub enum Event<'l> {
     /// Something changed in the list of channels in a guild.
@@ -28,8 +30,18 @@ pub enum Event<'l> {
     MemberListUpdate,
     /// A new message was sent.
     Message(&'l Message),
+    /// A message changed. Only the fields that changed are sent, which for now
+    /// is only ever its link previews once they're ready.
+    MessageUpdate { id: &'l str, embeds: &'l [Embed] },
     /// A message was deleted.
     DeleteMessage { id: &'l str },
+    /// Someone joined or left a voice channel in a guild, or changed their
+    /// voice state. `channelId` is null when they left.
+    #[serde(rename_all = "camelCase")]
+    VoiceStateUpdate {
+        channel_id: Option<&'l str>,
+        member: &'l VoiceMemberState,
+    },
     /// A user started typing in a channel.
     Typing { user: &'l PublicUserInfo },
 
@@ -48,6 +60,13 @@ pub enum Event<'l> {
     FriendRequestRemove { user: &'l PublicUserInfo },
     /// Someone severed all ties with you
     FriendRemove { user: &'l PublicUserInfo },
+
+    /// The name, icon, owner or participant list of a group DM changed.
+    /// Clients should refetch the group's information.
+    GroupDmUpdate,
+    /// You are no longer in a group DM, either because you left, you were
+    /// removed by the owner, or the group was deleted.
+    GroupDmRemove,
 }
 
 impl PubSub {
@@ -128,30 +147,21 @@ impl PubSub {
         .await;
     }
 
-    pub async fn notify_dm_new_message(&self, recipient_id: &str, message: &Message) {
-        // send to the recipient
-        self.send_to_user(
-            recipient_id,
-            &Topic::new(TopicType::DmChannel, message.author.id.clone()),
-            Event::Message(message),
-        )
-        .await;
+    /// Sends an event to every participant of a DM channel, on the topic
+    /// each of them follows for that channel.
+    pub async fn send_to_dm_channel(&self, channel: &DMChannel, event: Event<'_>) {
+        let futures = channel.participants.iter().map(|participant| {
+            let topic = channel.topic_for(participant);
+            let event = event.clone();
+            async move { self.send_to_user(participant, &topic, event).await }
+        });
 
-        if recipient_id == message.author.id {
-            // somehow we are in a dm with ourselves and we are sending a
-            // message to ourselves
-            return;
-        }
+        join_all(futures).await;
+    }
 
-        // send the author's own message to the author as well
-        self.send_to_user(
-            &message.author.id,
-            // the dmchannel topic has the recipient's id because
-            // that's the topic that the author follows
-            &Topic::new(TopicType::DmChannel, recipient_id.to_string()),
-            Event::Message(message),
-        )
-        .await;
+    pub async fn notify_dm_new_message(&self, channel: &DMChannel, message: &Message) {
+        self.send_to_dm_channel(channel, Event::Message(message))
+            .await;
     }
 
     pub async fn notify_guild_channel_list_update(&self, guild_id: &str) {
@@ -170,6 +180,19 @@ impl PubSub {
         .await;
     }
 
+    pub async fn notify_voice_state_update(
+        &self,
+        guild_id: &str,
+        channel_id: Option<&str>,
+        member: &VoiceMemberState,
+    ) {
+        self.broadcast(
+            &Topic::new(TopicType::Guild, guild_id.to_string()),
+            Event::VoiceStateUpdate { channel_id, member },
+        )
+        .await;
+    }
+
     pub async fn send_typing(&self, channel_id: &str, user: &User) {
         self.broadcast(
             &Topic::new(TopicType::Channel, channel_id.to_string()),
@@ -180,49 +203,66 @@ impl PubSub {
         .await;
     }
 
-    pub async fn send_dm_typing(&self, recipient_id: &str, sender: &PublicUserInfo) {
-        self.send_to_user(
-            recipient_id,
-            &Topic::new(TopicType::DmChannel, sender.id.clone()),
-            Event::Typing { user: sender },
-        )
-        .await;
+    pub async fn send_dm_typing(&self, channel: &DMChannel, sender: &PublicUserInfo) {
+        let futures = channel
+            .participants
+            .iter()
+            // don't tell the sender that they're typing
+            .filter(|participant| **participant != sender.id)
+            .map(|participant| {
+                let topic = channel.topic_for(participant);
+                async move {
+                    self.send_to_user(participant, &topic, Event::Typing { user: sender })
+                        .await
+                }
+            });
+
+        join_all(futures).await;
     }
 
-    pub async fn notify_message_deleted(&self, channel_id: &str, message_id: &str) {
+    pub async fn notify_message_update(
+        &self,
+        channel_id: &str,
+        message_id: &str,
+        embeds: &[Embed],
+    ) {
         self.broadcast(
             &Topic::new(TopicType::Channel, channel_id.to_string()),
-            Event::DeleteMessage { id: message_id },
+            Event::MessageUpdate {
+                id: message_id,
+                embeds,
+            },
         )
         .await;
     }
 
-    pub async fn notify_dm_message_deleted(
+    pub async fn notify_dm_message_update(
         &self,
-        recipient_id: &str,
-        deleter_id: &str,
+        channel: &DMChannel,
         message_id: &str,
+        embeds: &[Embed],
     ) {
-        self.send_to_user(
-            recipient_id,
-            &Topic::new(TopicType::DmChannel, deleter_id.to_string()),
-            Event::DeleteMessage { id: (message_id) },
+        self.send_to_dm_channel(
+            channel,
+            Event::MessageUpdate {
+                id: message_id,
+                embeds,
+            },
         )
         .await;
+    }
 
-        if recipient_id == deleter_id {
-            // somehow we are in a dm with ourselves and we are deleting our own message
-            return;
-        }
+    pub async fn notify_message_deleted(&self, channel_id: &str, message_id: &str) {
+        self.broadcast(
+            &Topic::new(TopicType::Channel, channel_id.to_string()),
+            Event::DeleteMessage { id: message_id },
+        )
+        .await;
+    }
 
-        // send event back to the deleter as well
-        self.send_to_user(
-            deleter_id,
-            // the dmchannel topic has the recipient's id because
-            // that's the topic that the deleter follows
-            &Topic::new(TopicType::DmChannel, recipient_id.to_string()),
-            Event::DeleteMessage { id: (message_id) },
-        ).await;
+    pub async fn notify_dm_message_deleted(&self, channel: &DMChannel, message_id: &str) {
+        self.send_to_dm_channel(channel, Event::DeleteMessage { id: message_id })
+            .await;
     }
 
     pub async fn notify_friend_request_sent(&self, recipient_id: &str, sender: &PublicUserInfo) {
@@ -270,4 +310,17 @@ impl PubSub {
         )
         .await;
     }
+
+    pub async fn notify_group_dm_update(&self, channel: &DMChannel) {
+        self.send_to_dm_channel(channel, Event::GroupDmUpdate).await;
+    }
+
+    pub async fn notify_group_dm_remove(&self, user_id: &str, group_id: &str) {
+        self.send_to_user(
+            user_id,
+            &Topic::new(TopicType::GroupDm, group_id.to_string()),
+            Event::GroupDmRemove,
+        )
+        .await;
+    }
 }
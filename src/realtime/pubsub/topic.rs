@@ -43,6 +43,9 @@ pub enum TopicType {
     /// Messages or typing, etc... in a DM channel
     /// ID is the other user's ID relative to the current user
     DmChannel,
+    /// Messages, typing and participant changes in a group DM
+    /// ID is the group DM channel's ID
+    GroupDm,
 }
 
 impl FromStr for TopicType {
@@ -54,6 +57,7 @@ impl FromStr for TopicType {
             "channel" => Ok(Self::Channel),
             "user" => Ok(Self::User),
             "dm_channel" => Ok(Self::DmChannel),
+            "group_dm" => Ok(Self::GroupDm),
             _ => Err(()),
         }
     }
@@ -172,6 +172,15 @@ impl Socket {
         Ok((instance, response))
     }
 
+    /// Closes the session without calling `on_disconnect` right away. It is
+    /// still called once the read loop notices, if the socket is still around
+    /// by then.
+    pub async fn close(&self) {
+        if let Some(session) = self.session.write().await.take() {
+            session.close(None).await.unwrap_or(());
+        }
+    }
+
     pub async fn send(&self, msg: String) -> Result<(), SendFailureReason> {
         use SendFailureReason::*;
 
@@ -9,15 +9,154 @@
 
 */
 
-use lazy_static::lazy_static;
-use regex::Regex;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
 
-lazy_static! {
-    static ref RESOURCE_REGEX: Regex =
-        Regex::new(r"^/media/[a-zA-Z0-9_-]+/[a-zA-Z0-9_.-]+$").unwrap();
+/// Whether an address is reachable on the public internet. Used to keep the
+/// server from being tricked into making requests to itself or to other
+/// machines on its private network.
+pub fn is_public_ip(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => is_public_ipv4(ip),
+        IpAddr::V6(ip) => is_public_ipv6(ip),
+    }
 }
 
-/// Ensures that a resource url is from the zling media api.
-pub fn validate_resource_origin(url: &str) -> bool {
-    RESOURCE_REGEX.is_match(url)
+fn is_public_ipv4(ip: Ipv4Addr) -> bool {
+    let [a, b, c, _] = ip.octets();
+
+    !(ip.is_unspecified()
+        || ip.is_loopback()
+        || ip.is_private()
+        || ip.is_link_local()
+        || ip.is_broadcast()
+        || ip.is_documentation()
+        || ip.is_multicast()
+        // "this network", 0.0.0.0/8
+        || a == 0
+        // carrier grade nat, 100.64.0.0/10
+        || (a == 100 && (64..128).contains(&b))
+        // protocol assignments, 192.0.0.0/24
+        || (a == 192 && b == 0 && c == 0)
+        // benchmarking, 198.18.0.0/15
+        || (a == 198 && (b == 18 || b == 19))
+        // reserved, 240.0.0.0/4
+        || a >= 240)
+}
+
+fn is_public_ipv6(ip: Ipv6Addr) -> bool {
+    // v4 addresses can be smuggled in as mapped or translated addresses
+    if let Some(v4) = ip.to_ipv4_mapped() {
+        return is_public_ipv4(v4);
+    }
+
+    let segments = ip.segments();
+
+    // nat64, 64:ff9b::/96
+    if segments[..6] == [0x64, 0xff9b, 0, 0, 0, 0] {
+        let [_, _, _, _, _, _, high, low] = segments;
+        return is_public_ipv4(Ipv4Addr::from(((high as u32) << 16) | low as u32));
+    }
+
+    !(ip.is_unspecified()
+        || ip.is_loopback()
+        || ip.is_multicast()
+        // unique local, fc00::/7
+        || (segments[0] & 0xfe00) == 0xfc00
+        // link local, fe80::/10
+        || (segments[0] & 0xffc0) == 0xfe80
+        // site local (deprecated), fec0::/10
+        || (segments[0] & 0xffc0) == 0xfec0
+        // documentation, 2001:db8::/32
+        || (segments[0] == 0x2001 && segments[1] == 0xdb8)
+        // ipv4 compatible (deprecated), ::/96
+        || segments[..6] == [0, 0, 0, 0, 0, 0])
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::IpAddr;
+
+    use super::is_public_ip;
+
+    fn is_public(ip: &str) -> bool {
+        is_public_ip(ip.parse::<IpAddr>().unwrap())
+    }
+
+    #[test]
+    fn public_addresses() {
+        for ip in [
+            "1.1.1.1",
+            "8.8.8.8",
+            "2606:4700:4700::1111",
+            "2001:4860::8888",
+        ] {
+            assert!(is_public(ip), "{} should be public", ip);
+        }
+    }
+
+    #[test]
+    fn private_ipv4() {
+        for ip in [
+            "10.0.0.1",
+            "172.16.0.1",
+            "172.31.255.255",
+            "192.168.1.1",
+            "100.64.0.1",
+            "0.0.0.0",
+            "0.1.2.3",
+            "255.255.255.255",
+            "240.0.0.1",
+            "224.0.0.1",
+            "198.18.0.1",
+            "192.0.0.1",
+            "192.0.2.1",
+        ] {
+            assert!(!is_public(ip), "{} should not be public", ip);
+        }
+    }
+
+    #[test]
+    fn loopback() {
+        for ip in ["127.0.0.1", "127.1.2.3", "::1"] {
+            assert!(!is_public(ip), "{} should not be public", ip);
+        }
+    }
+
+    #[test]
+    fn link_local() {
+        for ip in ["169.254.169.254", "fe80::1", "febf::1"] {
+            assert!(!is_public(ip), "{} should not be public", ip);
+        }
+    }
+
+    #[test]
+    fn private_ipv6() {
+        for ip in [
+            "::",
+            "fc00::1",
+            "fd12:3456::1",
+            "fec0::1",
+            "ff02::1",
+            "2001:db8::1",
+        ] {
+            assert!(!is_public(ip), "{} should not be public", ip);
+        }
+    }
+
+    #[test]
+    fn ipv4_in_ipv6() {
+        // mapped
+        assert!(!is_public("::ffff:127.0.0.1"));
+        assert!(!is_public("::ffff:10.0.0.1"));
+        assert!(!is_public("::ffff:169.254.169.254"));
+        assert!(is_public("::ffff:1.1.1.1"));
+
+        // nat64
+        assert!(!is_public("64:ff9b::192.168.1.1"));
+        assert!(is_public("64:ff9b::8.8.8.8"));
+
+        // compatible
+        assert!(!is_public("::127.0.0.1"));
+        assert!(!is_public("::8.8.8.8"));
+    }
 }
@@ -6,10 +6,12 @@ use crate::{
     auth::access_token::AccessToken,
     db::DB,
     error::{macros::err, HResult},
+    media::{attachments::resolve_square_image, storage::Storage},
 };
 
 #[derive(Deserialize)]
 pub struct SetAvatarRequest {
+    /// ID of an image you uploaded through the media API
     avatar: String,
 }
 
@@ -18,11 +20,9 @@ pub async fn set_avatar(
     token: AccessToken,
     req: Json<SetAvatarRequest>,
     db: DB,
+    storage: Storage,
 ) -> HResult<HttpResponse> {
-    // todo: better check here
-    if !req.avatar.starts_with("/media/") {
-        err!(400, "Invalid avatar URL.")?;
-    }
+    let avatar = resolve_square_image(&db, &storage, &token.user_id, &req.avatar).await?;
 
     // alter avatar in db
     let result = sqlx::query!(
@@ -31,7 +31,7 @@ pub async fn set_avatar(
             SET avatar = $1
             WHERE id = $2
         "#,
-        req.avatar,
+        avatar,
         token.user_id
     )
     .execute(&db.pool)
@@ -1,5 +1,8 @@
 pub mod avatar;
+pub mod privacy;
 
 pub fn configure_app(cfg: &mut actix_web::web::ServiceConfig) {
-    cfg.service(avatar::set_avatar);
+    cfg.service(avatar::set_avatar)
+        .service(privacy::get_privacy)
+        .service(privacy::set_privacy);
 }
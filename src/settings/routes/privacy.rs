@@ -0,0 +1,60 @@
+use actix_web::{get, post, web::Json, HttpResponse};
+use log::warn;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    auth::access_token::AccessToken,
+    db::DB,
+    error::{macros::err, HResult},
+    friends::friend_request::FriendRequestPrivacy,
+};
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PrivacySettings {
+    friend_requests: FriendRequestPrivacy,
+}
+
+#[get("/settings/privacy")]
+pub async fn get_privacy(token: AccessToken, db: DB) -> HResult<Json<PrivacySettings>> {
+    let record = sqlx::query!(
+        r#"
+            SELECT friend_request_privacy AS "friend_requests: FriendRequestPrivacy"
+            FROM users
+            WHERE id = $1
+        "#,
+        token.user_id
+    )
+    .fetch_one(&db.pool)
+    .await?;
+
+    Ok(Json(PrivacySettings {
+        friend_requests: record.friend_requests,
+    }))
+}
+
+#[post("/settings/privacy")]
+pub async fn set_privacy(
+    token: AccessToken,
+    req: Json<PrivacySettings>,
+    db: DB,
+) -> HResult<HttpResponse> {
+    let result = sqlx::query!(
+        r#"
+            UPDATE users
+            SET friend_request_privacy = $1
+            WHERE id = $2
+        "#,
+        req.friend_requests as FriendRequestPrivacy,
+        token.user_id
+    )
+    .execute(&db.pool)
+    .await?;
+
+    if result.rows_affected() != 1 {
+        warn!("privacy set nothing");
+        err!()?;
+    }
+
+    Ok(HttpResponse::Ok().finish())
+}
@@ -1,59 +1,102 @@
-use std::{num::NonZeroU16, sync::Arc};
+use std::{collections::HashMap, num::NonZeroU16, sync::Arc};
 use tokio::sync::Mutex;
 
 use crate::{
     options,
-    voice::{client::VoiceClient, VoiceChannels, VoiceClients},
+    realtime::pubsub::pubsub::PubSub,
+    voice::{client::VoiceClient, routes::produce::ProducerSource, VoiceChannels, VoiceClients},
 };
 use actix_web::web::Data;
-use log::{info, warn};
+use log::{error, info, warn};
 use mediasoup::{
+    plain_transport::PlainTransport,
     prelude::{AudioLevelObserver, AudioLevelObserverOptions},
-    router::Router,
+    producer::ProducerId,
+    router::{
+        PipeProducerToRouterError, PipeProducerToRouterPair, PipeToRouterOptions, Router, RouterId,
+    },
     webrtc_server::WebRtcServer,
     webrtc_transport::WebRtcTransport,
-    worker::RequestError,
+    worker::{RequestError, WorkerId},
 };
 
-use super::pool::VoiceWorkerPool;
+use super::{
+    pool::{PoolRouter, VoicePoolError, VoiceWorkerPool, WorkerLoad},
+    recording::Recording,
+    speaking::{watch_speakers, VOLUME_INTERVAL},
+};
+
+/// Most people reported as speaking at the same time
+const MAX_SPEAKERS: u16 = 16;
 
 pub struct VoiceChannel {
     pub id: String,
+    /// The guild the voice channel is in, where voice state updates are sent
+    pub guild_id: String,
     pub clients: Mutex<Vec<Arc<VoiceClient>>>,
+    /// Where the channel's producers are
     pub router: Router,
     pub webrtc_server: WebRtcServer,
+    /// How busy the worker of `router` is
+    pub load: WorkerLoad,
+    /// Routers on other workers that listeners are spread over once the
+    /// channel's own worker gets busy. Producers are piped to them when
+    /// someone there consumes them.
+    pub consumer_routers: Mutex<Vec<PoolRouter>>,
+    /// Producers piped to the consumer routers, by producer and router
+    pipes: Mutex<HashMap<(ProducerId, RouterId), PipeProducerToRouterPair>>,
     pub al_observer: AudioLevelObserver,
+    /// Set while the channel is being recorded
+    pub recording: Mutex<Option<Arc<Recording>>>,
+    pub pubsub: Data<PubSub>,
+    vwp: Data<Mutex<VoiceWorkerPool>>,
 }
 
 impl VoiceChannel {
-    pub async fn new_with_id(id: String, vwp: &Mutex<VoiceWorkerPool>) -> Self {
-        // TODO: do not unwrap this
-        let (router, webrtc_server) = { vwp.lock().await.allocate_router().await.unwrap() };
+    pub async fn new_with_id(
+        id: String,
+        guild_id: String,
+        vwp: Data<Mutex<VoiceWorkerPool>>,
+        pubsub: Data<PubSub>,
+    ) -> Result<Self, VoicePoolError> {
+        let PoolRouter {
+            router,
+            webrtc_server,
+            load,
+        } = { vwp.lock().await.allocate_router().await? };
 
         let al_observer = router
             .create_audio_level_observer({
                 let mut options = AudioLevelObserverOptions::default();
-                options.max_entries = NonZeroU16::new(1).unwrap();
+                // everyone who is speaking, not just the loudest
+                options.max_entries = NonZeroU16::new(MAX_SPEAKERS).unwrap();
                 options.threshold = -70;
-                options.interval = 1;
+                options.interval = VOLUME_INTERVAL;
                 options
             })
             .await
-            .unwrap();
-
-        // al_observer.on_volumes(|volumes| {
-        //     for volume in volumes {
-        //         info!("Volume of {}: {} dB", volume.producer.id(), volume.volume);
-        //     }
-        // }).detach();
+            .map_err(|e| {
+                error!(
+                    "channel[{:?}]: creating audio level observer failed: {}",
+                    id, e
+                );
+                VoicePoolError::RouterCreateFailed
+            })?;
 
-        Self {
+        Ok(Self {
             id: id.to_owned(),
+            guild_id,
             clients: Mutex::new(Vec::new()),
             router,
             webrtc_server,
+            load,
+            consumer_routers: Mutex::new(Vec::new()),
+            pipes: Mutex::new(HashMap::new()),
             al_observer,
-        }
+            recording: Mutex::new(None),
+            pubsub,
+            vwp,
+        })
     }
 
     pub async fn erase_client(
@@ -74,7 +117,22 @@ impl VoiceChannel {
         let removed = global_clients.lock().unwrap().remove(client_identity);
 
         match removed {
-            Some(r) => r.cleanup(),
+            Some(r) => {
+                r.cleanup();
+
+                // the client's producers close along with it, but their
+                // copies on the consumer routers are held here
+                let producer_ids: Vec<ProducerId> = r
+                    .producers
+                    .lock()
+                    .unwrap()
+                    .values()
+                    .map(|producer| producer.id())
+                    .collect();
+                for producer_id in producer_ids {
+                    self.unpipe_producer(producer_id).await;
+                }
+            }
             None => {
                 // this should never happen in theory
                 warn!(
@@ -86,9 +144,14 @@ impl VoiceChannel {
 
         // if the channel is empty, remove it from the global channels map
         if self.clients.lock().await.is_empty() {
+            // nobody is left to record
+            if let Some(recording) = self.stop_recording().await {
+                actix_rt::spawn(recording.finish());
+            }
+
             // at this point the only reference to this channel is the one in the channels map
             // so we can safely remove it from the map
-            global_channels.lock().unwrap().remove(&self.id);
+            self.remove_from(global_channels);
         }
 
         println!("{:?}", global_channels.lock().unwrap().len());
@@ -110,15 +173,182 @@ impl VoiceChannel {
         self.erase_client(&client.identity, global_clients, global_channels)
             .await;
         self.notify_client_left(client).await;
+        self.pubsub
+            .notify_voice_state_update(&self.guild_id, None, &client.member_state())
+            .await;
+    }
+
+    /// The source of a producer of anyone in the channel
+    pub async fn producer_source(&self, producer_id: &str) -> Option<ProducerSource> {
+        self.clients.lock().await.iter().find_map(|client| {
+            client
+                .producers
+                .lock()
+                .unwrap()
+                .get(producer_id)
+                .map(ProducerSource::of)
+        })
     }
 
-    pub async fn create_webrtc_transport(&self) -> Result<WebRtcTransport, RequestError> {
-        self.router
+    pub async fn create_webrtc_transport(
+        &self,
+        router: &PoolRouter,
+    ) -> Result<WebRtcTransport, RequestError> {
+        router
+            .router
             .create_webrtc_transport(options::webrtc_transport_options(
-                self.webrtc_server.clone(),
+                router.webrtc_server.clone(),
             ))
             .await
     }
+
+    pub async fn create_plain_transport(
+        &self,
+        router: &PoolRouter,
+        comedia: bool,
+    ) -> Result<PlainTransport, RequestError> {
+        router
+            .router
+            .create_plain_transport(options::plain_transport_options(comedia))
+            .await
+    }
+
+    /// The router the channel's producers are on
+    pub fn main_router(&self) -> PoolRouter {
+        PoolRouter {
+            router: self.router.clone(),
+            webrtc_server: self.webrtc_server.clone(),
+            load: self.load.clone(),
+        }
+    }
+
+    /// Picks the router for someone who is about to start listening, which is
+    /// the channel's least busy one. When all of them are busy, the channel
+    /// is spread to another worker if there is one with room to spare.
+    pub async fn pick_consumer_router(&self) -> PoolRouter {
+        let main = self.main_router();
+        let mut routers = self.consumer_routers.lock().await;
+
+        let least_loaded = std::iter::once(&main)
+            .chain(routers.iter())
+            .min_by_key(|router| router.load.get())
+            .unwrap() // there's always the main router
+            .clone();
+
+        let load = least_loaded.load.get();
+        if load < *options::WRTC_SPREAD_CONSUMERS {
+            return least_loaded;
+        }
+
+        let used: Vec<WorkerId> = std::iter::once(&main)
+            .chain(routers.iter())
+            .map(PoolRouter::worker_id)
+            .collect();
+
+        let extra = self
+            .vwp
+            .lock()
+            .await
+            .allocate_extra_router(&used, load)
+            .await;
+
+        match extra {
+            Ok(Some(router)) => {
+                info!(
+                    "channel[{:?}]: spread to worker {}, now on {}",
+                    self.id,
+                    router.worker_id(),
+                    routers.len() + 2
+                );
+                routers.push(router.clone());
+                router
+            }
+            // every worker is at least as busy
+            Ok(None) => least_loaded,
+            Err(e) => {
+                warn!(
+                    "channel[{:?}]: spreading to another worker failed: {}",
+                    self.id, e
+                );
+                least_loaded
+            }
+        }
+    }
+
+    /// Makes a producer consumable on another of the channel's routers. The
+    /// pipes are held until `unpipe_producer` is called for the producer.
+    pub async fn pipe_producer_to(
+        &self,
+        producer_id: ProducerId,
+        router: &Router,
+    ) -> Result<(), PipeProducerToRouterError> {
+        if router.id() == self.router.id() {
+            return Ok(());
+        }
+
+        let mut pipes = self.pipes.lock().await;
+
+        // forget about the pipes of producers that were closed since
+        pipes.retain(|_, pair| !pair.pipe_consumer.closed());
+
+        let key = (producer_id, router.id());
+        if pipes.contains_key(&key) {
+            return Ok(());
+        }
+
+        let pair = self
+            .router
+            .pipe_producer_to_router(producer_id, PipeToRouterOptions::new(router.clone()))
+            .await?;
+
+        pipes.insert(key, pair);
+
+        Ok(())
+    }
+
+    /// Closes a producer's copies on the consumer routers, and with them
+    /// everyone's consumers of it there
+    pub async fn unpipe_producer(&self, producer_id: ProducerId) {
+        self.pipes
+            .lock()
+            .await
+            .retain(|(id, _), _| *id != producer_id);
+    }
+
+    /// Drops the consumer routers on a worker that died, and the pipes to
+    /// them. Returns whether the channel's own router was on it, in which
+    /// case nothing the channel has works anymore.
+    pub async fn forget_worker(&self, worker_id: WorkerId) -> bool {
+        let main_died = self.router.worker().id() == worker_id;
+
+        let mut routers = self.consumer_routers.lock().await;
+        let dead: Vec<RouterId> = routers
+            .iter()
+            .filter(|router| router.worker_id() == worker_id)
+            .map(|router| router.router.id())
+            .collect();
+        routers.retain(|router| router.worker_id() != worker_id);
+
+        // every pipe starts at the channel's own router
+        self.pipes
+            .lock()
+            .await
+            .retain(|(_, router_id), _| !main_died && !dead.contains(router_id));
+
+        main_died
+    }
+
+    /// Removes the channel from the channels map, unless someone rejoining
+    /// already put a new one with the same id there
+    pub fn remove_from(&self, global_channels: &VoiceChannels) {
+        let mut channels = global_channels.lock().unwrap();
+        if channels
+            .get(&self.id)
+            .is_some_and(|channel| std::ptr::eq(channel.as_ref(), self))
+        {
+            channels.remove(&self.id);
+        }
+    }
 }
 
 impl Drop for VoiceChannel {
@@ -129,16 +359,178 @@ impl Drop for VoiceChannel {
 
 pub async fn create_channel(
     id: &str,
+    guild_id: &str,
     channels: Data<VoiceChannels>,
-    vwp: &Mutex<VoiceWorkerPool>,
-) -> Arc<VoiceChannel> {
-    let channel = VoiceChannel::new_with_id(id.to_owned(), vwp).await;
+    vwp: Data<Mutex<VoiceWorkerPool>>,
+    pubsub: Data<PubSub>,
+) -> Result<Arc<VoiceChannel>, VoicePoolError> {
+    let channel =
+        VoiceChannel::new_with_id(id.to_owned(), guild_id.to_owned(), vwp, pubsub).await?;
     let channel = Arc::new(channel);
 
+    watch_speakers(&channel);
+
     let mut channels = channels.lock().unwrap();
     channels.insert(channel.id.clone(), channel.clone());
 
     info!("channel[{:?}]: created", channel.id);
 
-    channel
+    Ok(channel)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        net::UdpSocket,
+        num::{NonZeroU32, NonZeroU8},
+        time::Duration,
+    };
+
+    use mediasoup::{
+        consumer::{Consumer, ConsumerOptions},
+        direct_transport::DirectTransportOptions,
+        producer::{Producer, ProducerOptions},
+        router::Router,
+        rtp_parameters::{
+            MediaKind, MimeTypeAudio, RtcpParameters, RtpCodecParameters,
+            RtpCodecParametersParameters, RtpEncodingParameters, RtpParameters,
+        },
+        transport::Transport,
+        worker_manager::WorkerManager,
+    };
+    use tokio::sync::oneshot;
+
+    use super::*;
+
+    async fn channel() -> VoiceChannel {
+        // a free port for the channel's webrtc server
+        let port = UdpSocket::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .port();
+
+        let vwp = VoiceWorkerPool::new(WorkerManager::new(), vec![port]);
+
+        VoiceChannel::new_with_id(
+            "channel".to_string(),
+            "guild".to_string(),
+            Data::new(Mutex::new(vwp)),
+            Data::new(PubSub::new()),
+        )
+        .await
+        .unwrap()
+    }
+
+    async fn produce_audio(router: &Router) -> Producer {
+        let transport = router
+            .create_direct_transport(DirectTransportOptions::default())
+            .await
+            .unwrap();
+
+        transport
+            .produce(ProducerOptions::new(
+                MediaKind::Audio,
+                RtpParameters {
+                    mid: None,
+                    codecs: vec![RtpCodecParameters::Audio {
+                        mime_type: MimeTypeAudio::Opus,
+                        payload_type: 100,
+                        clock_rate: NonZeroU32::new(48000).unwrap(),
+                        channels: NonZeroU8::new(2).unwrap(),
+                        parameters: RtpCodecParametersParameters::default(),
+                        rtcp_feedback: vec![],
+                    }],
+                    header_extensions: vec![],
+                    encodings: vec![RtpEncodingParameters {
+                        ssrc: Some(1111),
+                        ..RtpEncodingParameters::default()
+                    }],
+                    rtcp: RtcpParameters::default(),
+                },
+            ))
+            .await
+            .unwrap()
+    }
+
+    async fn consume(router: &Router, producer_id: ProducerId) -> Consumer {
+        let transport = router
+            .create_direct_transport(DirectTransportOptions::default())
+            .await
+            .unwrap();
+
+        transport
+            .consume(ConsumerOptions::new(
+                producer_id,
+                options::recording_rtp_capabilities(),
+            ))
+            .await
+            .unwrap()
+    }
+
+    /// Whether `on_close` fired in time
+    async fn fired(on_close: oneshot::Receiver<()>) -> bool {
+        tokio::time::timeout(Duration::from_secs(1), on_close)
+            .await
+            .is_ok()
+    }
+
+    #[actix_rt::test]
+    async fn dropping_the_only_handle_closes_a_producer() {
+        let channel = channel().await;
+        let producer = produce_audio(&channel.router).await;
+        let consumer = consume(&channel.router, producer.id()).await;
+
+        let (producer_closed, producer_on_close) = oneshot::channel();
+        producer
+            .on_close(move || {
+                let _ = producer_closed.send(());
+            })
+            .detach();
+
+        let (consumer_closed, consumer_on_close) = oneshot::channel();
+        consumer
+            .on_close(move || {
+                let _ = consumer_closed.send(());
+            })
+            .detach();
+
+        drop(producer);
+
+        assert!(fired(producer_on_close).await);
+        assert!(fired(consumer_on_close).await);
+        assert!(consumer.closed());
+    }
+
+    #[actix_rt::test]
+    async fn unpiping_a_producer_closes_its_consumers_on_other_routers() {
+        let channel = channel().await;
+        let other_router = channel
+            .router
+            .worker()
+            .create_router(options::router_options())
+            .await
+            .unwrap();
+
+        let producer = produce_audio(&channel.router).await;
+        let producer_id = producer.id();
+        channel
+            .pipe_producer_to(producer_id, &other_router)
+            .await
+            .unwrap();
+        let consumer = consume(&other_router, producer_id).await;
+
+        let (consumer_closed, consumer_on_close) = oneshot::channel();
+        consumer
+            .on_close(move || {
+                let _ = consumer_closed.send(());
+            })
+            .detach();
+
+        drop(producer);
+        channel.unpipe_producer(producer_id).await;
+
+        assert!(fired(consumer_on_close).await);
+        assert!(consumer.closed());
+    }
 }
@@ -4,11 +4,11 @@ use actix_web::{
     FromRequest,
 };
 use futures::Future;
-use log::info;
-use mediasoup::{prelude::Consumer, producer::Producer, webrtc_transport::WebRtcTransport};
+use log::{info, warn};
+use mediasoup::{prelude::Consumer, producer::Producer, rtp_parameters::MediaKind};
 use nanoid::nanoid;
 use std::{
-    collections::HashMap,
+    collections::{HashMap, HashSet},
     ops::Deref,
     pin::Pin,
     sync::{Arc, Mutex},
@@ -18,7 +18,13 @@ use tokio::sync::RwLock;
 use crate::{
     auth::{access_token::AccessToken, user::User},
     error::{macros::err, HResult, HandlerError, IntoHandlerErrorResult},
-    voice::{channel::VoiceChannel, MutexMap, VoiceClients},
+    voice::{
+        channel::VoiceChannel,
+        pool::PoolRouter,
+        state::{VoiceMemberState, VoiceState},
+        transport::ClientTransport,
+        MutexMap, VoiceClients,
+    },
 };
 use crate::{realtime::socket::Socket, util::constant_time_compare};
 
@@ -29,17 +35,27 @@ pub struct VoiceClient {
     pub token: String,
     pub channel: Arc<VoiceChannel>,
     // c2s
-    pub c2s_transport: RwLock<Option<WebRtcTransport>>,
+    pub c2s_transport: RwLock<Option<ClientTransport>>,
     pub producers: MutexMap<Producer>,
     // s2c
-    pub s2c_transport: RwLock<Option<WebRtcTransport>>,
+    pub s2c_transport: RwLock<Option<ClientTransport>>,
     pub consumers: MutexMap<Consumer>,
+    // the router the s2c transport is on, which isn't necessarily the
+    // channel's own
+    pub consumer_router: Mutex<Option<PoolRouter>>,
+    // producers and consumers the client paused itself, which lifting a
+    // server mute or deafen must leave paused
+    pub paused_producers: Mutex<HashSet<String>>,
+    pub paused_consumers: Mutex<HashSet<String>>,
 
     pub socket: tokio::sync::RwLock<Option<Arc<Socket>>>,
     // this is used to cancel the initial connect watch task
     pub socket_initial_connect_watch_handle: Mutex<Option<JoinHandle<()>>>,
+    // this disconnects the client if its socket isn't connected again in time
+    pub socket_reconnect_watch_handle: Mutex<Option<JoinHandle<()>>>,
     // the user that this client belongs to
     pub user: User,
+    pub state: Mutex<VoiceState>,
 }
 
 impl VoiceClient {
@@ -52,12 +68,152 @@ impl VoiceClient {
             producers: HashMap::new().into(),
             s2c_transport: None.into(),
             consumers: HashMap::new().into(),
+            consumer_router: Mutex::new(None),
+            paused_producers: Mutex::new(HashSet::new()),
+            paused_consumers: Mutex::new(HashSet::new()),
             socket: None.into(),
             socket_initial_connect_watch_handle: Mutex::new(None),
+            socket_reconnect_watch_handle: Mutex::new(None),
             user,
+            state: Mutex::new(VoiceState::default()),
         }
     }
 
+    /// Pauses or resumes the client's audio producers and its consumers to
+    /// match what moderators set its server mute and deafen to. Whatever the
+    /// client paused itself, or muted or deafened itself for, stays paused.
+    pub async fn apply_server_state(&self) {
+        let state = *self.state.lock().unwrap();
+
+        let producers: Vec<Producer> = self
+            .producers
+            .lock()
+            .unwrap()
+            .values()
+            .filter(|producer| producer.kind() == MediaKind::Audio)
+            .cloned()
+            .collect();
+
+        for producer in producers {
+            let id = producer.id().to_string();
+            let paused_by_client =
+                state.muted || self.paused_producers.lock().unwrap().contains(&id);
+            let should_pause = state.server_muted;
+
+            // only undo what a server mute did
+            if producer.paused() == should_pause || (!should_pause && paused_by_client) {
+                continue;
+            }
+
+            let result = if should_pause {
+                producer.pause().await
+            } else {
+                producer.resume().await
+            };
+
+            if let Err(e) = result {
+                warn!(
+                    "client[{:?}]: server mute of producer {} failed: {}",
+                    self.identity, id, e
+                );
+                continue;
+            }
+
+            // peers are told the same as when the client pauses it itself
+            let event_type = if should_pause {
+                "producer_paused"
+            } else {
+                "producer_resumed"
+            };
+            self.channel
+                .notify_producer_update(self, event_type, &id)
+                .await;
+        }
+
+        let consumers: Vec<Consumer> = self.consumers.lock().unwrap().values().cloned().collect();
+
+        for consumer in consumers {
+            let id = consumer.id().to_string();
+            let paused_by_client =
+                state.deafened || self.paused_consumers.lock().unwrap().contains(&id);
+            let should_pause = state.server_deafened;
+
+            if consumer.paused() == should_pause || (!should_pause && paused_by_client) {
+                continue;
+            }
+
+            let result = if should_pause {
+                consumer.pause().await
+            } else {
+                consumer.resume().await
+            };
+
+            if let Err(e) = result {
+                warn!(
+                    "client[{:?}]: server deafen of consumer {} failed: {}",
+                    self.identity, id, e
+                );
+            }
+        }
+    }
+
+    /// Adds a producer to the client. Once mediasoup closes it, for example
+    /// because its transport closed, it is taken out again.
+    pub fn add_producer(self: &Arc<Self>, producer: Producer) {
+        let id = producer.id().to_string();
+        let weak_client = Arc::downgrade(self);
+
+        {
+            let id = id.clone();
+            producer
+                .on_close(move || {
+                    if let Some(client) = weak_client.upgrade() {
+                        client.producers.lock().unwrap().remove(&id);
+                        client.paused_producers.lock().unwrap().remove(&id);
+                    }
+                })
+                .detach();
+        }
+
+        self.producers.lock().unwrap().insert(id, producer);
+    }
+
+    /// Adds a consumer to the client. Once mediasoup closes it, for example
+    /// because the producer it consumes was closed, it is taken out again.
+    pub fn add_consumer(self: &Arc<Self>, consumer: Consumer) {
+        let id = consumer.id().to_string();
+        let weak_client = Arc::downgrade(self);
+
+        {
+            let id = id.clone();
+            consumer
+                .on_close(move || {
+                    if let Some(client) = weak_client.upgrade() {
+                        client.consumers.lock().unwrap().remove(&id);
+                        client.paused_consumers.lock().unwrap().remove(&id);
+                    }
+                })
+                .detach();
+        }
+
+        self.consumers.lock().unwrap().insert(id, consumer);
+    }
+
+    pub fn member_state(&self) -> VoiceMemberState {
+        VoiceMemberState {
+            identity: self.identity.clone(),
+            user: self.user.clone().into(),
+            state: *self.state.lock().unwrap(),
+        }
+    }
+
+    /// Whether the client has connected its event socket. Clients that lost it
+    /// and may still reconnect within the grace period count as connected.
+    pub async fn is_connected(&self) -> bool {
+        let reconnecting = self.socket_reconnect_watch_handle.lock().unwrap().is_some();
+        reconnecting || self.socket.read().await.is_some()
+    }
+
     pub fn cleanup(&self) {
         if let Some(handle) = self
             .socket_initial_connect_watch_handle
@@ -67,6 +223,10 @@ impl VoiceClient {
         {
             handle.abort();
         }
+
+        if let Some(handle) = self.socket_reconnect_watch_handle.lock().unwrap().take() {
+            handle.abort();
+        }
     }
 }
 
@@ -101,13 +261,16 @@ impl FromRequest for VoiceClientEx {
         Box::pin(async move {
             let trying_to_connect_to_ws = req.path() == "/voice/ws" && req.method() == "GET";
 
-            if !trying_to_connect_to_ws {
-                // validate session
-                AccessToken::from_request(&req, &mut actix_web::dev::Payload::None).await?;
-
-                // todo: a bunch of logic & checks here to make sure the user is allowed to do operations in the channel
-                //       use the return value of the above line to get the user
-            }
+            // the user the request is made as, checked against the client's
+            // owner below. whether they could join the channel at all was
+            // already checked when they joined.
+            let token_user_id = if trying_to_connect_to_ws {
+                None
+            } else {
+                let token =
+                    AccessToken::from_request(&req, &mut actix_web::dev::Payload::None).await?;
+                Some(token.user_id.clone())
+            };
 
             let rtc_identity;
             let rtc_token;
@@ -171,6 +334,11 @@ impl FromRequest for VoiceClientEx {
                 return err!(403)?;
             }
 
+            // someone else's rtc credentials are no good
+            if token_user_id.is_some_and(|id| id != client.user.id) {
+                return err!(403)?;
+            }
+
             if !trying_to_connect_to_ws && client.socket.read().await.is_none() {
                 return err!(400, "You need to connect to the voice event socket first.")?;
             }
@@ -6,8 +6,13 @@ use self::{channel::VoiceChannel, client::VoiceClient};
 
 pub mod channel;
 pub mod client;
+pub mod ogg;
 pub mod pool;
+pub mod recording;
 pub mod routes;
+pub mod signalling;
+pub mod speaking;
+pub mod state;
 pub mod transport;
 
 pub type VoiceClients = MutexMap<Arc<VoiceClient>>;
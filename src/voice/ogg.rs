@@ -0,0 +1,211 @@
+//! Just enough of Ogg (RFC 3533) and Ogg Opus (RFC 7845) to write the Opus
+//! packets of an RTP stream into a file that players understand.
+
+use sha2::{Digest, Sha256};
+use tokio::{fs::File, io::AsyncWriteExt};
+
+/// Opus always counts time in 48 kHz samples, whatever was actually recorded
+pub const SAMPLE_RATE: u32 = 48000;
+
+/// 20 ms of silence in a single CELT frame
+const SILENCE: [u8; 3] = [0xf8, 0xff, 0xfe];
+const SILENCE_SAMPLES: u64 = 960;
+
+/// Packets are collected into pages of about a second each
+const PACKETS_PER_PAGE: usize = 50;
+
+const FLAG_BEGINNING_OF_STREAM: u8 = 0x02;
+const FLAG_END_OF_STREAM: u8 = 0x04;
+
+pub struct OggOpusWriter {
+    file: File,
+    serial: u32,
+    sequence: u32,
+    /// Samples in all the packets written so far
+    granule: u64,
+    packets: Vec<Vec<u8>>,
+    size: u64,
+    hash: Sha256,
+}
+
+impl OggOpusWriter {
+    /// Starts a new file with the Opus headers
+    pub async fn new(file: File, channels: u8) -> std::io::Result<Self> {
+        let mut writer = Self {
+            file,
+            serial: rand::random(),
+            sequence: 0,
+            granule: 0,
+            packets: Vec::with_capacity(PACKETS_PER_PAGE),
+            size: 0,
+            hash: Sha256::new(),
+        };
+
+        let mut head = Vec::with_capacity(19);
+        head.extend_from_slice(b"OpusHead");
+        head.push(1); // version
+        head.push(channels);
+        head.extend_from_slice(&0u16.to_le_bytes()); // pre-skip
+        head.extend_from_slice(&SAMPLE_RATE.to_le_bytes());
+        head.extend_from_slice(&0i16.to_le_bytes()); // output gain
+        head.push(0); // mono or stereo, no mapping table
+
+        let vendor = b"zling";
+        let mut tags = Vec::with_capacity(20);
+        tags.extend_from_slice(b"OpusTags");
+        tags.extend_from_slice(&(vendor.len() as u32).to_le_bytes());
+        tags.extend_from_slice(vendor);
+        tags.extend_from_slice(&0u32.to_le_bytes()); // no comments
+
+        // both headers go on pages of their own
+        writer
+            .write_page(&[head], 0, FLAG_BEGINNING_OF_STREAM)
+            .await?;
+        writer.write_page(&[tags], 0, 0).await?;
+
+        Ok(writer)
+    }
+
+    pub async fn write_packet(&mut self, packet: &[u8]) -> std::io::Result<()> {
+        // a page can only have 255 segments, and packets over 255 bytes take
+        // more than one. Opus packets sent over RTP are hardly ever that big.
+        let segments: usize = self.packets.iter().map(|p| segments_of(p)).sum();
+        if segments + segments_of(packet) > 255 {
+            self.flush(0).await?;
+        }
+
+        self.granule += packet_samples(packet);
+        self.packets.push(packet.to_vec());
+
+        if self.packets.len() >= PACKETS_PER_PAGE {
+            self.flush(0).await?;
+        }
+
+        Ok(())
+    }
+
+    /// Fills the time until `position` with silence
+    pub async fn write_silence_until(&mut self, position: u64) -> std::io::Result<()> {
+        while self.granule + SILENCE_SAMPLES <= position {
+            self.write_packet(&SILENCE).await?;
+        }
+
+        Ok(())
+    }
+
+    /// Writes the last page and returns the size and SHA-256 of the file
+    pub async fn finish(mut self) -> std::io::Result<(u64, String)> {
+        if self.packets.is_empty() {
+            // the stream needs to end on a page with a packet
+            self.packets.push(SILENCE.to_vec());
+            self.granule += SILENCE_SAMPLES;
+        }
+
+        self.flush(FLAG_END_OF_STREAM).await?;
+        self.file.flush().await?;
+
+        Ok((self.size, hex::encode(self.hash.finalize())))
+    }
+
+    async fn flush(&mut self, flags: u8) -> std::io::Result<()> {
+        let packets = std::mem::take(&mut self.packets);
+        self.write_page(&packets, self.granule, flags).await
+    }
+
+    async fn write_page(
+        &mut self,
+        packets: &[Vec<u8>],
+        granule: u64,
+        flags: u8,
+    ) -> std::io::Result<()> {
+        let mut lacing = Vec::with_capacity(packets.iter().map(|p| segments_of(p)).sum());
+        for packet in packets {
+            lacing.extend(std::iter::repeat(255).take(packet.len() / 255));
+            lacing.push((packet.len() % 255) as u8);
+        }
+
+        let mut page =
+            Vec::with_capacity(27 + lacing.len() + packets.iter().map(Vec::len).sum::<usize>());
+        page.extend_from_slice(b"OggS");
+        page.push(0); // version
+        page.push(flags);
+        page.extend_from_slice(&granule.to_le_bytes());
+        page.extend_from_slice(&self.serial.to_le_bytes());
+        page.extend_from_slice(&self.sequence.to_le_bytes());
+        page.extend_from_slice(&[0; 4]); // checksum, filled in below
+        page.push(lacing.len() as u8);
+        page.extend_from_slice(&lacing);
+        for packet in packets {
+            page.extend_from_slice(packet);
+        }
+
+        let checksum = crc32(&page);
+        page[22..26].copy_from_slice(&checksum.to_le_bytes());
+
+        self.file.write_all(&page).await?;
+        self.hash.update(&page);
+        self.size += page.len() as u64;
+        self.sequence += 1;
+
+        Ok(())
+    }
+}
+
+/// How many lacing values a packet takes up on a page
+fn segments_of(packet: &[u8]) -> usize {
+    packet.len() / 255 + 1
+}
+
+/// How many samples an Opus packet decodes to, from its TOC byte (RFC 6716
+/// section 3.1)
+pub fn packet_samples(packet: &[u8]) -> u64 {
+    let Some(toc) = packet.first() else {
+        return 0;
+    };
+
+    let config = toc >> 3;
+    let frame_samples = match config {
+        // SILK, 10, 20, 40 or 60 ms
+        0..=11 => [480, 960, 1920, 2880][config as usize % 4],
+        // hybrid, 10 or 20 ms
+        12..=15 => [480, 960][config as usize % 2],
+        // CELT, 2.5, 5, 10 or 20 ms
+        _ => [120, 240, 480, 960][config as usize % 4],
+    };
+
+    let frames = match toc & 0x03 {
+        0 => 1,
+        1 | 2 => 2,
+        _ => packet.get(1).map_or(0, |count| count & 0x3f) as u64,
+    };
+
+    frame_samples * frames
+}
+
+/// The CRC Ogg pages are checked with, which unlike the usual CRC-32 isn't
+/// reflected
+fn crc32(data: &[u8]) -> u32 {
+    data.iter().fold(0, |crc, byte| {
+        (crc << 8) ^ CRC_TABLE[((crc >> 24) as u8 ^ byte) as usize]
+    })
+}
+
+const CRC_TABLE: [u32; 256] = {
+    let mut table = [0; 256];
+    let mut i = 0;
+    while i < 256 {
+        let mut crc = (i as u32) << 24;
+        let mut bit = 0;
+        while bit < 8 {
+            crc = if crc & 0x8000_0000 != 0 {
+                (crc << 1) ^ 0x04c1_1db7
+            } else {
+                crc << 1
+            };
+            bit += 1;
+        }
+        table[i] = crc;
+        i += 1;
+    }
+    table
+};
@@ -1,73 +1,332 @@
-use std::io;
+use std::sync::{
+    atomic::{AtomicUsize, Ordering},
+    Arc,
+};
 
+use actix_web::{web::Data, ResponseError};
+use derive_more::{Display, Error};
+use log::{error, info, warn};
 use mediasoup::{
-    router::Router, webrtc_server::WebRtcServer, worker::Worker, worker_manager::WorkerManager,
+    router::Router,
+    webrtc_server::WebRtcServer,
+    worker::{Worker, WorkerId},
+    worker_manager::WorkerManager,
+};
+use serde_json::json;
+use tokio::sync::{
+    mpsc::{self, UnboundedReceiver, UnboundedSender},
+    Mutex,
 };
 
 use crate::options;
 
+use super::{VoiceChannels, VoiceClients};
+
+/// The pool couldn't provide a router, so voice chat is unavailable for now.
+/// What went wrong is logged where it happens.
+#[derive(Debug, Display, Error)]
+#[display(rename_all = "snake_case")]
+pub enum VoicePoolError {
+    WorkerCreateFailed,
+    WebRtcServerCreateFailed,
+    RouterCreateFailed,
+    NoWorkers,
+}
+
+impl ResponseError for VoicePoolError {
+    fn status_code(&self) -> actix_web::http::StatusCode {
+        actix_web::http::StatusCode::SERVICE_UNAVAILABLE
+    }
+}
+
+/// How many consumers a worker is forwarding media to. New rooms and
+/// consumers go to the worker with the least of them.
+#[derive(Clone, Default)]
+pub struct WorkerLoad(Arc<AtomicUsize>);
+
+impl WorkerLoad {
+    pub fn get(&self) -> usize {
+        self.0.load(Ordering::Relaxed)
+    }
+
+    pub fn add_consumer(&self) {
+        self.0.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn remove_consumer(&self) {
+        self.0.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+/// A router on one of the pool's workers, along with what's needed to create
+/// transports on it
+#[derive(Clone)]
+pub struct PoolRouter {
+    pub router: Router,
+    pub webrtc_server: WebRtcServer,
+    pub load: WorkerLoad,
+}
+
+impl PoolRouter {
+    pub fn worker_id(&self) -> WorkerId {
+        self.router.worker().id()
+    }
+}
+
+struct PoolWorker {
+    worker: Worker,
+    webrtc_server: WebRtcServer,
+    load: WorkerLoad,
+    port: u16,
+}
+
 pub struct VoiceWorkerPool {
     worker_manager: WorkerManager,
-    workers: Vec<(Worker, WebRtcServer)>,
-    // must be a valid index into workers
-    next_worker: usize,
-    // must have at least 1 port
+    workers: Vec<PoolWorker>,
+    // ports that don't have a worker yet
     // this will get popped from the end, so preferably put lower ports last
     ports: Vec<u16>,
+    // workers that died are sent here, see `recover_dead_workers`
+    dead_workers: UnboundedSender<WorkerId>,
+    dead_workers_receiver: Option<UnboundedReceiver<WorkerId>>,
 }
 impl VoiceWorkerPool {
     pub fn new(worker_manager: WorkerManager, ports: Vec<u16>) -> Self {
+        let (dead_workers, dead_workers_receiver) = mpsc::unbounded_channel();
+
         Self {
             worker_manager,
             workers: vec![],
-            next_worker: 0,
             ports,
+            dead_workers,
+            dead_workers_receiver: Some(dead_workers_receiver),
         }
     }
 
-    pub async fn allocate_router(&mut self) -> io::Result<(Router, WebRtcServer)> {
-        let worker;
-        let webrtc_server;
+    /// Where the ids of workers that died are sent. Can only be taken once.
+    pub fn take_dead_workers(&mut self) -> Option<UnboundedReceiver<WorkerId>> {
+        self.dead_workers_receiver.take()
+    }
 
-        if self.workers.len() < self.ports.len() {
+    /// Creates a router for a new room, on a new worker if there are ports left
+    /// for one, otherwise on the least busy worker.
+    pub async fn allocate_router(&mut self) -> Result<PoolRouter, VoicePoolError> {
+        let worker = if !self.ports.is_empty() {
             // there are still unallocated ports
-            (worker, webrtc_server) = self.create_worker_and_server().await?;
+            self.create_worker_and_server().await?
         } else {
-            (worker, webrtc_server) = self.get_existing_worker_and_server();
+            self.least_loaded_worker(&[])
+                .ok_or(VoicePoolError::NoWorkers)?
+        };
+
+        Self::create_router(worker).await
+    }
+
+    /// Creates another router for a room that is busy, on a worker the room
+    /// isn't using yet. Only a worker that is less busy than `load` is worth
+    /// moving to, if there isn't one `None` is returned.
+    pub async fn allocate_extra_router(
+        &mut self,
+        used: &[WorkerId],
+        load: usize,
+    ) -> Result<Option<PoolRouter>, VoicePoolError> {
+        let worker = if !self.ports.is_empty() {
+            self.create_worker_and_server().await?
+        } else {
+            match self.least_loaded_worker(used) {
+                Some(worker) if worker.load.get() < load => worker,
+                _ => return Ok(None),
+            }
+        };
+
+        Self::create_router(worker).await.map(Some)
+    }
+
+    /// Replaces a worker that died with a new one on the same port. Routers on
+    /// the dead worker stay dead, whoever was using them has to start over.
+    pub async fn respawn(&mut self, worker_id: WorkerId) -> Result<(), VoicePoolError> {
+        let Some(index) = self
+            .workers
+            .iter()
+            .position(|worker| worker.worker.id() == worker_id)
+        else {
+            // already replaced
+            return Ok(());
         };
 
+        let dead = self.workers.remove(index);
+        self.ports.push(dead.port);
+
+        self.create_worker_and_server().await?;
+
+        info!(
+            "voice worker on port {} respawned after {} died",
+            dead.port, worker_id
+        );
+
+        Ok(())
+    }
+
+    async fn create_router(worker: &PoolWorker) -> Result<PoolRouter, VoicePoolError> {
         let router = worker
+            .worker
             .create_router(options::router_options())
             .await
-            .unwrap();
+            .map_err(|e| {
+                error!(
+                    "creating router on worker {} failed: {}",
+                    worker.worker.id(),
+                    e
+                );
+                VoicePoolError::RouterCreateFailed
+            })?;
 
-        Ok((router, webrtc_server))
+        Ok(PoolRouter {
+            router,
+            webrtc_server: worker.webrtc_server.clone(),
+            load: worker.load.clone(),
+        })
     }
-    async fn create_worker_and_server(&mut self) -> io::Result<(&Worker, WebRtcServer)> {
-        let worker = self
+
+    async fn create_worker_and_server(&mut self) -> Result<&PoolWorker, VoicePoolError> {
+        // the port goes back if anything fails, so it can be tried again later
+        let port = self.ports.pop().ok_or(VoicePoolError::NoWorkers)?;
+
+        let worker = match self
             .worker_manager
             .create_worker(options::worker_settings())
-            .await?;
+            .await
+        {
+            Ok(worker) => worker,
+            Err(e) => {
+                error!("creating voice worker failed: {}", e);
+                self.ports.push(port);
+                return Err(VoicePoolError::WorkerCreateFailed);
+            }
+        };
 
         // create a new webrtc server
-        let port = self.ports.pop().unwrap();
         let opts = options::webrtc_server_options(port);
-        let server = worker.create_webrtc_server(opts).await.unwrap(); // todo: handle error here possibly
+        let server = match worker.create_webrtc_server(opts).await {
+            Ok(server) => server,
+            Err(e) => {
+                error!("creating webrtc server on port {} failed: {}", port, e);
+                self.ports.push(port);
+                return Err(VoicePoolError::WebRtcServerCreateFailed);
+            }
+        };
+
+        {
+            let worker_id = worker.id();
+            let dead_workers = self.dead_workers.clone();
+            worker
+                .on_dead(move |reason| {
+                    error!("voice worker {} died: {:?}", worker_id, reason);
+                    dead_workers.send(worker_id).ok();
+                })
+                .detach();
+        }
 
-        self.workers.push((worker, server));
+        self.workers.push(PoolWorker {
+            worker,
+            webrtc_server: server,
+            load: WorkerLoad::default(),
+            port,
+        });
 
-        let (worker, server) = self.workers.last().unwrap();
-        Ok((worker, server.clone()))
+        Ok(self.workers.last().unwrap())
     }
 
-    fn get_existing_worker_and_server(&mut self) -> (&Worker, WebRtcServer) {
-        let (worker, server) = &self.workers[self.next_worker];
+    fn least_loaded_worker(&self, except: &[WorkerId]) -> Option<&PoolWorker> {
+        self.workers
+            .iter()
+            .filter(|worker| !except.contains(&worker.worker.id()))
+            .min_by_key(|worker| worker.load.get())
+    }
+}
+
+/// Respawns workers that die and tells everyone whose media went through them
+/// to join again with a `reconnect` event. Runs until the server stops.
+pub async fn recover_dead_workers(
+    mut dead_workers: UnboundedReceiver<WorkerId>,
+    vwp: Data<Mutex<VoiceWorkerPool>>,
+    clients: Data<VoiceClients>,
+    channels: Data<VoiceChannels>,
+) {
+    while let Some(worker_id) = dead_workers.recv().await {
+        // respawn first, so there's somewhere to reconnect to
+        if let Err(e) = vwp.lock().await.respawn(worker_id).await {
+            warn!("respawning voice worker {} failed: {}", worker_id, e);
+        }
+
+        let all_channels: Vec<_> = channels.lock().unwrap().values().cloned().collect();
+        // channels lock is released here
+
+        let event = json!({ "type": "reconnect" }).to_string();
+
+        for channel in all_channels {
+            let whole_channel = channel.forget_worker(worker_id).await;
+
+            // whoever joins again gets a new channel on a live worker
+            if whole_channel {
+                channel.remove_from(&channels);
+            }
+
+            let affected: Vec<_> = channel
+                .clients
+                .lock()
+                .await
+                .iter()
+                .filter(|client| {
+                    whole_channel
+                        || client
+                            .consumer_router
+                            .lock()
+                            .unwrap()
+                            .as_ref()
+                            .is_some_and(|router| router.worker_id() == worker_id)
+                })
+                .cloned()
+                .collect();
 
-        self.next_worker += 1;
-        if self.next_worker >= self.workers.len() {
-            self.next_worker = 0;
+            for client in affected {
+                client.send(event.clone()).await.ok();
+                channel
+                    .disconnect_client(&client, &clients, &channels)
+                    .await;
+            }
         }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use actix_web::{http::StatusCode, ResponseError};
+
+    use super::VoicePoolError;
+
+    #[test]
+    fn pool_errors_are_service_unavailable() {
+        for error in [
+            VoicePoolError::WorkerCreateFailed,
+            VoicePoolError::WebRtcServerCreateFailed,
+            VoicePoolError::RouterCreateFailed,
+            VoicePoolError::NoWorkers,
+        ] {
+            assert_eq!(error.status_code(), StatusCode::SERVICE_UNAVAILABLE);
+            assert_eq!(
+                error.error_response().status(),
+                StatusCode::SERVICE_UNAVAILABLE
+            );
+        }
+    }
 
-        (worker, server.clone())
+    #[test]
+    fn pool_errors_are_named_in_snake_case() {
+        assert_eq!(
+            VoicePoolError::RouterCreateFailed.to_string(),
+            "router_create_failed"
+        );
+        assert_eq!(VoicePoolError::NoWorkers.to_string(), "no_workers");
     }
 }
@@ -0,0 +1,415 @@
+use std::{
+    collections::HashSet,
+    error::Error,
+    io,
+    net::{IpAddr, Ipv4Addr},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
+
+use actix_rt::task::JoinHandle;
+use chrono::Utc;
+use log::{info, warn};
+use mediasoup::{
+    consumer::{Consumer, ConsumerOptions},
+    plain_transport::{PlainTransport, PlainTransportRemoteParameters},
+    producer::ProducerId,
+    rtp_parameters::MediaKind,
+    transport::Transport,
+};
+use nanoid::nanoid;
+use serde_json::json;
+use tokio::{fs::File, net::UdpSocket, sync::Mutex};
+
+use crate::{
+    db::DB,
+    media::{
+        record::Media,
+        storage::{self, Storage},
+        util::clean_filename,
+    },
+    options,
+};
+
+use super::{
+    channel::VoiceChannel,
+    client::VoiceClient,
+    ogg::{OggOpusWriter, SAMPLE_RATE},
+    routes::produce::ProducerSource,
+};
+
+/// How often a track checks whether the recording was stopped when nothing is
+/// being said
+const STOP_CHECK_INTERVAL: Duration = Duration::from_millis(500);
+
+/// A recording of a voice channel in progress. Every audio producer in the
+/// channel is consumed over a plain transport to the server itself, and
+/// written into an Ogg Opus file of its own.
+pub struct Recording {
+    pub id: String,
+    pub started_by: String,
+    started_at: Instant,
+    stopping: Arc<AtomicBool>,
+    /// Producers that are already being recorded
+    recorded: std::sync::Mutex<HashSet<ProducerId>>,
+    tracks: Mutex<Vec<JoinHandle<Option<RecordedTrack>>>>,
+    db: DB,
+    storage: Storage,
+}
+
+/// A file a producer was recorded into, before it is put into media storage
+struct RecordedTrack {
+    path: String,
+    user_id: String,
+    username: String,
+    source: ProducerSource,
+    size: u64,
+    sha256: String,
+}
+
+impl Recording {
+    pub fn new(id: String, started_by: String, db: DB, storage: Storage) -> Self {
+        Self {
+            id,
+            started_by,
+            started_at: Instant::now(),
+            stopping: Arc::new(AtomicBool::new(false)),
+            recorded: std::sync::Mutex::new(HashSet::new()),
+            tracks: Mutex::new(Vec::new()),
+            db,
+            storage,
+        }
+    }
+
+    /// Starts recording one of a client's audio producers
+    pub async fn record_producer(
+        &self,
+        channel: &VoiceChannel,
+        client: &VoiceClient,
+        producer_id: ProducerId,
+        source: ProducerSource,
+    ) {
+        if !self.recorded.lock().unwrap().insert(producer_id) {
+            return;
+        }
+
+        match self.start_track(channel, client, producer_id, source).await {
+            Ok(track) => self.tracks.lock().await.push(track),
+            Err(e) => warn!(
+                "recording[{:?}]: recording producer {} failed: {}",
+                self.id, producer_id, e
+            ),
+        }
+    }
+
+    async fn start_track(
+        &self,
+        channel: &VoiceChannel,
+        client: &VoiceClient,
+        producer_id: ProducerId,
+        source: ProducerSource,
+    ) -> Result<JoinHandle<Option<RecordedTrack>>, Box<dyn Error>> {
+        let socket = UdpSocket::bind((IpAddr::V4(Ipv4Addr::LOCALHOST), 0)).await?;
+        let port = socket.local_addr()?.port();
+
+        let transport = channel
+            .router
+            .create_plain_transport(options::recording_transport_options())
+            .await?;
+
+        transport
+            .connect(PlainTransportRemoteParameters {
+                ip: Some(IpAddr::V4(Ipv4Addr::LOCALHOST)),
+                port: Some(port),
+                rtcp_port: None,
+                srtp_parameters: None,
+            })
+            .await?;
+
+        let consumer = transport
+            .consume(ConsumerOptions::new(
+                producer_id,
+                options::recording_rtp_capabilities(),
+            ))
+            .await?;
+
+        tokio::fs::create_dir_all(storage::spool_dir()).await?;
+        let path = format!(
+            "{}/recording_{}_{}.ogg",
+            storage::spool_dir(),
+            self.id,
+            consumer.id()
+        );
+        let writer = OggOpusWriter::new(File::create(&path).await?, 2).await?;
+
+        let user_id = client.user.id.clone();
+        let username = client.user.name.clone();
+        let started_at = self.started_at;
+        let stopping = self.stopping.clone();
+        let recording_id = self.id.clone();
+
+        Ok(actix_rt::spawn(async move {
+            let recorded =
+                record_track(socket, writer, transport, consumer, started_at, stopping).await;
+
+            match recorded {
+                Ok(Some((size, sha256))) => Some(RecordedTrack {
+                    path,
+                    user_id,
+                    username,
+                    source,
+                    size,
+                    sha256,
+                }),
+                Ok(None) => {
+                    tokio::fs::remove_file(&path).await.ok();
+                    None
+                }
+                Err(e) => {
+                    warn!(
+                        "recording[{:?}]: writing track of producer {} failed: {}",
+                        recording_id, producer_id, e
+                    );
+                    tokio::fs::remove_file(&path).await.ok();
+                    None
+                }
+            }
+        }))
+    }
+
+    /// Stops recording and puts the tracks into media storage, where they
+    /// count towards the storage of whoever started the recording
+    pub async fn finish(self: Arc<Self>) {
+        self.stopping.store(true, Ordering::Relaxed);
+
+        let tracks = std::mem::take(&mut *self.tracks.lock().await);
+
+        let mut saved = 0;
+        for track in tracks {
+            let track = match track.await {
+                Ok(Some(track)) => track,
+                Ok(None) => continue,
+                Err(e) => {
+                    warn!("recording[{:?}]: track task failed: {}", self.id, e);
+                    continue;
+                }
+            };
+
+            let path = track.path.clone();
+            match self.save_track(track).await {
+                Ok(()) => saved += 1,
+                Err(e) => {
+                    warn!("recording[{:?}]: saving track failed: {}", self.id, e);
+                    tokio::fs::remove_file(&path).await.ok();
+                }
+            }
+        }
+
+        if let Err(e) = sqlx::query!(
+            "UPDATE voice_recordings SET ended_at = now() WHERE id = $1",
+            self.id
+        )
+        .execute(&self.db.pool)
+        .await
+        {
+            warn!("recording[{:?}]: marking as ended failed: {}", self.id, e);
+        }
+
+        info!("recording[{:?}]: finished with {} tracks", self.id, saved);
+    }
+
+    async fn save_track(&self, track: RecordedTrack) -> Result<(), Box<dyn Error>> {
+        let name = format!("{}_{}.ogg", track.username, track.source.as_str());
+
+        let media = Media {
+            id: nanoid!(),
+            uploader: self.started_by.clone(),
+            name: clean_filename(name).unwrap_or(format!("{}.ogg", track.source.as_str())),
+            size: track.size as i64,
+            sha256: track.sha256,
+            mime_type: "audio/ogg".into(),
+            width: None,
+            height: None,
+            created_at: Utc::now().naive_utc(),
+        };
+
+        self.storage.store_file(&media.key(), &track.path).await?;
+        self.db.insert_media(&media).await?;
+
+        sqlx::query!(
+            r#"INSERT INTO voice_recording_tracks (recording_id, media_id, user_id, source)
+                VALUES ($1, $2, $3, $4)"#,
+            self.id,
+            media.id,
+            track.user_id,
+            track.source.as_str()
+        )
+        .execute(&self.db.pool)
+        .await?;
+
+        Ok(())
+    }
+}
+
+/// Writes the Opus packets of a consumer into a file until the recording stops
+/// or the producer is closed. Tracks start with silence until the speaker's
+/// first packet, and gaps where they sent nothing are filled with silence as
+/// well, so all tracks of a recording line up.
+///
+/// Returns the size and hash of the file, or `None` if nothing was received.
+async fn record_track(
+    socket: UdpSocket,
+    mut writer: OggOpusWriter,
+    // closing these stops the media
+    _transport: PlainTransport,
+    consumer: Consumer,
+    started_at: Instant,
+    stopping: Arc<AtomicBool>,
+) -> io::Result<Option<(u64, String)>> {
+    let mut buf = [0; 2048];
+    // rtp timestamp and position in the file of the last packet
+    let mut last: Option<(u32, u64)> = None;
+
+    while !stopping.load(Ordering::Relaxed) && !consumer.closed() {
+        let len = match actix_rt::time::timeout(STOP_CHECK_INTERVAL, socket.recv(&mut buf)).await {
+            Ok(received) => received?,
+            Err(_) => continue,
+        };
+
+        let Some((timestamp, payload)) = parse_rtp(&buf[..len]) else {
+            continue;
+        };
+
+        let position = match last {
+            None => started_at.elapsed().as_millis() as u64 * (SAMPLE_RATE / 1000) as u64,
+            Some((last_timestamp, last_position)) => {
+                let elapsed = timestamp.wrapping_sub(last_timestamp) as i32;
+                if elapsed <= 0 {
+                    // late or repeated
+                    continue;
+                }
+                last_position + elapsed as u64
+            }
+        };
+
+        writer.write_silence_until(position).await?;
+        writer.write_packet(payload).await?;
+        last = Some((timestamp, position));
+    }
+
+    if last.is_none() {
+        return Ok(None);
+    }
+
+    writer.finish().await.map(Some)
+}
+
+/// The timestamp and payload of an RTP packet, or `None` if it isn't one
+fn parse_rtp(packet: &[u8]) -> Option<(u32, &[u8])> {
+    if packet.len() < 12 || packet[0] >> 6 != 2 {
+        return None;
+    }
+
+    // RTCP comes in on the same port
+    if (200..=204).contains(&packet[1]) {
+        return None;
+    }
+
+    let timestamp = u32::from_be_bytes([packet[4], packet[5], packet[6], packet[7]]);
+
+    // skip the CSRCs and the header extension
+    let mut start = 12 + 4 * (packet[0] & 0x0f) as usize;
+    if packet[0] & 0x10 != 0 {
+        let length = packet.get(start + 2..start + 4)?;
+        start += 4 + 4 * u16::from_be_bytes([length[0], length[1]]) as usize;
+    }
+
+    let mut end = packet.len();
+    if packet[0] & 0x20 != 0 {
+        end = end.checked_sub(*packet.last()? as usize)?;
+    }
+
+    packet
+        .get(start..end)
+        .filter(|payload| !payload.is_empty())
+        .map(|payload| (timestamp, payload))
+}
+
+impl VoiceChannel {
+    /// Starts recording the channel and tells everyone in it. Fails if it is
+    /// already being recorded.
+    pub async fn start_recording(&self, recording: Arc<Recording>) -> Result<(), Arc<Recording>> {
+        {
+            let mut current = self.recording.lock().await;
+            if current.is_some() {
+                return Err(recording);
+            }
+            *current = Some(recording.clone());
+        }
+
+        let event = json!({
+            "type": "recording_started",
+            "recording_id": recording.id,
+            "started_by": recording.started_by,
+        });
+        self.send_to_all(event.to_string()).await;
+
+        let clients = self.clients.lock().await.clone();
+        for client in clients {
+            let producers: Vec<_> = client
+                .producers
+                .lock()
+                .unwrap()
+                .values()
+                .filter(|producer| producer.kind() == MediaKind::Audio)
+                .map(|producer| (producer.id(), ProducerSource::of(producer)))
+                .collect();
+
+            for (producer_id, source) in producers {
+                recording
+                    .record_producer(self, &client, producer_id, source)
+                    .await;
+            }
+        }
+
+        info!(
+            "channel[{:?}]: recording {:?} started",
+            self.id, recording.id
+        );
+
+        Ok(())
+    }
+
+    /// Stops recording the channel and tells everyone in it. The recording
+    /// still has to be finished.
+    pub async fn stop_recording(&self) -> Option<Arc<Recording>> {
+        let recording = self.recording.lock().await.take()?;
+
+        let event = json!({
+            "type": "recording_stopped",
+            "recording_id": recording.id,
+        });
+        self.send_to_all(event.to_string()).await;
+
+        Some(recording)
+    }
+
+    /// Records a new audio producer if the channel is being recorded
+    pub async fn record_if_recording(
+        &self,
+        client: &VoiceClient,
+        producer_id: ProducerId,
+        source: ProducerSource,
+    ) {
+        let recording = self.recording.lock().await.clone();
+
+        if let Some(recording) = recording {
+            recording
+                .record_producer(self, client, producer_id, source)
+                .await;
+        }
+    }
+}
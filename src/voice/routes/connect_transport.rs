@@ -10,7 +10,9 @@ use serde::Deserialize;
 use utoipa::ToSchema;
 
 use crate::voice::{
-    client::VoiceClientEx, routes::create_transport::TransportTypeQuery, transport::TransportType,
+    client::{VoiceClient, VoiceClientEx},
+    routes::create_transport::TransportTypeQuery,
+    transport::{ClientTransport, TransportType},
 };
 
 /// This contains the DTLS parameters of the remote peer, which are needed to connect the transport.
@@ -19,7 +21,7 @@ use crate::voice::{
 pub struct ConnectTransportRequest {
     /// See: [DtlsParameters](https://mediasoup.org/documentation/v3/mediasoup/api/#WebRtcTransportDtlsParameters)
     #[schema(value_type = Object)]
-    dtls_parameters: DtlsParameters,
+    pub dtls_parameters: DtlsParameters,
 }
 
 /// If things go wrong when connecting a transport, this enum will be used to specify the error.
@@ -27,6 +29,7 @@ pub struct ConnectTransportRequest {
 #[display(rename_all = "snake_case")]
 pub enum ConnectTransportError {
     TransportNotCreated,
+    NotAWebRtcTransport,
     TransportConnectFailed,
 }
 impl ResponseError for ConnectTransportError {
@@ -34,7 +37,7 @@ impl ResponseError for ConnectTransportError {
         use actix_web::http::StatusCode;
         use ConnectTransportError::*;
         match self {
-            TransportNotCreated => StatusCode::BAD_REQUEST,
+            TransportNotCreated | NotAWebRtcTransport => StatusCode::BAD_REQUEST,
             TransportConnectFailed => StatusCode::INTERNAL_SERVER_ERROR,
         }
     }
@@ -54,7 +57,7 @@ pub type ConnectTransportResponse = Result<&'static str, ConnectTransportError>;
     params(TransportTypeQuery),
     responses(
         (status = OK, description = "Transport connected", example = "connected"),
-        (status = BAD_REQUEST, description = "A transport of this type has not been created for this voice connection yet, so it cannot be connected."),
+        (status = BAD_REQUEST, description = "A transport of this type has not been created for this voice connection yet, so it cannot be connected. Also returned for plain transports, which are connected with `/voice/transport/plain/connect`."),
         (status = INTERNAL_SERVER_ERROR, description = "There was an error while establishing the RTP connection for this transport.")
     )
 )]
@@ -64,41 +67,57 @@ pub async fn connect_transport(
     request: Json<ConnectTransportRequest>,
     query: Query<TransportTypeQuery>,
 ) -> ConnectTransportResponse {
-    use ConnectTransportError::*;
-    use TransportType::*;
+    client
+        .connect_transport(query.transport_type, request.dtls_parameters.clone())
+        .await?;
 
-    let transport_to_connect = match query.transport_type {
-        Send => &client.c2s_transport,
-        Receive => &client.s2c_transport,
-    };
+    Ok("connected")
+}
 
-    transport_to_connect
-        .read()
-        .await
-        .as_ref()
-        .ok_or_else(|| {
-            warn!(
-                "client[{:?}]: tried to connect transport {:?} before creating it",
-                client.identity, query.transport_type
-            );
-            TransportNotCreated
-        })?
-        .connect(WebRtcTransportRemoteParameters {
-            dtls_parameters: request.dtls_parameters.clone(),
-        })
-        .await
-        .map_err(|e| {
-            error!(
-                "client[{:?}]: {:?} connect failed: {}",
-                client.identity, query.transport_type, e
-            );
-            TransportConnectFailed
-        })?;
+impl VoiceClient {
+    pub async fn connect_transport(
+        &self,
+        transport_type: TransportType,
+        dtls_parameters: DtlsParameters,
+    ) -> Result<(), ConnectTransportError> {
+        use ConnectTransportError::*;
+        use TransportType::*;
 
-    info!(
-        "client[{:?}]: {:?} transport connected",
-        client.identity, query.transport_type
-    );
+        let transport_to_connect = match transport_type {
+            Send => &self.c2s_transport,
+            Receive => &self.s2c_transport,
+        };
 
-    Ok("connected")
+        let transport = transport_to_connect.read().await.clone();
+
+        let transport = match transport {
+            Some(ClientTransport::WebRtc(transport)) => transport,
+            Some(ClientTransport::Plain(_)) => return Err(NotAWebRtcTransport),
+            None => {
+                warn!(
+                    "client[{:?}]: tried to connect transport {:?} before creating it",
+                    self.identity, transport_type
+                );
+                return Err(TransportNotCreated);
+            }
+        };
+
+        transport
+            .connect(WebRtcTransportRemoteParameters { dtls_parameters })
+            .await
+            .map_err(|e| {
+                error!(
+                    "client[{:?}]: {:?} connect failed: {}",
+                    self.identity, transport_type, e
+                );
+                TransportConnectFailed
+            })?;
+
+        info!(
+            "client[{:?}]: {:?} transport connected",
+            self.identity, transport_type
+        );
+
+        Ok(())
+    }
 }
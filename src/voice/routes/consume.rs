@@ -1,15 +1,20 @@
+use std::sync::Arc;
+
 use actix_web::error::ResponseError;
 use actix_web::post;
 use actix_web::web::Json;
 use derive_more::{Display, Error};
 use log::error;
 use mediasoup::prelude::ConsumerOptions;
+use mediasoup::producer::ProducerId;
 use mediasoup::rtp_parameters::{MediaKind, RtpCapabilities, RtpParameters};
-use mediasoup::{producer::ProducerId, transport::Transport};
 use serde::{Deserialize, Serialize};
 use utoipa::ToSchema;
 
-use crate::voice::client::VoiceClientEx;
+use crate::voice::{
+    client::{VoiceClient, VoiceClientEx},
+    routes::produce::ProducerSource,
+};
 
 #[derive(Debug, Deserialize, ToSchema)]
 #[serde(rename_all = "camelCase")]
@@ -58,6 +63,16 @@ pub struct ConsumeReply {
     pub kind: MediaKind,
     #[schema(value_type = Object)]
     pub rtp_parameters: RtpParameters,
+    /// Whether the producer is paused, in which case nothing will be received
+    /// until a `producer_resumed` event for it
+    pub producer_paused: bool,
+    pub app_data: ConsumerAppData,
+}
+
+/// Handed to the clientside consumer as its `appData`
+#[derive(Debug, Serialize, ToSchema)]
+pub struct ConsumerAppData {
+    pub source: ProducerSource,
 }
 
 pub type ConsumeResponse = Result<Json<ConsumeReply>, ConsumeError>;
@@ -83,55 +98,92 @@ pub async fn handle_consume(
     client: VoiceClientEx,
     request: Json<ConsumeRequest>,
 ) -> ConsumeResponse {
-    use ConsumeError::*;
+    client.consume(&request).await.map(Json)
+}
 
-    if client.s2c_transport.read().await.is_none() {
-        return Err(TransportNotCreated);
-    }
+impl VoiceClient {
+    pub async fn consume(
+        self: &Arc<Self>,
+        request: &ConsumeRequest,
+    ) -> Result<ConsumeReply, ConsumeError> {
+        use ConsumeError::*;
 
-    if !client
-        .channel
-        .router
-        .can_consume(&request.producer_id, &request.rtp_capabilities)
-    {
-        return Err(CannotConsume);
-    }
+        if self.s2c_transport.read().await.is_none() {
+            return Err(TransportNotCreated);
+        }
 
-    let consumer;
+        let router = self
+            .consumer_router
+            .lock()
+            .unwrap()
+            .clone()
+            .unwrap_or_else(|| self.channel.main_router());
 
-    {
-        let transport = client.s2c_transport.read().await;
-        let transport = transport.as_ref().unwrap();
+        // the producer has to be on the same router to be consumed
+        self.channel
+            .pipe_producer_to(request.producer_id, &router.router)
+            .await
+            .map_err(|e| {
+                error!(
+                    "client[{:?}]: piping producer {} failed: {}",
+                    self.identity, request.producer_id, e
+                );
+                CannotConsume
+            })?;
 
-        if transport.closed() {
-            return Err(TransportNotConnected);
+        if !router
+            .router
+            .can_consume(&request.producer_id, &request.rtp_capabilities)
+        {
+            return Err(CannotConsume);
         }
 
-        consumer = transport
-            .consume(ConsumerOptions::new(
-                request.producer_id,
-                request.rtp_capabilities.clone(),
-            ))
-            .await
-            .map_err(|e| {
+        let consumer;
+
+        {
+            let transport = self.s2c_transport.read().await;
+            let transport = transport.as_ref().unwrap();
+
+            if transport.closed() {
+                return Err(TransportNotConnected);
+            }
+
+            let mut options =
+                ConsumerOptions::new(request.producer_id, request.rtp_capabilities.clone());
+            // someone deafened by a moderator doesn't get to hear anyone
+            options.paused = self.state.lock().unwrap().server_deafened;
+
+            consumer = transport.consume(options).await.map_err(|e| {
                 error!(
                     "client[{:?}]: consume() on producer {} failed: {}",
-                    client.identity, request.producer_id, e
+                    self.identity, request.producer_id, e
                 );
                 ConsumerFailed
             })?;
-    }
+        }
 
-    let id = consumer.id().to_string();
+        router.load.add_consumer();
+        consumer
+            .on_close(move || router.load.remove_consumer())
+            .detach();
 
-    let reply = ConsumeReply {
-        id: id.clone(),
-        producer_id: request.producer_id,
-        kind: consumer.kind(),
-        rtp_parameters: consumer.rtp_parameters().clone(),
-    };
+        let source = self
+            .channel
+            .producer_source(&request.producer_id.to_string())
+            .await
+            .unwrap_or(ProducerSource::default_for(consumer.kind()));
+
+        let reply = ConsumeReply {
+            id: consumer.id().to_string(),
+            producer_id: request.producer_id,
+            kind: consumer.kind(),
+            rtp_parameters: consumer.rtp_parameters().clone(),
+            producer_paused: consumer.producer_paused(),
+            app_data: ConsumerAppData { source },
+        };
 
-    client.consumers.lock().unwrap().insert(id, consumer);
+        self.add_consumer(consumer);
 
-    Ok(Json(reply))
+        Ok(reply)
+    }
 }
@@ -0,0 +1,226 @@
+use actix_web::{
+    delete, post,
+    web::{Json, Path},
+    HttpResponse,
+};
+use log::error;
+use mediasoup::consumer::{Consumer, ConsumerLayers};
+use serde::Deserialize;
+use utoipa::{IntoParams, ToSchema};
+
+use crate::{
+    error::{macros::err, HResult, IntoHandlerErrorResult},
+    voice::client::{VoiceClient, VoiceClientEx},
+};
+
+#[derive(Deserialize, IntoParams)]
+pub struct ConsumerPath {
+    /// One of your own consumers
+    pub consumer_id: String,
+}
+
+/// Close consumer
+///
+/// Stops receiving a producer, for example when you stop watching someone's
+/// screen share. Consumers are closed automatically when the producer they
+/// consume is closed, so there's no need to call this after a
+/// `producer_closed` event.
+#[utoipa::path(
+    tag = "voice",
+    security(("voice" = [])),
+    params(ConsumerPath),
+    responses(
+        (status = OK, description = "Consumer closed"),
+        (status = NOT_FOUND, description = "No such consumer"),
+    )
+)]
+#[delete("/voice/consumers/{consumer_id}")]
+pub async fn close_consumer(
+    client: VoiceClientEx,
+    path: Path<ConsumerPath>,
+) -> HResult<HttpResponse> {
+    client.close_consumer(&path.consumer_id)?;
+    Ok(HttpResponse::Ok().finish())
+}
+
+/// Pause consumer
+///
+/// Stops receiving media of a producer until the consumer is resumed, for
+/// example while someone's video is scrolled out of view.
+#[utoipa::path(
+    tag = "voice",
+    security(("voice" = [])),
+    params(ConsumerPath),
+    responses(
+        (status = OK, description = "Consumer paused"),
+        (status = NOT_FOUND, description = "No such consumer"),
+    )
+)]
+#[post("/voice/consumers/{consumer_id}/pause")]
+pub async fn pause_consumer(
+    client: VoiceClientEx,
+    path: Path<ConsumerPath>,
+) -> HResult<HttpResponse> {
+    client.pause_consumer(&path.consumer_id).await?;
+    Ok(HttpResponse::Ok().finish())
+}
+
+/// Resume consumer
+///
+/// Starts receiving media of a paused consumer again. Consumers can't be
+/// resumed while you are deafened by a moderator.
+#[utoipa::path(
+    tag = "voice",
+    security(("voice" = [])),
+    params(ConsumerPath),
+    responses(
+        (status = OK, description = "Consumer resumed"),
+        (status = FORBIDDEN, description = "Deafened by a moderator"),
+        (status = NOT_FOUND, description = "No such consumer"),
+    )
+)]
+#[post("/voice/consumers/{consumer_id}/resume")]
+pub async fn resume_consumer(
+    client: VoiceClientEx,
+    path: Path<ConsumerPath>,
+) -> HResult<HttpResponse> {
+    client.resume_consumer(&path.consumer_id).await?;
+    Ok(HttpResponse::Ok().finish())
+}
+
+/// Which layers of a simulcast or SVC producer to receive. If a layer isn't
+/// available, the closest lower one is received instead.
+#[derive(Debug, Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct PreferredLayersRequest {
+    /// Resolution, 0 being the lowest
+    #[schema(example = 2)]
+    pub spatial_layer: u8,
+    /// Frame rate, 0 being the lowest. Leave it out for the highest.
+    #[schema(example = 2)]
+    pub temporal_layer: Option<u8>,
+}
+
+/// Set preferred layers
+///
+/// Picks the quality to receive a simulcast or SVC video producer in, for
+/// example the lowest when it's shown as a thumbnail. Does nothing for
+/// producers that only send one layer.
+#[utoipa::path(
+    tag = "voice",
+    security(("voice" = [])),
+    params(ConsumerPath),
+    request_body = PreferredLayersRequest,
+    responses(
+        (status = OK, description = "Preferred layers set"),
+        (status = NOT_FOUND, description = "No such consumer"),
+    )
+)]
+#[post("/voice/consumers/{consumer_id}/layers")]
+pub async fn set_preferred_layers(
+    client: VoiceClientEx,
+    path: Path<ConsumerPath>,
+    request: Json<PreferredLayersRequest>,
+) -> HResult<HttpResponse> {
+    client
+        .set_preferred_layers(&path.consumer_id, &request)
+        .await?;
+    Ok(HttpResponse::Ok().finish())
+}
+
+impl VoiceClient {
+    pub fn close_consumer(&self, consumer_id: &str) -> HResult<()> {
+        let consumer = self
+            .consumers
+            .lock()
+            .unwrap()
+            .remove(consumer_id)
+            .or_err(404)?;
+        // consumers lock is released here
+
+        // the client holds the only handle to the consumer, so dropping it
+        // closes it
+        drop(consumer);
+
+        Ok(())
+    }
+
+    pub async fn pause_consumer(&self, consumer_id: &str) -> HResult<()> {
+        let consumer = self.get_consumer(consumer_id)?;
+
+        consumer
+            .pause()
+            .await
+            .map_err(|e| {
+                error!(
+                    "client[{:?}]: pausing consumer {} failed: {}",
+                    self.identity, consumer_id, e
+                );
+                e
+            })
+            .or_err(500)?;
+
+        self.paused_consumers
+            .lock()
+            .unwrap()
+            .insert(consumer_id.to_string());
+
+        Ok(())
+    }
+
+    pub async fn resume_consumer(&self, consumer_id: &str) -> HResult<()> {
+        let consumer = self.get_consumer(consumer_id)?;
+
+        if self.state.lock().unwrap().server_deafened {
+            return err!(403, "You were deafened by a moderator.");
+        }
+
+        consumer
+            .resume()
+            .await
+            .map_err(|e| {
+                error!(
+                    "client[{:?}]: resuming consumer {} failed: {}",
+                    self.identity, consumer_id, e
+                );
+                e
+            })
+            .or_err(500)?;
+
+        self.paused_consumers.lock().unwrap().remove(consumer_id);
+
+        Ok(())
+    }
+
+    pub async fn set_preferred_layers(
+        &self,
+        consumer_id: &str,
+        layers: &PreferredLayersRequest,
+    ) -> HResult<()> {
+        let consumer = self.get_consumer(consumer_id)?;
+
+        consumer
+            .set_preferred_layers(ConsumerLayers {
+                spatial_layer: layers.spatial_layer,
+                temporal_layer: layers.temporal_layer,
+            })
+            .await
+            .map_err(|e| {
+                error!(
+                    "client[{:?}]: setting preferred layers of consumer {} failed: {}",
+                    self.identity, consumer_id, e
+                );
+                e
+            })
+            .or_err(500)
+    }
+
+    fn get_consumer(&self, consumer_id: &str) -> HResult<Consumer> {
+        self.consumers
+            .lock()
+            .unwrap()
+            .get(consumer_id)
+            .cloned()
+            .or_err(404)
+    }
+}
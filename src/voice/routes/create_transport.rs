@@ -12,7 +12,13 @@ use mediasoup::{
 use serde::{Deserialize, Serialize};
 use utoipa::{IntoParams, ToSchema};
 
-use crate::voice::{client::VoiceClientEx, transport::TransportType};
+use crate::{
+    options,
+    voice::{
+        client::{VoiceClient, VoiceClientEx},
+        transport::{ClientTransport, TransportType},
+    },
+};
 
 /*
    There are two handlers registered for transports.
@@ -97,54 +103,80 @@ pub async fn create_transport(
     client: VoiceClientEx,
     query: Query<TransportTypeQuery>,
 ) -> CreateTransportResponse {
-    use CreateTransportError::*;
-    use TransportType::*;
-
-    // Get the transport to assign to. This is either the send or receive transport.
-    let transport_to_assign = match query.transport_type {
-        Send => &client.c2s_transport,
-        Receive => &client.s2c_transport,
-    };
-
-    // If the transport already exists, return an error.
-    if transport_to_assign.read().await.is_some() {
-        warn!(
-            "client[{:?}]: tried to create {:?} transport when it already exists",
-            client.identity, query.transport_type
-        );
-        return Err(TransportAlreadyExists);
-    }
-
-    // Create the transport.
-    let transport = client
-        .channel
-        .create_webrtc_transport()
+    client
+        .create_transport(query.transport_type)
         .await
-        .map_err(|e| {
-            error!(
-                "client[{:?}]: transport {:?} create failed: {}",
-                client.identity, query.transport_type, e
+        .map(Json)
+}
+
+impl VoiceClient {
+    pub async fn create_transport(
+        &self,
+        transport_type: TransportType,
+    ) -> Result<CreateTransportReply, CreateTransportError> {
+        use CreateTransportError::*;
+        use TransportType::*;
+
+        // Get the transport to assign to. This is either the send or receive transport.
+        let transport_to_assign = match transport_type {
+            Send => &self.c2s_transport,
+            Receive => &self.s2c_transport,
+        };
+
+        // If the transport already exists, return an error.
+        if transport_to_assign.read().await.is_some() {
+            warn!(
+                "client[{:?}]: tried to create {:?} transport when it already exists",
+                self.identity, transport_type
             );
-            TransportCreateFailed
-        })?;
-
-    // Prepare the reply with all the needed information.
-    let reply = CreateTransportReply {
-        id: transport.id().to_string(),
-        ice_parameters: transport.ice_parameters().clone(),
-        ice_candidates: transport.ice_candidates().clone(),
-        dtls_parameters: transport.dtls_parameters(),
-    };
-
-    info!(
-        "client[{:?}]: {:?} transport created, id: {}",
-        client.identity,
-        query.transport_type,
-        transport.id()
-    );
-
-    // Write through the RwLock and assign the transport to the client.
-    *transport_to_assign.write().await = Some(transport);
-
-    Ok(Json(reply))
+            return Err(TransportAlreadyExists);
+        }
+
+        // Sending goes to the router with the channel's producers, receiving can
+        // be spread over other routers when the channel is busy.
+        let router = match transport_type {
+            Send => self.channel.main_router(),
+            Receive => self.channel.pick_consumer_router().await,
+        };
+
+        // Create the transport.
+        let transport = self
+            .channel
+            .create_webrtc_transport(&router)
+            .await
+            .map_err(|e| {
+                error!(
+                    "client[{:?}]: transport {:?} create failed: {}",
+                    self.identity, transport_type, e
+                );
+                TransportCreateFailed
+            })?;
+
+        // Prepare the reply with all the needed information.
+        let reply = CreateTransportReply {
+            id: transport.id().to_string(),
+            ice_parameters: transport.ice_parameters().clone(),
+            ice_candidates: options::announced_ice_candidates(transport.ice_candidates()),
+            dtls_parameters: transport.dtls_parameters(),
+        };
+
+        info!(
+            "client[{:?}]: {:?} transport created, id: {}",
+            self.identity,
+            transport_type,
+            transport.id()
+        );
+
+        if let Receive = transport_type {
+            *self.consumer_router.lock().unwrap() = Some(router);
+        }
+
+        // Write through the RwLock and assign the transport to the client.
+        let transport = ClientTransport::WebRtc(transport);
+        transport.log_state_changes(&self.identity, transport_type);
+
+        *transport_to_assign.write().await = Some(transport);
+
+        Ok(reply)
+    }
 }
@@ -0,0 +1,69 @@
+use actix_web::{
+    get,
+    web::{Data, Json},
+};
+
+use crate::{
+    auth::access_token::AccessToken,
+    db::DB,
+    error::{macros::err, HResult},
+    guilds::routes::{GuildIdParams, GuildPath},
+    voice::{state::VoiceChannelState, VoiceChannels},
+};
+
+/// List voice channel members
+///
+/// Lists who is connected to each voice channel of a guild, along with their
+/// voice state. Channels nobody is connected to are left out. Keep the list up
+/// to date with the `voiceStateUpdate` events sent on the guild's topic.
+#[utoipa::path(
+    tag = "voice",
+    security(("token" = [])),
+    params(GuildIdParams),
+    responses(
+        (status = OK, description = "Voice channels with people in them", body = Vec<VoiceChannelState>),
+        (status = FORBIDDEN, description = "Not a member of the guild"),
+    )
+)]
+#[get("/guilds/{guild_id}/voice")]
+pub async fn guild_voice_states(
+    db: DB,
+    token: AccessToken,
+    path: GuildPath,
+    channels: Data<VoiceChannels>,
+) -> HResult<Json<Vec<VoiceChannelState>>> {
+    if !db.is_user_in_guild(&token.user_id, &path.guild_id).await? {
+        return err!(403);
+    }
+
+    let guild_channels: Vec<_> = channels
+        .lock()
+        .unwrap()
+        .values()
+        .filter(|channel| channel.guild_id == path.guild_id)
+        .cloned()
+        .collect();
+    // channels lock is released here
+
+    let mut states = Vec::with_capacity(guild_channels.len());
+
+    for channel in guild_channels {
+        let clients = channel.clients.lock().await;
+        let mut members = Vec::with_capacity(clients.len());
+
+        for client in clients.iter() {
+            if client.is_connected().await {
+                members.push(client.member_state());
+            }
+        }
+
+        if !members.is_empty() {
+            states.push(VoiceChannelState {
+                channel_id: channel.id.clone(),
+                members,
+            });
+        }
+    }
+
+    Ok(Json(states))
+}
@@ -13,6 +13,9 @@ use utoipa::{IntoParams, ToSchema};
 
 use crate::{
     auth::user::User,
+    db::DB,
+    error::{macros::err, HResult, IntoHandlerErrorResult},
+    realtime::pubsub::pubsub::PubSub,
     voice::{
         channel::create_channel, client::VoiceClient, pool::VoiceWorkerPool, VoiceChannels,
         VoiceClients,
@@ -21,7 +24,7 @@ use crate::{
 
 #[derive(Deserialize, IntoParams)]
 pub struct JoinVcQuery {
-    /// Channel ID - will be created if it does not already exist
+    /// ID of a voice channel in a guild you're in
     #[serde(rename = "c")]
     channel_id: String,
 }
@@ -36,6 +39,9 @@ pub struct JoinVcReply {
     /// Router RTP capabilities, feed to `device.load` on your client.
     #[schema(value_type = Object)]
     rtp: RtpCapabilitiesFinalized,
+    /// Whether the channel is being recorded. Let the user know before they
+    /// start speaking!
+    recording: bool,
 }
 
 // #[derive(Debug, Display, Error)]
@@ -61,7 +67,7 @@ pub struct JoinVcReply {
 /// credentials will be invalidated. Peers already in the voice channel will not
 /// be notified of your connection until you connect to the websocket.
 ///
-/// Note that if a channel with the given ID does not exist, it will be created.
+/// The channel must be a voice channel in a guild that you are a member of.
 ///
 /// ### Connection Process
 /// ```
@@ -116,16 +122,39 @@ pub struct JoinVcReply {
     params(JoinVcQuery),
     responses(
         (status = OK, description = "Ready for websocket connection", body = JoinVcReply),
+        (status = FORBIDDEN, description = "Not a voice channel, or you can't connect to it"),
+        (status = SERVICE_UNAVAILABLE, description = "No voice worker could take the channel"),
     )
 )]
 #[get("/voice/join")]
 pub async fn join_vc(
+    db: DB,
     user: User,
     clients: Data<VoiceClients>,
     channels: Data<VoiceChannels>,
     vwp: Data<Mutex<VoiceWorkerPool>>,
     query: Query<JoinVcQuery>,
-) -> Json<JoinVcReply> {
+    pubsub: Data<PubSub>,
+) -> HResult<Json<JoinVcReply>> {
+    // this also makes sure the channel exists, rooms are only ever made for
+    // real voice channels
+    if !db
+        .can_user_connect_to_voice_in(&user.id, &query.channel_id)
+        .await?
+    {
+        return err!(403);
+    }
+
+    let member = sqlx::query!(
+        r#"SELECT channels.guild_id, members.voice_muted, members.voice_deafened
+            FROM channels, members
+            WHERE channels.id = $1 AND members.guild_id = channels.guild_id AND members.user_id = $2"#,
+        query.channel_id,
+        user.id
+    )
+    .fetch_one(&db.pool)
+    .await?;
+
     // get the channel
     let channel = channels.lock().unwrap().get(&query.channel_id).cloned();
     // channels lock is released here
@@ -134,11 +163,27 @@ pub async fn join_vc(
         // existing channel exists
         Some(existing) => existing.clone(),
         // create a new channel
-        None => create_channel(&query.channel_id, channels.clone(), &vwp.into_inner()).await,
+        None => create_channel(
+            &query.channel_id,
+            &member.guild_id,
+            channels.clone(),
+            vwp,
+            pubsub,
+        )
+        .await
+        .or_err_msg(503, "Voice chat is unavailable right now, try again later.")?,
     };
 
     // create a new client
     let client = VoiceClient::with_channel_and_user(channel.clone(), user);
+
+    // moderators' mutes and deafens stick around between joins
+    {
+        let mut state = client.state.lock().unwrap();
+        state.server_muted = member.voice_muted;
+        state.server_deafened = member.voice_deafened;
+    }
+
     let client = Arc::new(client);
 
     // add the client to the channel's client list
@@ -149,6 +194,7 @@ pub async fn join_vc(
         identity: client.identity.clone(),
         token: client.token.clone(),
         rtp: channel.router.rtp_capabilities().clone(),
+        recording: channel.recording.lock().await.is_some(),
     };
 
     // the following closure checks if the client has connected to the websocket in time
@@ -198,5 +244,5 @@ pub async fn join_vc(
         .unwrap()
         .insert(client.identity.clone(), client);
 
-    Json(reply)
+    Ok(Json(reply))
 }
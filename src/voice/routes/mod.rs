@@ -2,21 +2,39 @@ use utoipa::OpenApi;
 
 use self::{
     connect_transport::ConnectTransportRequest,
-    consume::{ConsumeReply, ConsumeRequest},
+    consume::{ConsumeReply, ConsumeRequest, ConsumerAppData},
+    consumers::PreferredLayersRequest,
     create_transport::CreateTransportReply,
     join_vc::JoinVcReply,
     list_vc_peers::ChannelMemberInfo,
-    produce::{ProduceReply, ProduceRequest},
+    moderate::ModerateVoiceMemberRequest,
+    plain_transport::{ConnectPlainTransportRequest, CreatePlainTransportReply},
+    produce::{ProduceReply, ProduceRequest, ProducerSource},
+    recording::{RecordingInfo, RecordingTrack},
+    stats::{VoiceClientStats, VoiceConsumerStats, VoiceProducerStats},
+    update_state::UpdateVoiceStateRequest,
+};
+use super::{
+    state::{VoiceChannelState, VoiceMemberState, VoiceState},
+    transport::TransportType,
 };
-use super::transport::TransportType;
 
 pub mod connect_transport;
 pub mod consume;
+pub mod consumers;
 pub mod create_transport;
+pub mod guild_voice_states;
 pub mod join_vc;
 pub mod leave_vc;
 pub mod list_vc_peers;
+pub mod moderate;
+pub mod plain_transport;
 pub mod produce;
+pub mod producers;
+pub mod recording;
+pub mod restart_ice;
+pub mod stats;
+pub mod update_state;
 pub mod voice_events;
 
 pub fn configure_app(cfg: &mut actix_web::web::ServiceConfig) {
@@ -26,8 +44,27 @@ pub fn configure_app(cfg: &mut actix_web::web::ServiceConfig) {
         .service(voice_events::voice_events_ws)
         .service(create_transport::create_transport)
         .service(connect_transport::connect_transport)
+        .service(restart_ice::restart_ice)
+        .service(plain_transport::create_plain_transport)
+        .service(plain_transport::connect_plain_transport)
         .service(produce::handle_produce)
-        .service(consume::handle_consume);
+        .service(consume::handle_consume)
+        .service(producers::close_producer)
+        .service(producers::pause_producer)
+        .service(producers::resume_producer)
+        .service(consumers::close_consumer)
+        .service(consumers::pause_consumer)
+        .service(consumers::resume_consumer)
+        .service(consumers::set_preferred_layers)
+        .service(update_state::update_state)
+        .service(guild_voice_states::guild_voice_states)
+        .service(moderate::moderate_voice_member)
+        .service(moderate::disconnect_voice_member)
+        .service(recording::start_recording)
+        .service(recording::stop_recording)
+        .service(recording::list_recordings)
+        .service(stats::get_voice_stats)
+        .service(stats::get_voice_channel_stats);
 }
 
 #[derive(OpenApi)]
@@ -42,8 +79,27 @@ pub fn configure_app(cfg: &mut actix_web::web::ServiceConfig) {
         voice_events::voice_events_ws,
         create_transport::create_transport,
         connect_transport::connect_transport,
+        restart_ice::restart_ice,
+        plain_transport::create_plain_transport,
+        plain_transport::connect_plain_transport,
         produce::handle_produce,
         consume::handle_consume,
+        producers::close_producer,
+        producers::pause_producer,
+        producers::resume_producer,
+        consumers::close_consumer,
+        consumers::pause_consumer,
+        consumers::resume_consumer,
+        consumers::set_preferred_layers,
+        update_state::update_state,
+        guild_voice_states::guild_voice_states,
+        moderate::moderate_voice_member,
+        moderate::disconnect_voice_member,
+        recording::start_recording,
+        recording::stop_recording,
+        recording::list_recordings,
+        stats::get_voice_stats,
+        stats::get_voice_channel_stats,
     ),
     components(schemas(
         ChannelMemberInfo,
@@ -51,10 +107,25 @@ pub fn configure_app(cfg: &mut actix_web::web::ServiceConfig) {
         TransportType,
         ConnectTransportRequest,
         CreateTransportReply,
+        CreatePlainTransportReply,
+        ConnectPlainTransportRequest,
         ConsumeRequest,
         ConsumeReply,
         ProduceRequest,
-        ProduceReply
+        ProduceReply,
+        ProducerSource,
+        ConsumerAppData,
+        PreferredLayersRequest,
+        UpdateVoiceStateRequest,
+        VoiceState,
+        VoiceMemberState,
+        VoiceChannelState,
+        ModerateVoiceMemberRequest,
+        RecordingInfo,
+        RecordingTrack,
+        VoiceClientStats,
+        VoiceProducerStats,
+        VoiceConsumerStats
     ))
 )]
 pub struct VoiceApiDoc;
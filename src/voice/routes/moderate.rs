@@ -0,0 +1,235 @@
+use std::sync::Arc;
+
+use actix_web::{
+    delete, patch,
+    web::{Data, Json, Path},
+    HttpResponse,
+};
+use serde::Deserialize;
+use serde_json::json;
+use utoipa::{IntoParams, ToSchema};
+
+use crate::{
+    auth::access_token::AccessToken,
+    db::DB,
+    error::{macros::err, HResult},
+    voice::{client::VoiceClient, VoiceChannels, VoiceClients},
+};
+
+#[derive(Deserialize, IntoParams)]
+pub struct VoiceMemberPath {
+    pub guild_id: String,
+    pub user_id: String,
+}
+
+/// Fields that are left out stay as they are.
+#[derive(Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct ModerateVoiceMemberRequest {
+    /// Stop everyone from hearing them
+    server_muted: Option<bool>,
+    /// Stop them from hearing anyone
+    server_deafened: Option<bool>,
+    /// Move them to another voice channel in the guild
+    #[schema(example = "jqNNyhSbOl1AwqCTMAZ2G")]
+    channel_id: Option<String>,
+}
+
+/// Moderate voice member
+///
+/// Server mutes or deafens a member of the guild, or moves them to another
+/// voice channel. Mutes and deafens stay in place until they are undone, even
+/// if the member leaves voice in the meantime.
+///
+/// Moving someone sends them a `moved` event with the channel they should join
+/// on the voice websocket, after which they are disconnected from their current
+/// channel.
+///
+/// Requires permission to moderate voice channels in the guild.
+#[utoipa::path(
+    tag = "voice",
+    security(("token" = [])),
+    params(VoiceMemberPath),
+    request_body = ModerateVoiceMemberRequest,
+    responses(
+        (status = OK, description = "Member moderated"),
+        (status = BAD_REQUEST, description = "They can't be moved to that channel"),
+        (status = FORBIDDEN, description = "No permission to moderate voice channels"),
+        (status = NOT_FOUND, description = "Not a member of the guild, or not in voice when trying to move them"),
+    )
+)]
+#[patch("/guilds/{guild_id}/voice/{user_id}")]
+pub async fn moderate_voice_member(
+    db: DB,
+    token: AccessToken,
+    path: Path<VoiceMemberPath>,
+    request: Json<ModerateVoiceMemberRequest>,
+    clients: Data<VoiceClients>,
+    channels: Data<VoiceChannels>,
+) -> HResult<HttpResponse> {
+    if !db
+        .can_user_moderate_voice_in(&token.user_id, &path.guild_id)
+        .await?
+    {
+        return err!(403);
+    }
+
+    let targets = clients_in_guild(&channels, &path.guild_id, &path.user_id).await;
+
+    // make sure they can be moved before changing anything
+    if let Some(ref channel_id) = request.channel_id {
+        if targets.is_empty() {
+            return err!(404, "They are not in a voice channel.");
+        }
+
+        let in_guild = sqlx::query!(
+            r#"SELECT EXISTS (SELECT 1 FROM channels WHERE id = $1 AND guild_id = $2) AS "in_guild!""#,
+            channel_id,
+            path.guild_id
+        )
+        .fetch_one(&db.pool)
+        .await?
+        .in_guild;
+
+        if !in_guild
+            || !db
+                .can_user_connect_to_voice_in(&path.user_id, channel_id)
+                .await?
+        {
+            return err!(400, "They can't be moved to that channel.");
+        }
+    }
+
+    let updated = sqlx::query!(
+        r#"UPDATE members
+            SET voice_muted = COALESCE($3, voice_muted), voice_deafened = COALESCE($4, voice_deafened)
+            WHERE guild_id = $1 AND user_id = $2"#,
+        path.guild_id,
+        path.user_id,
+        request.server_muted,
+        request.server_deafened
+    )
+    .execute(&db.pool)
+    .await?;
+
+    if updated.rows_affected() == 0 {
+        return err!(404);
+    }
+
+    if request.server_muted.is_some() || request.server_deafened.is_some() {
+        for client in targets.iter() {
+            {
+                let mut state = client.state.lock().unwrap();
+                state.server_muted = request.server_muted.unwrap_or(state.server_muted);
+                state.server_deafened = request.server_deafened.unwrap_or(state.server_deafened);
+            }
+
+            client.apply_server_state().await;
+            client.channel.publish_state(client).await;
+        }
+    }
+
+    if let Some(ref channel_id) = request.channel_id {
+        let event = json!({
+            "type": "moved",
+            "channel_id": channel_id,
+        });
+
+        for client in targets.iter() {
+            if client.channel.id == *channel_id {
+                continue;
+            }
+
+            client.send(event.to_string()).await.ok();
+            client
+                .channel
+                .disconnect_client(client, &clients, &channels)
+                .await;
+        }
+    }
+
+    Ok(HttpResponse::Ok().finish())
+}
+
+/// Disconnect voice member
+///
+/// Disconnects every voice connection a member of the guild has in its voice
+/// channels. They are sent a `disconnected` event on the voice websocket first.
+/// Nothing stops them from joining again.
+///
+/// Requires permission to moderate voice channels in the guild.
+#[utoipa::path(
+    tag = "voice",
+    security(("token" = [])),
+    params(VoiceMemberPath),
+    responses(
+        (status = OK, description = "Member disconnected"),
+        (status = FORBIDDEN, description = "No permission to moderate voice channels"),
+        (status = NOT_FOUND, description = "They are not in a voice channel"),
+    )
+)]
+#[delete("/guilds/{guild_id}/voice/{user_id}")]
+pub async fn disconnect_voice_member(
+    db: DB,
+    token: AccessToken,
+    path: Path<VoiceMemberPath>,
+    clients: Data<VoiceClients>,
+    channels: Data<VoiceChannels>,
+) -> HResult<HttpResponse> {
+    if !db
+        .can_user_moderate_voice_in(&token.user_id, &path.guild_id)
+        .await?
+    {
+        return err!(403);
+    }
+
+    let targets = clients_in_guild(&channels, &path.guild_id, &path.user_id).await;
+
+    if targets.is_empty() {
+        return err!(404);
+    }
+
+    let event = json!({ "type": "disconnected" }).to_string();
+
+    for client in targets.iter() {
+        client.send(event.clone()).await.ok();
+        client
+            .channel
+            .disconnect_client(client, &clients, &channels)
+            .await;
+    }
+
+    Ok(HttpResponse::Ok().finish())
+}
+
+/// All voice connections of a user in the voice channels of a guild
+async fn clients_in_guild(
+    channels: &VoiceChannels,
+    guild_id: &str,
+    user_id: &str,
+) -> Vec<Arc<VoiceClient>> {
+    let guild_channels: Vec<_> = channels
+        .lock()
+        .unwrap()
+        .values()
+        .filter(|channel| channel.guild_id == guild_id)
+        .cloned()
+        .collect();
+    // channels lock is released here
+
+    let mut targets = vec![];
+
+    for channel in guild_channels {
+        targets.extend(
+            channel
+                .clients
+                .lock()
+                .await
+                .iter()
+                .filter(|client| client.user.id == user_id)
+                .cloned(),
+        );
+    }
+
+    targets
+}
@@ -0,0 +1,254 @@
+use std::net::IpAddr;
+
+use actix_web::{
+    post,
+    web::{Json, Query},
+    ResponseError,
+};
+use derive_more::{Display, Error};
+use log::{error, info, warn};
+use mediasoup::{
+    data_structures::TransportTuple, plain_transport::PlainTransportRemoteParameters,
+    transport::Transport,
+};
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use crate::voice::{
+    client::{VoiceClient, VoiceClientEx},
+    routes::create_transport::TransportTypeQuery,
+    transport::{ClientTransport, TransportType},
+};
+
+/*
+   Bots can't do WebRTC, so they get plain RTP transports instead.
+   - POST /voice/transport/plain/create
+   - POST /voice/transport/plain/connect
+
+   Once created (and for `recv`, connected), they produce and consume through
+   the same endpoints as everyone else.
+*/
+
+/// Where the server sends and receives a plain transport's RTP
+#[derive(Debug, Serialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct CreatePlainTransportReply {
+    #[schema(value_type = uuid::Uuid)]
+    id: String,
+    /// The server's address and port, as `localAddress` and `localPort`. RTCP
+    /// goes over the same port as RTP.
+    #[schema(value_type = Object)]
+    tuple: TransportTuple,
+}
+
+/// Where the bot wants to receive RTP
+#[derive(Debug, Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct ConnectPlainTransportRequest {
+    #[schema(value_type = String, example = "203.0.113.7")]
+    pub ip: IpAddr,
+    #[schema(example = 5004)]
+    pub port: u16,
+    /// Only if RTCP should go to another port than RTP
+    pub rtcp_port: Option<u16>,
+}
+
+/// If things go wrong with a plain transport, this enum will be used to specify the error.
+#[derive(Debug, Display, Error)]
+#[display(rename_all = "snake_case")]
+pub enum PlainTransportError {
+    NotABot,
+    TransportAlreadyExists,
+    TransportNotCreated,
+    NotAPlainTransport,
+    TransportCreateFailed,
+    TransportConnectFailed,
+}
+impl ResponseError for PlainTransportError {
+    fn status_code(&self) -> actix_web::http::StatusCode {
+        use actix_web::http::StatusCode;
+        use PlainTransportError::*;
+        match self {
+            NotABot => StatusCode::FORBIDDEN,
+            TransportAlreadyExists => StatusCode::CONFLICT,
+            TransportNotCreated | NotAPlainTransport => StatusCode::BAD_REQUEST,
+            TransportCreateFailed | TransportConnectFailed => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+}
+
+/// Create plain RTP transport
+///
+/// The bot counterpart of `/voice/transport/create`, for taking part in voice
+/// chat with plain RTP instead of WebRTC, for example straight from ffmpeg or
+/// GStreamer. Only bots may create plain transports.
+///
+/// To send, create a `send` transport and send Opus RTP to the `tuple` in the
+/// reply, then announce it with `/voice/produce`, using the payload type and
+/// SSRC you send with in `rtpParameters`. The server answers to wherever the
+/// first packet came from, so a `send` transport needs no connecting.
+///
+/// To receive, create a `recv` transport, connect it to where you want the RTP
+/// to go, and call `/voice/consume` for each producer. Audio isn't mixed, each
+/// producer arrives separately with the SSRC in its consumer's `rtpParameters`.
+#[utoipa::path(
+    tag = "voice",
+    security(("voice" = [])),
+    params(TransportTypeQuery),
+    responses(
+        (status = OK, description = "Transport created", body = CreatePlainTransportReply),
+        (status = FORBIDDEN, description = "You're not a bot"),
+        (status = CONFLICT, description = "Transport of this type already created"),
+        (status = INTERNAL_SERVER_ERROR, description = "Transport allocation failed on the serverside"),
+    )
+)]
+#[post("/voice/transport/plain/create")]
+pub async fn create_plain_transport(
+    client: VoiceClientEx,
+    query: Query<TransportTypeQuery>,
+) -> Result<Json<CreatePlainTransportReply>, PlainTransportError> {
+    client
+        .create_plain_transport(query.transport_type)
+        .await
+        .map(Json)
+}
+
+/// Connect plain RTP transport
+///
+/// Tells a plain `recv` transport where to send RTP to. Connecting a `send`
+/// transport isn't necessary, and fails once it has received media.
+#[utoipa::path(
+    tag = "voice",
+    security(("voice" = [])),
+    params(TransportTypeQuery),
+    request_body = ConnectPlainTransportRequest,
+    responses(
+        (status = OK, description = "Transport connected", example = "connected"),
+        (status = BAD_REQUEST, description = "No plain transport of this type has been created yet"),
+        (status = INTERNAL_SERVER_ERROR, description = "Connecting the transport failed"),
+    )
+)]
+#[post("/voice/transport/plain/connect")]
+pub async fn connect_plain_transport(
+    client: VoiceClientEx,
+    request: Json<ConnectPlainTransportRequest>,
+    query: Query<TransportTypeQuery>,
+) -> Result<&'static str, PlainTransportError> {
+    client
+        .connect_plain_transport(query.transport_type, &request)
+        .await?;
+
+    Ok("connected")
+}
+
+impl VoiceClient {
+    pub async fn create_plain_transport(
+        &self,
+        transport_type: TransportType,
+    ) -> Result<CreatePlainTransportReply, PlainTransportError> {
+        use PlainTransportError::*;
+        use TransportType::*;
+
+        if !self.user.bot {
+            return Err(NotABot);
+        }
+
+        let transport_to_assign = match transport_type {
+            Send => &self.c2s_transport,
+            Receive => &self.s2c_transport,
+        };
+
+        if transport_to_assign.read().await.is_some() {
+            warn!(
+                "client[{:?}]: tried to create plain {:?} transport when it already exists",
+                self.identity, transport_type
+            );
+            return Err(TransportAlreadyExists);
+        }
+
+        let router = match transport_type {
+            Send => self.channel.main_router(),
+            Receive => self.channel.pick_consumer_router().await,
+        };
+
+        // the bot's address is only known once it sends something
+        let comedia = matches!(transport_type, Send);
+
+        let transport = self
+            .channel
+            .create_plain_transport(&router, comedia)
+            .await
+            .map_err(|e| {
+                error!(
+                    "client[{:?}]: plain transport {:?} create failed: {}",
+                    self.identity, transport_type, e
+                );
+                TransportCreateFailed
+            })?;
+
+        let reply = CreatePlainTransportReply {
+            id: transport.id().to_string(),
+            tuple: transport.tuple(),
+        };
+
+        info!(
+            "client[{:?}]: plain {:?} transport created, id: {}",
+            self.identity,
+            transport_type,
+            transport.id()
+        );
+
+        if let Receive = transport_type {
+            *self.consumer_router.lock().unwrap() = Some(router);
+        }
+
+        let transport = ClientTransport::Plain(transport);
+        transport.log_state_changes(&self.identity, transport_type);
+
+        *transport_to_assign.write().await = Some(transport);
+
+        Ok(reply)
+    }
+
+    pub async fn connect_plain_transport(
+        &self,
+        transport_type: TransportType,
+        request: &ConnectPlainTransportRequest,
+    ) -> Result<(), PlainTransportError> {
+        use PlainTransportError::*;
+
+        let transport = match transport_type {
+            TransportType::Send => &self.c2s_transport,
+            TransportType::Receive => &self.s2c_transport,
+        };
+
+        let transport = match transport.read().await.clone() {
+            Some(ClientTransport::Plain(transport)) => transport,
+            Some(ClientTransport::WebRtc(_)) => return Err(NotAPlainTransport),
+            None => return Err(TransportNotCreated),
+        };
+
+        transport
+            .connect(PlainTransportRemoteParameters {
+                ip: Some(request.ip),
+                port: Some(request.port),
+                rtcp_port: request.rtcp_port,
+                srtp_parameters: None,
+            })
+            .await
+            .map_err(|e| {
+                error!(
+                    "client[{:?}]: plain {:?} connect failed: {}",
+                    self.identity, transport_type, e
+                );
+                TransportConnectFailed
+            })?;
+
+        info!(
+            "client[{:?}]: plain {:?} transport connected to {}:{}",
+            self.identity, transport_type, request.ip, request.port
+        );
+
+        Ok(())
+    }
+}
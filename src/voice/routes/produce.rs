@@ -1,19 +1,21 @@
 // implement the produce request
+use std::sync::Arc;
+
 use actix_web::error::ResponseError;
 use actix_web::post;
 use actix_web::web::Json;
 use derive_more::{Display, Error};
-use log::error;
+use log::{error, warn};
 use mediasoup::rtp_observer::{RtpObserver, RtpObserverAddProducerOptions};
 use mediasoup::{
-    producer::ProducerOptions,
+    prelude::AppData,
+    producer::{Producer, ProducerOptions},
     rtp_parameters::{MediaKind, RtpParameters},
-    transport::Transport,
 };
 use serde::{Deserialize, Serialize};
 use utoipa::ToSchema;
 
-use crate::voice::client::VoiceClientEx;
+use crate::voice::client::{VoiceClient, VoiceClientEx};
 
 #[derive(Debug, Deserialize, ToSchema)]
 #[serde(rename_all = "camelCase")]
@@ -22,6 +24,53 @@ pub struct ProduceRequest {
     pub kind: MediaKind,
     #[schema(value_type = Object)]
     pub rtp_parameters: RtpParameters,
+    /// Where the media comes from. Defaults to `mic` for audio and `camera`
+    /// for video.
+    pub source: Option<ProducerSource>,
+}
+
+/// Where a producer's media comes from, so that peers can tell a camera from a
+/// screen share
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "kebab-case")]
+pub enum ProducerSource {
+    Mic,
+    Camera,
+    Screen,
+    ScreenAudio,
+}
+
+impl ProducerSource {
+    pub fn kind(self) -> MediaKind {
+        match self {
+            Self::Mic | Self::ScreenAudio => MediaKind::Audio,
+            Self::Camera | Self::Screen => MediaKind::Video,
+        }
+    }
+
+    /// The source of a producer, as given when it was produced
+    pub fn of(producer: &Producer) -> Self {
+        match producer.app_data().downcast_ref::<Self>() {
+            Some(source) => *source,
+            None => Self::default_for(producer.kind()),
+        }
+    }
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Mic => "mic",
+            Self::Camera => "camera",
+            Self::Screen => "screen",
+            Self::ScreenAudio => "screen-audio",
+        }
+    }
+
+    pub fn default_for(kind: MediaKind) -> Self {
+        match kind {
+            MediaKind::Audio => Self::Mic,
+            MediaKind::Video => Self::Camera,
+        }
+    }
 }
 
 #[derive(Debug, Display, Error)]
@@ -29,6 +78,7 @@ pub struct ProduceRequest {
 pub enum ProduceError {
     TransportNotCreated,
     TransportNotConnected,
+    SourceDoesNotMatchKind,
     ProducerFailed,
 }
 
@@ -39,6 +89,7 @@ impl ResponseError for ProduceError {
         match self {
             TransportNotCreated => StatusCode::BAD_REQUEST,
             TransportNotConnected => StatusCode::BAD_REQUEST,
+            SourceDoesNotMatchKind => StatusCode::BAD_REQUEST,
             ProducerFailed => StatusCode::INTERNAL_SERVER_ERROR,
         }
     }
@@ -61,6 +112,9 @@ pub type ProduceResponse = Result<Json<ProduceReply>, ProduceError>;
 /// Connected peers are notified of the creation of this producer, and it can be
 /// discovered through the peers list endpoint, ready to be `consume()`d.
 ///
+/// Video can be simulcast with VP8 or H264, or use SVC layers with VP9. Peers
+/// consuming it can then pick the layers they want.
+///
 /// This endpoint requires a created and connected `send` transport.
 #[utoipa::path(
     tag = "voice",
@@ -75,52 +129,81 @@ pub async fn handle_produce(
     client: VoiceClientEx,
     request: Json<ProduceRequest>,
 ) -> ProduceResponse {
-    if client.c2s_transport.read().await.is_none() {
-        return Err(ProduceError::TransportNotCreated);
-    }
+    client.produce(&request).await.map(Json)
+}
 
-    let producer;
+impl VoiceClient {
+    pub async fn produce(
+        self: &Arc<Self>,
+        request: &ProduceRequest,
+    ) -> Result<ProduceReply, ProduceError> {
+        if self.c2s_transport.read().await.is_none() {
+            return Err(ProduceError::TransportNotCreated);
+        }
 
-    {
-        let transport = client.c2s_transport.read().await;
-        let transport = transport.as_ref().unwrap(); // this is a safe unwrap because of the check above
+        let source = request
+            .source
+            .unwrap_or(ProducerSource::default_for(request.kind));
 
-        if transport.closed() {
-            return Err(ProduceError::TransportNotConnected);
+        if source.kind() != request.kind {
+            return Err(ProduceError::SourceDoesNotMatchKind);
         }
 
-        producer = transport
-            .produce(ProducerOptions::new(
-                request.kind,
-                request.rtp_parameters.clone(),
-            ))
-            .await
-            .map_err(|e| {
-                error!("client[{:?}]: produce() failed: {}", client.identity, e);
+        let producer;
+
+        {
+            let transport = self.c2s_transport.read().await;
+            let transport = transport.as_ref().unwrap(); // this is a safe unwrap because of the check above
+
+            if transport.closed() {
+                return Err(ProduceError::TransportNotConnected);
+            }
+
+            let mut options = ProducerOptions::new(request.kind, request.rtp_parameters.clone());
+            options.app_data = AppData::new(source);
+            // nobody gets to hear someone who was muted by a moderator
+            options.paused =
+                request.kind == MediaKind::Audio && self.state.lock().unwrap().server_muted;
+
+            producer = transport.produce(options).await.map_err(|e| {
+                error!("client[{:?}]: produce() failed: {}", self.identity, e);
                 ProduceError::ProducerFailed
             })?
-    };
-
-    client
-        .channel
-        .al_observer
-        .add_producer(RtpObserverAddProducerOptions::new(producer.id()))
-        .await
-        .unwrap();
-
-    let id = producer.id().to_string();
-    let kind = producer.kind();
-
-    client
-        .producers
-        .lock()
-        .unwrap()
-        .insert(id.clone(), producer);
-
-    client
-        .channel
-        .notify_new_producer(&client, id.clone(), kind)
-        .await;
-
-    Ok(Json(ProduceReply { id }))
+        };
+
+        // only people speaking into their mic count as speaking
+        if source == ProducerSource::Mic {
+            if let Err(e) = self
+                .channel
+                .al_observer
+                .add_producer(RtpObserverAddProducerOptions::new(producer.id()))
+                .await
+            {
+                warn!(
+                    "client[{:?}]: observing volume of producer {} failed: {}",
+                    self.identity,
+                    producer.id(),
+                    e
+                );
+            }
+        }
+
+        let producer_id = producer.id();
+        let id = producer_id.to_string();
+        let kind = producer.kind();
+
+        self.add_producer(producer);
+
+        if kind == MediaKind::Audio {
+            self.channel
+                .record_if_recording(self, producer_id, source)
+                .await;
+        }
+
+        self.channel
+            .notify_new_producer(self, id.clone(), kind, source)
+            .await;
+
+        Ok(ProduceReply { id })
+    }
 }
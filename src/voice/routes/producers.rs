@@ -0,0 +1,176 @@
+use actix_web::{delete, post, web::Path, HttpResponse};
+use log::error;
+use mediasoup::{producer::Producer, rtp_parameters::MediaKind};
+use serde::Deserialize;
+use utoipa::IntoParams;
+
+use crate::{
+    error::{macros::err, HResult, IntoHandlerErrorResult},
+    voice::client::{VoiceClient, VoiceClientEx},
+};
+
+#[derive(Deserialize, IntoParams)]
+pub struct ProducerPath {
+    /// One of your own producers
+    pub producer_id: String,
+}
+
+/// Close producer
+///
+/// Stops producing, for example when you turn off your camera. Everyone's
+/// consumers of the producer are closed along with it, and everyone else is
+/// sent a `producer_closed` event.
+#[utoipa::path(
+    tag = "voice",
+    security(("voice" = [])),
+    params(ProducerPath),
+    responses(
+        (status = OK, description = "Producer closed"),
+        (status = NOT_FOUND, description = "No such producer"),
+    )
+)]
+#[delete("/voice/producers/{producer_id}")]
+pub async fn close_producer(
+    client: VoiceClientEx,
+    path: Path<ProducerPath>,
+) -> HResult<HttpResponse> {
+    client.close_producer(&path.producer_id).await?;
+    Ok(HttpResponse::Ok().finish())
+}
+
+/// Pause producer
+///
+/// Stops sending media to everyone consuming the producer until it is resumed,
+/// without having to produce again. Everyone else is sent a `producer_paused`
+/// event.
+#[utoipa::path(
+    tag = "voice",
+    security(("voice" = [])),
+    params(ProducerPath),
+    responses(
+        (status = OK, description = "Producer paused"),
+        (status = NOT_FOUND, description = "No such producer"),
+    )
+)]
+#[post("/voice/producers/{producer_id}/pause")]
+pub async fn pause_producer(
+    client: VoiceClientEx,
+    path: Path<ProducerPath>,
+) -> HResult<HttpResponse> {
+    client.pause_producer(&path.producer_id).await?;
+    Ok(HttpResponse::Ok().finish())
+}
+
+/// Resume producer
+///
+/// Starts sending media of a paused producer again. Everyone else is sent a
+/// `producer_resumed` event. Audio can't be resumed while you are muted by a
+/// moderator.
+#[utoipa::path(
+    tag = "voice",
+    security(("voice" = [])),
+    params(ProducerPath),
+    responses(
+        (status = OK, description = "Producer resumed"),
+        (status = FORBIDDEN, description = "Muted by a moderator"),
+        (status = NOT_FOUND, description = "No such producer"),
+    )
+)]
+#[post("/voice/producers/{producer_id}/resume")]
+pub async fn resume_producer(
+    client: VoiceClientEx,
+    path: Path<ProducerPath>,
+) -> HResult<HttpResponse> {
+    client.resume_producer(&path.producer_id).await?;
+    Ok(HttpResponse::Ok().finish())
+}
+
+impl VoiceClient {
+    pub async fn close_producer(&self, producer_id: &str) -> HResult<()> {
+        let producer = self
+            .producers
+            .lock()
+            .unwrap()
+            .remove(producer_id)
+            .or_err(404)?;
+        // producers lock is released here
+
+        // the client holds the only handle to the producer, so dropping it
+        // closes it, which closes everyone's consumers of it on this router
+        let id = producer.id();
+        drop(producer);
+
+        // and the channel holds its copies on the other routers
+        self.channel.unpipe_producer(id).await;
+
+        self.channel
+            .notify_producer_update(self, "producer_closed", producer_id)
+            .await;
+
+        Ok(())
+    }
+
+    pub async fn pause_producer(&self, producer_id: &str) -> HResult<()> {
+        let producer = self.get_producer(producer_id)?;
+
+        producer
+            .pause()
+            .await
+            .map_err(|e| {
+                error!(
+                    "client[{:?}]: pausing producer {} failed: {}",
+                    self.identity, producer_id, e
+                );
+                e
+            })
+            .or_err(500)?;
+
+        self.paused_producers
+            .lock()
+            .unwrap()
+            .insert(producer_id.to_string());
+
+        self.channel
+            .notify_producer_update(self, "producer_paused", producer_id)
+            .await;
+
+        Ok(())
+    }
+
+    pub async fn resume_producer(&self, producer_id: &str) -> HResult<()> {
+        let producer = self.get_producer(producer_id)?;
+
+        if producer.kind() == MediaKind::Audio && self.state.lock().unwrap().server_muted {
+            return err!(403, "You were muted by a moderator.");
+        }
+
+        producer
+            .resume()
+            .await
+            .map_err(|e| {
+                error!(
+                    "client[{:?}]: resuming producer {} failed: {}",
+                    self.identity, producer_id, e
+                );
+                e
+            })
+            .or_err(500)?;
+
+        self.paused_producers.lock().unwrap().remove(producer_id);
+
+        self.channel
+            .notify_producer_update(self, "producer_resumed", producer_id)
+            .await;
+
+        Ok(())
+    }
+
+    fn get_producer(&self, producer_id: &str) -> HResult<Producer> {
+        self.producers
+            .lock()
+            .unwrap()
+            .get(producer_id)
+            .cloned()
+            .or_err(404)
+    }
+}
@@ -0,0 +1,297 @@
+use std::sync::Arc;
+
+use actix_web::{
+    delete, get, post,
+    web::{Data, Json, Path},
+};
+use chrono::NaiveDateTime;
+use nanoid::nanoid;
+use serde::{Deserialize, Serialize};
+use utoipa::{IntoParams, ToSchema};
+
+use crate::{
+    auth::access_token::AccessToken,
+    db::DB,
+    error::{macros::err, HResult, IntoHandlerErrorResult},
+    media::{
+        record::Media, routes::upload::UploadedFileInfo, signing::sign_file_info, storage::Storage,
+    },
+    voice::{recording::Recording, VoiceChannels},
+};
+
+#[derive(Deserialize, IntoParams)]
+pub struct VoiceChannelPath {
+    /// A voice channel in a guild
+    pub channel_id: String,
+}
+
+#[derive(Serialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct RecordingInfo {
+    #[schema(example = "CAxTsV4Mx0oCSdN1aNu7G")]
+    id: String,
+    #[schema(example = "jqNNyhSbOl1AwqCTMAZ2G")]
+    channel_id: String,
+    /// The user who started the recording
+    #[schema(example = "xoKM4W7NDqHjK_V0g9s3y")]
+    started_by: String,
+    started_at: NaiveDateTime,
+    /// Not set while the recording is still going
+    ended_at: Option<NaiveDateTime>,
+    tracks: Vec<RecordingTrack>,
+}
+
+/// Everything one producer said during a recording. Tracks all start when the
+/// recording started, so they can be played back together.
+#[derive(Serialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct RecordingTrack {
+    #[schema(example = "xoKM4W7NDqHjK_V0g9s3y")]
+    user_id: String,
+    /// `mic` or `screen-audio`
+    #[schema(example = "mic")]
+    source: String,
+    /// An Ogg Opus file
+    file: UploadedFileInfo,
+}
+
+/// Start recording
+///
+/// Starts recording everyone speaking in a voice channel, including people
+/// who join later. Each microphone or screen share audio is recorded into an
+/// Ogg Opus file of its own. Everyone in the channel is told with a
+/// `recording_started` event on the voice websocket, and people joining while
+/// it is going are told through `recording` in the reply to `/voice/join`.
+///
+/// Recordings stop when they are stopped or when everyone has left the
+/// channel. The files count towards the storage quota of whoever started the
+/// recording.
+///
+/// Requires permission to record voice channels in the guild.
+#[utoipa::path(
+    tag = "voice",
+    security(("token" = [])),
+    params(VoiceChannelPath),
+    responses(
+        (status = OK, description = "Recording started", body = RecordingInfo),
+        (status = BAD_REQUEST, description = "Nobody is in the voice channel"),
+        (status = FORBIDDEN, description = "No permission to record voice channels"),
+        (status = NOT_FOUND, description = "No such voice channel"),
+        (status = CONFLICT, description = "The channel is already being recorded"),
+    )
+)]
+#[post("/channels/{channel_id}/recording")]
+pub async fn start_recording(
+    db: DB,
+    storage: Storage,
+    token: AccessToken,
+    path: Path<VoiceChannelPath>,
+    channels: Data<VoiceChannels>,
+) -> HResult<Json<RecordingInfo>> {
+    check_can_record(&db, &token.user_id, &path.channel_id).await?;
+
+    let channel = channels.lock().unwrap().get(&path.channel_id).cloned();
+    // channels lock is released here
+
+    let channel = channel.or_err_msg(400, "Nobody is in the voice channel.")?;
+
+    if channel.recording.lock().await.is_some() {
+        return err!(409);
+    }
+
+    let id = nanoid!();
+    sqlx::query!(
+        "INSERT INTO voice_recordings (id, channel_id, started_by) VALUES ($1, $2, $3)",
+        id,
+        path.channel_id,
+        token.user_id
+    )
+    .execute(&db.pool)
+    .await?;
+
+    let recording = Recording::new(
+        id.clone(),
+        token.user_id.clone(),
+        db.clone(),
+        storage.clone(),
+    );
+
+    if channel.start_recording(Arc::new(recording)).await.is_err() {
+        // someone else started one in the meantime
+        sqlx::query!("DELETE FROM voice_recordings WHERE id = $1", id)
+            .execute(&db.pool)
+            .await?;
+        return err!(409);
+    }
+
+    recordings_in(&db, &path.channel_id, Some(&id))
+        .await?
+        .pop()
+        .or_err(500)
+        .map(Json)
+}
+
+/// Stop recording
+///
+/// Stops recording a voice channel and replies with the recording once its
+/// files are ready. Everyone in the channel is told with a `recording_stopped`
+/// event on the voice websocket.
+///
+/// Requires permission to record voice channels in the guild.
+#[utoipa::path(
+    tag = "voice",
+    security(("token" = [])),
+    params(VoiceChannelPath),
+    responses(
+        (status = OK, description = "Recording stopped", body = RecordingInfo),
+        (status = FORBIDDEN, description = "No permission to record voice channels"),
+        (status = NOT_FOUND, description = "No such voice channel, or it isn't being recorded"),
+    )
+)]
+#[delete("/channels/{channel_id}/recording")]
+pub async fn stop_recording(
+    db: DB,
+    token: AccessToken,
+    path: Path<VoiceChannelPath>,
+    channels: Data<VoiceChannels>,
+) -> HResult<Json<RecordingInfo>> {
+    check_can_record(&db, &token.user_id, &path.channel_id).await?;
+
+    let channel = channels.lock().unwrap().get(&path.channel_id).cloned();
+    // channels lock is released here
+
+    let recording = match channel {
+        Some(channel) => channel.stop_recording().await,
+        None => None,
+    };
+
+    let Some(recording) = recording else {
+        return err!(404, "The channel is not being recorded.");
+    };
+
+    let id = recording.id.clone();
+    recording.finish().await;
+
+    recordings_in(&db, &path.channel_id, Some(&id))
+        .await?
+        .pop()
+        .or_err(500)
+        .map(Json)
+}
+
+/// List recordings
+///
+/// Lists the recordings of a voice channel, newest first. The files can be
+/// downloaded by anyone who can join the channel.
+#[utoipa::path(
+    tag = "voice",
+    security(("token" = [])),
+    params(VoiceChannelPath),
+    responses(
+        (status = OK, description = "Recordings of the channel", body = Vec<RecordingInfo>),
+        (status = FORBIDDEN, description = "Not a voice channel you can join"),
+    )
+)]
+#[get("/channels/{channel_id}/recordings")]
+pub async fn list_recordings(
+    db: DB,
+    token: AccessToken,
+    path: Path<VoiceChannelPath>,
+) -> HResult<Json<Vec<RecordingInfo>>> {
+    if !db
+        .can_user_connect_to_voice_in(&token.user_id, &path.channel_id)
+        .await?
+    {
+        return err!(403);
+    }
+
+    recordings_in(&db, &path.channel_id, None).await.map(Json)
+}
+
+async fn check_can_record(db: &DB, user_id: &str, channel_id: &str) -> HResult<()> {
+    let channel = sqlx::query!(
+        "SELECT guild_id FROM channels WHERE id = $1 AND type = 'voice'",
+        channel_id
+    )
+    .fetch_optional(&db.pool)
+    .await?
+    .or_err(404)?;
+
+    if !db
+        .can_user_record_voice_in(user_id, &channel.guild_id)
+        .await?
+    {
+        return err!(403);
+    }
+
+    Ok(())
+}
+
+/// The recordings of a channel with their tracks, or only one of them
+async fn recordings_in(
+    db: &DB,
+    channel_id: &str,
+    recording_id: Option<&str>,
+) -> HResult<Vec<RecordingInfo>> {
+    let recordings = sqlx::query!(
+        r#"SELECT id, channel_id, started_by, started_at, ended_at
+            FROM voice_recordings
+            WHERE channel_id = $1 AND ($2::text IS NULL OR id = $2)
+            ORDER BY started_at DESC"#,
+        channel_id,
+        recording_id
+    )
+    .fetch_all(&db.pool)
+    .await?;
+
+    let ids: Vec<String> = recordings.iter().map(|r| r.id.clone()).collect();
+
+    let tracks = sqlx::query!(
+        r#"SELECT voice_recording_tracks.recording_id, voice_recording_tracks.user_id,
+                voice_recording_tracks.source, media.id, media.uploader, media.name, media.size,
+                media.sha256, media.mime_type, media.width, media.height, media.created_at
+            FROM voice_recording_tracks, media
+            WHERE voice_recording_tracks.recording_id = ANY($1)
+            AND media.id = voice_recording_tracks.media_id
+            ORDER BY media.created_at"#,
+        &ids
+    )
+    .fetch_all(&db.pool)
+    .await?;
+
+    let mut infos: Vec<RecordingInfo> = recordings
+        .into_iter()
+        .map(|r| RecordingInfo {
+            id: r.id,
+            channel_id: r.channel_id,
+            started_by: r.started_by,
+            started_at: r.started_at,
+            ended_at: r.ended_at,
+            tracks: vec![],
+        })
+        .collect();
+
+    for track in tracks {
+        let media = Media {
+            id: track.id,
+            uploader: track.uploader,
+            name: track.name,
+            size: track.size,
+            sha256: track.sha256,
+            mime_type: track.mime_type,
+            width: track.width,
+            height: track.height,
+            created_at: track.created_at,
+        };
+
+        if let Some(info) = infos.iter_mut().find(|i| i.id == track.recording_id) {
+            info.tracks.push(RecordingTrack {
+                user_id: track.user_id,
+                source: track.source,
+                file: sign_file_info(media.file_info()),
+            });
+        }
+    }
+
+    Ok(infos)
+}
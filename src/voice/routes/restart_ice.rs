@@ -0,0 +1,33 @@
+use actix_web::{
+    post,
+    web::{Json, Query},
+};
+use mediasoup::prelude::IceParameters;
+
+use crate::{
+    error::HResult,
+    voice::{client::VoiceClientEx, routes::create_transport::TransportTypeQuery},
+};
+
+/// Restart ICE
+///
+/// Restarts ICE on a WebRTC transport, for when the client's network changed
+/// and media stopped flowing, for example after switching from Wi-Fi to mobile
+/// data. Feed the reply into the clientside transport's `restartIce()`.
+#[utoipa::path(
+    tag = "voice",
+    security(("voice" = [])),
+    params(TransportTypeQuery),
+    responses(
+        (status = OK, description = "The transport's new ICE parameters", body = Object),
+        (status = BAD_REQUEST, description = "No WebRTC transport of this type has been created yet"),
+        (status = INTERNAL_SERVER_ERROR, description = "Restarting ICE failed on the serverside"),
+    )
+)]
+#[post("/voice/transport/restart-ice")]
+pub async fn restart_ice(
+    client: VoiceClientEx,
+    query: Query<TransportTypeQuery>,
+) -> HResult<Json<IceParameters>> {
+    client.restart_ice(query.transport_type).await.map(Json)
+}
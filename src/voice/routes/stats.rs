@@ -0,0 +1,229 @@
+use actix_web::{
+    get,
+    web::{Data, Json, Path},
+};
+use log::warn;
+use mediasoup::rtp_parameters::MediaKind;
+use serde::Serialize;
+use serde_json::Value;
+use utoipa::ToSchema;
+
+use crate::{
+    auth::access_token::AccessToken,
+    db::DB,
+    error::{macros::err, HResult, IntoHandlerErrorResult},
+    voice::{
+        client::{VoiceClient, VoiceClientEx},
+        routes::{produce::ProducerSource, recording::VoiceChannelPath},
+        transport::TransportType,
+        VoiceChannels,
+    },
+};
+
+/// What mediasoup knows about how a voice connection is doing. The `stats`
+/// objects are mediasoup's own, see [its
+/// documentation](https://mediasoup.org/documentation/v3/mediasoup/rtc-statistics/)
+/// for what's in them.
+#[derive(Serialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct VoiceClientStats {
+    identity: String,
+    #[schema(example = "xoKM4W7NDqHjK_V0g9s3y")]
+    user_id: String,
+    /// Bitrates and, for WebRTC, the ICE and DTLS state and the selected tuple
+    #[schema(value_type = Option<Object>)]
+    send_transport: Option<Value>,
+    #[schema(value_type = Option<Object>)]
+    recv_transport: Option<Value>,
+    producers: Vec<VoiceProducerStats>,
+    consumers: Vec<VoiceConsumerStats>,
+}
+
+#[derive(Serialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct VoiceProducerStats {
+    #[schema(value_type = uuid::Uuid)]
+    id: String,
+    #[schema(value_type = String, example = "audio")]
+    kind: MediaKind,
+    source: ProducerSource,
+    paused: bool,
+    /// Bitrate, packet loss and round trip time of each stream that is received
+    #[schema(value_type = Vec<Object>)]
+    stats: Value,
+}
+
+#[derive(Serialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct VoiceConsumerStats {
+    #[schema(value_type = uuid::Uuid)]
+    id: String,
+    #[schema(value_type = uuid::Uuid)]
+    producer_id: String,
+    #[schema(value_type = String, example = "audio")]
+    kind: MediaKind,
+    paused: bool,
+    /// Bitrate, packet loss and round trip time of what is sent, and of the
+    /// producer it comes from
+    #[schema(value_type = Vec<Object>)]
+    stats: Value,
+}
+
+/// Get voice stats
+///
+/// Shows how your voice connection is doing from the server's side, which is
+/// useful for finding out why someone sounds bad. Compare it with your
+/// client's own WebRTC stats.
+#[utoipa::path(
+    tag = "voice",
+    security(("voice" = [])),
+    responses(
+        (status = OK, description = "Stats of your voice connection", body = VoiceClientStats),
+    )
+)]
+#[get("/voice/stats")]
+pub async fn get_voice_stats(client: VoiceClientEx) -> HResult<Json<VoiceClientStats>> {
+    Ok(Json(client.stats().await))
+}
+
+/// Get voice channel stats
+///
+/// Shows how the voice connection of everyone in a voice channel is doing.
+///
+/// Requires permission to moderate voice channels in the guild.
+#[utoipa::path(
+    tag = "voice",
+    security(("token" = [])),
+    params(VoiceChannelPath),
+    responses(
+        (status = OK, description = "Stats of everyone in the channel", body = Vec<VoiceClientStats>),
+        (status = FORBIDDEN, description = "No permission to moderate voice channels"),
+        (status = NOT_FOUND, description = "No such voice channel"),
+    )
+)]
+#[get("/channels/{channel_id}/voice/stats")]
+pub async fn get_voice_channel_stats(
+    db: DB,
+    token: AccessToken,
+    path: Path<VoiceChannelPath>,
+    channels: Data<VoiceChannels>,
+) -> HResult<Json<Vec<VoiceClientStats>>> {
+    let channel = sqlx::query!(
+        "SELECT guild_id FROM channels WHERE id = $1 AND type = 'voice'",
+        path.channel_id
+    )
+    .fetch_optional(&db.pool)
+    .await?
+    .or_err(404)?;
+
+    if !db
+        .can_user_moderate_voice_in(&token.user_id, &channel.guild_id)
+        .await?
+    {
+        return err!(403);
+    }
+
+    let voice_channel = channels.lock().unwrap().get(&path.channel_id).cloned();
+    // channels lock is released here
+
+    // nobody is in the channel
+    let Some(voice_channel) = voice_channel else {
+        return Ok(Json(vec![]));
+    };
+
+    let clients = voice_channel.clients.lock().await.clone();
+
+    let mut stats = Vec::with_capacity(clients.len());
+    for client in clients {
+        stats.push(client.stats().await);
+    }
+
+    Ok(Json(stats))
+}
+
+impl VoiceClient {
+    /// Producers, consumers and transports that close while this runs are
+    /// left out rather than failing the whole thing.
+    pub async fn stats(&self) -> VoiceClientStats {
+        let producers: Vec<_> = self.producers.lock().unwrap().values().cloned().collect();
+        let consumers: Vec<_> = self.consumers.lock().unwrap().values().cloned().collect();
+
+        let mut producer_stats = Vec::with_capacity(producers.len());
+        for producer in producers {
+            if producer.closed() {
+                continue;
+            }
+            let stats = match producer.get_stats().await {
+                Ok(stats) => stats,
+                Err(e) => {
+                    self.stats_failed("producer", e);
+                    continue;
+                }
+            };
+
+            producer_stats.push(VoiceProducerStats {
+                id: producer.id().to_string(),
+                kind: producer.kind(),
+                source: ProducerSource::of(&producer),
+                paused: producer.paused(),
+                stats: serde_json::to_value(stats).unwrap_or_default(),
+            });
+        }
+
+        let mut consumer_stats = Vec::with_capacity(consumers.len());
+        for consumer in consumers {
+            if consumer.closed() {
+                continue;
+            }
+            let stats = match consumer.get_stats().await {
+                Ok(stats) => stats,
+                Err(e) => {
+                    self.stats_failed("consumer", e);
+                    continue;
+                }
+            };
+
+            consumer_stats.push(VoiceConsumerStats {
+                id: consumer.id().to_string(),
+                producer_id: consumer.producer_id().to_string(),
+                kind: consumer.kind(),
+                paused: consumer.paused(),
+                stats: serde_json::to_value(stats).unwrap_or_default(),
+            });
+        }
+
+        VoiceClientStats {
+            identity: self.identity.clone(),
+            user_id: self.user.id.clone(),
+            send_transport: self.transport_stats(TransportType::Send).await,
+            recv_transport: self.transport_stats(TransportType::Receive).await,
+            producers: producer_stats,
+            consumers: consumer_stats,
+        }
+    }
+
+    async fn transport_stats(&self, transport_type: TransportType) -> Option<Value> {
+        let transport = match transport_type {
+            TransportType::Send => &self.c2s_transport,
+            TransportType::Receive => &self.s2c_transport,
+        };
+
+        let transport = transport.read().await.clone();
+
+        match transport {
+            Some(transport) => transport
+                .get_stats()
+                .await
+                .map_err(|e| self.stats_failed("transport", e))
+                .ok(),
+            None => None,
+        }
+    }
+
+    fn stats_failed(&self, of: &str, e: impl std::fmt::Display) {
+        warn!(
+            "client[{:?}]: getting {} stats failed: {}",
+            self.identity, of, e
+        );
+    }
+}
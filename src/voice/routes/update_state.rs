@@ -0,0 +1,52 @@
+use actix_web::{post, web::Json};
+use serde::Deserialize;
+use utoipa::ToSchema;
+
+use crate::voice::{client::VoiceClientEx, state::VoiceState};
+
+/// Fields that are left out stay as they are.
+#[derive(Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct UpdateVoiceStateRequest {
+    muted: Option<bool>,
+    deafened: Option<bool>,
+    video: Option<bool>,
+    screen_share: Option<bool>,
+}
+
+/// Update voice state
+///
+/// Lets everyone know that you muted or deafened yourself, or started or
+/// stopped sharing video or your screen. This is only shown to others, you
+/// still need to stop or pause your producers and consumers yourself.
+///
+/// Peers in the channel are sent a `state_update` event, and the guild gets a
+/// `voiceStateUpdate` event.
+#[utoipa::path(
+    tag = "voice",
+    security(("voice" = [])),
+    request_body = UpdateVoiceStateRequest,
+    responses(
+        (status = OK, description = "Your new voice state", body = VoiceState),
+    )
+)]
+#[post("/voice/state")]
+pub async fn update_state(
+    client: VoiceClientEx,
+    request: Json<UpdateVoiceStateRequest>,
+) -> Json<VoiceState> {
+    let state = {
+        let mut state = client.state.lock().unwrap();
+
+        state.muted = request.muted.unwrap_or(state.muted);
+        state.deafened = request.deafened.unwrap_or(state.deafened);
+        state.video = request.video.unwrap_or(state.video);
+        state.screen_share = request.screen_share.unwrap_or(state.screen_share);
+
+        *state
+    };
+
+    client.channel.publish_state(&client).await;
+
+    Json(state)
+}
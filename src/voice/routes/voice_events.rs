@@ -1,22 +1,24 @@
 use std::sync::Arc;
 
+use actix_rt::time::sleep;
 use actix_web::{
     get,
     web::{Data, Payload},
     Error, HttpRequest, HttpResponse,
 };
-use log::{error, info};
+use log::{debug, info};
 use mediasoup::rtp_parameters::MediaKind;
 use serde::Deserialize;
 use serde_json::json;
 
 use crate::{
     auth::user::PublicUserInfo,
-    error::macros::err,
+    options,
     realtime::socket::{SendFailureReason, Socket},
     voice::{
         channel::VoiceChannel,
         client::{VoiceClient, VoiceClientEx},
+        routes::produce::ProducerSource,
         VoiceChannels, VoiceClients,
     },
 };
@@ -41,10 +43,61 @@ pub struct IdAndToken {
 /// RTC Identity and Token must be sent in the query parameters of the intitial
 /// request, in the form `?i=identity` and `?t=token`.
 ///
+/// ## Reconnecting
+/// If the socket drops, for example because your network changed, connect it
+/// again with the same identity and token within 15 seconds (by default, see
+/// `VOICE_RECONNECT_GRACE`) to stay in the channel. There's no need to wait
+/// for the server to notice the old socket is gone, connecting a new one
+/// closes it. Peers aren't told you were gone, but you may have missed events
+/// in the meantime, so fetch `/voice/peers` again. If your media stopped
+/// flowing too, restart ICE on your transports with
+/// `/voice/transport/restart-ice`.
+///
+/// ## Signalling
+/// Instead of calling the REST endpoints one by one, you can make the same
+/// requests over this socket, which saves a round trip each. A request has an
+/// `id` of your choosing, which the response is sent back with. Requests are
+/// handled one at a time in the order they were sent, so there's no need to
+/// wait for a response before sending the next request.
+/// ```js
+/// {
+///     "id": 1,
+///     "method": "create_transport",
+///     "data": { "type": "send" },
+/// }
+/// ```
+///
+/// | Method | Data | Same as |
+/// |---|---|---|
+/// | `create_transport` | `{ type }` | `/voice/transport/create` |
+/// | `connect_transport` | `{ type, dtlsParameters }` | `/voice/transport/connect` |
+/// | `restart_ice` | `{ type }` | `/voice/transport/restart-ice` |
+/// | `create_plain_transport` | `{ type }` | `/voice/transport/plain/create` |
+/// | `connect_plain_transport` | `{ type, ip, port, rtcpPort }` | `/voice/transport/plain/connect` |
+/// | `produce` | `{ kind, rtpParameters }` | `/voice/produce` |
+/// | `consume` | `{ producerId, rtpCapabilities }` | `/voice/consume` |
+/// | `close_producer` | `{ producerId }` | `DELETE /voice/producers/{producer_id}` |
+/// | `pause_producer` | `{ producerId }` | `/voice/producers/{producer_id}/pause` |
+/// | `resume_producer` | `{ producerId }` | `/voice/producers/{producer_id}/resume` |
+/// | `close_consumer` | `{ consumerId }` | `DELETE /voice/consumers/{consumer_id}` |
+/// | `pause_consumer` | `{ consumerId }` | `/voice/consumers/{consumer_id}/pause` |
+/// | `resume_consumer` | `{ consumerId }` | `/voice/consumers/{consumer_id}/resume` |
+/// | `set_preferred_layers` | `{ consumerId, spatialLayer, temporalLayer }` | `/voice/consumers/{consumer_id}/layers` |
+///
+/// The response has either the `data` the endpoint would reply with, or an
+/// `error` with the status code it would fail with.
+/// ```js
+/// {
+///     "type": "response",
+///     "id": 1,
+///     "data": { ... },
+///     // or
+///     "error": { "code": 409, "message": "transport_already_exists" },
+/// }
+/// ```
+///
 /// ## Messages
-/// The connection is read-only, client to server signalling does not happen
-/// over this socket at all. These are the messages your client may receive and
-/// should respond to.
+/// These are the messages your client may receive and should respond to.
 ///
 /// #### Peer Joined
 /// ```js
@@ -63,14 +116,100 @@ pub struct IdAndToken {
 /// }
 /// ```
 ///
+/// #### Peer State Changed
+/// A peer muted, deafened, or started or stopped sharing video or their
+/// screen, or a moderator server muted or deafened them. This is sent for your
+/// own client too, since moderators can change your state. Your own mute and
+/// deafen are set with `/voice/state`.
+/// ```js
+/// {
+///     "type": "state_update",
+///     "identity": "YDjdIc06vuaVQZy4LS7hb",
+///     "state": {
+///         "muted": true,
+///         "deafened": false,
+///         "video": false,
+///         "screenShare": false,
+///         "serverMuted": false,
+///         "serverDeafened": false,
+///     },
+/// }
+/// ```
+///
 /// #### New Producer
-/// A peer created a new producer, you may consume it.
+/// A peer created a new producer, you may consume it. The source tells a
+/// camera apart from a screen share.
 /// ```js
 /// {
 ///     "type": "new_producer",
 ///     "identity": "YDjdIc06vuaVQZy4LS7hb",
 ///     "producer_id": "...",
 ///     "producer_kind": "audio|video",
+///     "producer_source": "mic|camera|screen|screen-audio",
+/// }
+/// ```
+///
+/// #### Producer Closed, Paused or Resumed
+/// A peer closed, paused or resumed one of their producers. When a producer is
+/// closed, your consumers of it are closed as well.
+/// ```js
+/// {
+///     "type": "producer_closed|producer_paused|producer_resumed",
+///     "identity": "YDjdIc06vuaVQZy4LS7hb",
+///     "producer_id": "...",
+/// }
+/// ```
+///
+/// #### Speaking
+/// A peer started or stopped speaking. Short pauses don't count as stopping.
+/// Your own client is included.
+/// ```js
+/// {
+///     "type": "speaking_start|speaking_stop",
+///     "identity": "YDjdIc06vuaVQZy4LS7hb",
+/// }
+/// ```
+///
+/// #### Recording Started or Stopped
+/// Someone started or stopped recording the channel. While it is being
+/// recorded, everything said in it is saved.
+/// ```js
+/// {
+///     "type": "recording_started",
+///     "recording_id": "CAxTsV4Mx0oCSdN1aNu7G",
+///     "started_by": "xoKM4W7NDqHjK_V0g9s3y",
+/// }
+/// // or
+/// {
+///     "type": "recording_stopped",
+///     "recording_id": "CAxTsV4Mx0oCSdN1aNu7G",
+/// }
+/// ```
+///
+/// #### Moved
+/// A moderator moved you to another voice channel. You have been disconnected
+/// from the current one and should join the new one.
+/// ```js
+/// {
+///     "type": "moved",
+///     "channel_id": "jqNNyhSbOl1AwqCTMAZ2G",
+/// }
+/// ```
+///
+/// #### Reconnect
+/// The voice server handling your connection had a problem. You have been
+/// disconnected and should join the channel again.
+/// ```js
+/// {
+///     "type": "reconnect",
+/// }
+/// ```
+///
+/// #### Disconnected
+/// A moderator disconnected you from the voice channel.
+/// ```js
+/// {
+///     "type": "disconnected",
 /// }
 /// ```
 #[utoipa::path(
@@ -82,7 +221,6 @@ pub struct IdAndToken {
     ),
     responses(
         (status = 101, description = "WebSocket connected successfully"),
-        (status = BAD_REQUEST, description = "Already connected"),
     )
 )]
 #[get("/voice/ws")] // WARNING: before changing this path, make sure to change it in the client extractor!!!
@@ -93,35 +231,34 @@ pub async fn voice_events_ws(
     req: HttpRequest,
     body: Payload,
 ) -> Result<HttpResponse, Error> {
-    if client.socket.read().await.is_some() {
-        err!(400, "Already connected to a voice event socket.")?;
-    }
-
     // this is ugly but needed so the `move` callbacks below can access the client
 
     let on_message_handler;
     let on_close_handler;
 
+    let socket_id = nanoid::nanoid!();
+
     {
-        // this is needed so the `move` callback below can access the client
-        let weak_client = Arc::downgrade(&client);
+        // requests are handled one at a time, in the order they were sent in
+        let signals = client.spawn_signal_handler();
+        let identity = client.identity.clone();
 
-        on_message_handler = Box::new(move |msg| {
-            let client = match weak_client.upgrade() {
-                Some(client) => client,
-                None => {
-                    // client is already deallocated
-                    return;
-                }
-            };
+        on_message_handler = Box::new(move |msg: String| {
+            debug!(
+                "client[{:?}]: received message on event socket ({} bytes)",
+                identity,
+                msg.len()
+            );
 
-            client.on_socket_message(msg)
+            // this only fails once the client is already deallocated
+            signals.send(msg).ok();
         });
     }
 
     {
         // this is needed so the `move` callback below can access the client
         let weak_client = Arc::downgrade(&client);
+        let socket_id = socket_id.clone();
 
         on_close_handler = Box::new(move |reason| {
             let client = match weak_client.upgrade() {
@@ -137,27 +274,16 @@ pub async fn voice_events_ws(
                 client.identity, reason
             );
 
-            {
-                // these arcs go in the closure below
-                let client = client.clone();
-                let clients = clients.clone();
-                let channels = channels.clone();
-                actix_rt::spawn(async move {
-                    if clients.lock().unwrap().contains_key(&client.identity) {
-                        // notify channel
-                        info!("calling channel.disconnect_client");
-                        client
-                            .channel
-                            .disconnect_client(&client, &clients, &channels)
-                            .await;
-                    }
-                });
-            }
+            actix_rt::spawn(client.on_socket_closed(
+                socket_id.clone(),
+                clients.clone(),
+                channels.clone(),
+            ));
         })
     }
 
     let (socket, response) = Socket::new_arc_from_request(
-        nanoid::nanoid!(),
+        socket_id,
         &req,
         body,
         // on message
@@ -171,18 +297,79 @@ pub async fn voice_events_ws(
         client.identity, client.channel.id
     );
 
-    *client.socket.write().await = Some(socket);
-    client.channel.notify_client_joined(&client).await;
+    let reconnect_watch = client.socket_reconnect_watch_handle.lock().unwrap().take();
+    if let Some(handle) = &reconnect_watch {
+        handle.abort();
+    }
+
+    // the old socket may not have noticed it's gone yet, in which case the new
+    // one takes its place. its close handler then finds it was replaced and
+    // leaves the client be.
+    let previous = client.socket.write().await.replace(socket);
+    if let Some(previous) = &previous {
+        previous.close().await;
+    }
+
+    if reconnect_watch.is_some() || previous.is_some() {
+        // peers never heard they were gone
+        info!("client[{:?}]: reconnected to event socket", client.identity);
+    } else {
+        client.channel.notify_client_joined(&client).await;
+        client.channel.publish_state(&client).await;
+    }
 
     Ok(response)
 }
 
 impl VoiceClient {
-    pub fn on_socket_message(&self, msg: String) {
-        info!(
-            "client[{:?}]: received message on event socket: {:?}",
-            self.identity, msg
-        );
+    /// Gives a client whose event socket closed some time to connect it again
+    /// with the same identity and token, before it is disconnected from the
+    /// channel.
+    async fn on_socket_closed(
+        self: Arc<Self>,
+        socket_id: String,
+        clients: Data<VoiceClients>,
+        channels: Data<VoiceChannels>,
+    ) {
+        {
+            let mut socket = self.socket.write().await;
+
+            // a new socket may already have taken its place
+            if !socket.as_ref().is_some_and(|socket| socket.id == socket_id) {
+                return;
+            }
+
+            *socket = None;
+        }
+
+        let client = self.clone();
+        let handle = actix_rt::spawn(async move {
+            sleep(*options::VOICE_RECONNECT_GRACE).await;
+
+            if client.socket.read().await.is_some() {
+                return;
+            }
+
+            if clients.lock().unwrap().contains_key(&client.identity) {
+                info!(
+                    "client[{:?}]: didn't reconnect to the event socket in time",
+                    client.identity
+                );
+                client
+                    .channel
+                    .disconnect_client(&client, &clients, &channels)
+                    .await;
+            }
+        });
+
+        if let Some(previous) = self
+            .socket_reconnect_watch_handle
+            .lock()
+            .unwrap()
+            .replace(handle)
+        {
+            previous.abort();
+        }
     }
 
     pub async fn send(&self, msg: String) -> Result<(), SendFailureReason> {
@@ -201,13 +388,18 @@ impl VoiceChannel {
                 continue;
             }
 
-            if client.send(msg.clone()).await.is_err() {
-                // this shouldn't happen
-                error!(
-                    "tried to send to disconnected client: {:?}",
-                    client.identity
-                );
-            };
+            // clients that haven't connected their socket yet, or are
+            // reconnecting it, miss out like in `send_to_all`
+            client.send(msg.clone()).await.ok();
+        }
+    }
+
+    pub async fn send_to_all(&self, msg: String) {
+        let clients = self.clients.lock().await;
+        for client in clients.iter() {
+            // clients that haven't connected their socket yet don't need to
+            // know about anything
+            client.send(msg.clone()).await.ok();
         }
     }
 
@@ -232,11 +424,30 @@ impl VoiceChannel {
         self.send_to_all_except(client, event.to_string()).await;
     }
 
+    /// Tells the channel and the guild about a client's voice state, after
+    /// they connect or when it changes. The client itself is told too, since
+    /// moderators can change it.
+    pub async fn publish_state(&self, client: &VoiceClient) {
+        let member = client.member_state();
+
+        let event = json!({
+            "type": "state_update",
+            "identity": client.identity,
+            "state": member.state,
+        });
+
+        self.send_to_all(event.to_string()).await;
+        self.pubsub
+            .notify_voice_state_update(&self.guild_id, Some(&self.id), &member)
+            .await;
+    }
+
     pub async fn notify_new_producer(
         &self,
         client: &VoiceClient,
         producer_id: String,
         producer_kind: MediaKind,
+        producer_source: ProducerSource,
     ) {
         // serialize the message
         let event = json!({
@@ -244,8 +455,35 @@ impl VoiceChannel {
             "identity": client.identity,
             "producer_id": producer_id,
             "producer_kind": producer_kind,
+            "producer_source": producer_source,
         });
 
         self.send_to_all_except(client, event.to_string()).await;
     }
+
+    /// Tells everyone else about something that happened to one of a client's
+    /// producers, `event_type` being the type of the event sent
+    pub async fn notify_producer_update(
+        &self,
+        client: &VoiceClient,
+        event_type: &str,
+        producer_id: &str,
+    ) {
+        let event = json!({
+            "type": event_type,
+            "identity": client.identity,
+            "producer_id": producer_id,
+        });
+
+        self.send_to_all_except(client, event.to_string()).await;
+    }
+
+    pub async fn notify_speaking(&self, identity: &str, speaking: bool) {
+        let event = json!({
+            "type": if speaking { "speaking_start" } else { "speaking_stop" },
+            "identity": identity,
+        });
+
+        self.send_to_all(event.to_string()).await;
+    }
 }
@@ -0,0 +1,217 @@
+use std::{net::IpAddr, sync::Arc};
+
+use actix_web::ResponseError;
+use log::warn;
+use mediasoup::prelude::DtlsParameters;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use tokio::sync::mpsc::{self, UnboundedSender};
+
+use crate::error::HandlerError;
+
+use super::{
+    client::VoiceClient,
+    routes::{
+        consume::ConsumeRequest, consumers::PreferredLayersRequest,
+        plain_transport::ConnectPlainTransportRequest, produce::ProduceRequest,
+    },
+    transport::TransportType,
+};
+
+/// What a client can ask for over the voice event socket. These do the same as
+/// the REST endpoints of the same name.
+#[derive(Deserialize)]
+#[serde(tag = "method", content = "data", rename_all = "snake_case")]
+enum SignalRequest {
+    CreateTransport {
+        #[serde(rename = "type")]
+        transport_type: TransportType,
+    },
+    #[serde(rename_all = "camelCase")]
+    ConnectTransport {
+        #[serde(rename = "type")]
+        transport_type: TransportType,
+        dtls_parameters: DtlsParameters,
+    },
+    RestartIce {
+        #[serde(rename = "type")]
+        transport_type: TransportType,
+    },
+    CreatePlainTransport {
+        #[serde(rename = "type")]
+        transport_type: TransportType,
+    },
+    #[serde(rename_all = "camelCase")]
+    ConnectPlainTransport {
+        #[serde(rename = "type")]
+        transport_type: TransportType,
+        ip: IpAddr,
+        port: u16,
+        rtcp_port: Option<u16>,
+    },
+    Produce(ProduceRequest),
+    Consume(ConsumeRequest),
+    #[serde(rename_all = "camelCase")]
+    CloseProducer {
+        producer_id: String,
+    },
+    #[serde(rename_all = "camelCase")]
+    PauseProducer {
+        producer_id: String,
+    },
+    #[serde(rename_all = "camelCase")]
+    ResumeProducer {
+        producer_id: String,
+    },
+    #[serde(rename_all = "camelCase")]
+    CloseConsumer {
+        consumer_id: String,
+    },
+    #[serde(rename_all = "camelCase")]
+    PauseConsumer {
+        consumer_id: String,
+    },
+    #[serde(rename_all = "camelCase")]
+    ResumeConsumer {
+        consumer_id: String,
+    },
+    #[serde(rename_all = "camelCase")]
+    SetPreferredLayers {
+        consumer_id: String,
+        spatial_layer: u8,
+        temporal_layer: Option<u8>,
+    },
+}
+
+impl VoiceClient {
+    /// Starts handling the requests sent over a voice event socket, which are
+    /// sent to the returned sender. They are handled one at a time in the
+    /// order they arrive, so for example a transport is connected before
+    /// anything is produced on it. Stops once the sender is dropped along
+    /// with the socket.
+    pub fn spawn_signal_handler(self: &Arc<Self>) -> UnboundedSender<String> {
+        let (sender, mut receiver) = mpsc::unbounded_channel();
+        let weak_client = Arc::downgrade(self);
+
+        actix_rt::spawn(async move {
+            while let Some(msg) = receiver.recv().await {
+                let Some(client) = weak_client.upgrade() else {
+                    // client is already deallocated
+                    return;
+                };
+
+                client.handle_signal(msg).await;
+            }
+        });
+
+        sender
+    }
+
+    /// Handles a request sent over the voice event socket, sending back a
+    /// response with the same id.
+    async fn handle_signal(self: &Arc<Self>, msg: String) {
+        let response = match serde_json::from_str::<Value>(&msg) {
+            Ok(mut message) => {
+                let id = message.get_mut("id").map(Value::take).unwrap_or_default();
+
+                match serde_json::from_value(message) {
+                    Ok(request) => respond(id, self.signal(request).await),
+                    Err(e) => respond(id, Err(HandlerError::with_code(400, e.to_string()))),
+                }
+            }
+            Err(e) => respond(
+                Value::Null,
+                Err(HandlerError::with_code(400, e.to_string())),
+            ),
+        };
+
+        if let Err(e) = self.send(response).await {
+            warn!(
+                "client[{:?}]: sending signalling response failed: {:?}",
+                self.identity, e
+            );
+        }
+    }
+
+    async fn signal(self: &Arc<Self>, request: SignalRequest) -> Result<Value, HandlerError> {
+        use SignalRequest::*;
+
+        match request {
+            CreateTransport { transport_type } => {
+                reply(self.create_transport(transport_type).await)
+            }
+            ConnectTransport {
+                transport_type,
+                dtls_parameters,
+            } => reply(
+                self.connect_transport(transport_type, dtls_parameters)
+                    .await,
+            ),
+            RestartIce { transport_type } => reply(self.restart_ice(transport_type).await),
+            CreatePlainTransport { transport_type } => {
+                reply(self.create_plain_transport(transport_type).await)
+            }
+            ConnectPlainTransport {
+                transport_type,
+                ip,
+                port,
+                rtcp_port,
+            } => {
+                let request = ConnectPlainTransportRequest {
+                    ip,
+                    port,
+                    rtcp_port,
+                };
+                reply(self.connect_plain_transport(transport_type, &request).await)
+            }
+            Produce(request) => reply(self.produce(&request).await),
+            Consume(request) => reply(self.consume(&request).await),
+            CloseProducer { producer_id } => reply(self.close_producer(&producer_id).await),
+            PauseProducer { producer_id } => reply(self.pause_producer(&producer_id).await),
+            ResumeProducer { producer_id } => reply(self.resume_producer(&producer_id).await),
+            CloseConsumer { consumer_id } => reply(self.close_consumer(&consumer_id)),
+            PauseConsumer { consumer_id } => reply(self.pause_consumer(&consumer_id).await),
+            ResumeConsumer { consumer_id } => reply(self.resume_consumer(&consumer_id).await),
+            SetPreferredLayers {
+                consumer_id,
+                spatial_layer,
+                temporal_layer,
+            } => {
+                let layers = PreferredLayersRequest {
+                    spatial_layer,
+                    temporal_layer,
+                };
+                reply(self.set_preferred_layers(&consumer_id, &layers).await)
+            }
+        }
+    }
+}
+
+/// Turns the result of a request into what is sent back, errors getting the
+/// same status code they would have over REST
+fn reply<T: Serialize, E: ResponseError>(result: Result<T, E>) -> Result<Value, HandlerError> {
+    match result {
+        Ok(data) => Ok(serde_json::to_value(data).unwrap_or_default()),
+        Err(e) => Err(HandlerError::with_code(
+            e.status_code().as_u16(),
+            e.to_string(),
+        )),
+    }
+}
+
+fn respond(id: Value, result: Result<Value, HandlerError>) -> String {
+    let response = match result {
+        Ok(data) => json!({
+            "type": "response",
+            "id": id,
+            "data": data,
+        }),
+        Err(error) => json!({
+            "type": "response",
+            "id": id,
+            "error": error,
+        }),
+    };
+
+    response.to_string()
+}
@@ -0,0 +1,120 @@
+use std::{
+    collections::{HashMap, HashSet},
+    sync::{Arc, Weak},
+    time::{Duration, Instant},
+};
+
+use mediasoup::producer::ProducerId;
+use tokio::sync::mpsc::{self, UnboundedReceiver};
+
+use super::channel::VoiceChannel;
+
+/// How often the audio level observer reports who is loud enough to be heard,
+/// in milliseconds. mediasoup doesn't go below 250.
+pub const VOLUME_INTERVAL: u16 = 250;
+
+/// How long someone has to stay quiet before they stop speaking, so the
+/// indicator doesn't flicker between words
+const SPEAKING_HOLD: Duration = Duration::from_millis(750);
+
+/// Starts telling a channel's clients who is speaking, through `speaking_start`
+/// and `speaking_stop` events. This stops on its own when the channel is gone.
+pub fn watch_speakers(channel: &Arc<VoiceChannel>) {
+    let (sender, receiver) = mpsc::unbounded_channel();
+
+    // the handler lives as long as the observer, which goes away with the
+    // channel, taking the sender with it
+    channel
+        .al_observer
+        .on_volumes(move |volumes| {
+            let producers = volumes.iter().map(|volume| volume.producer.id()).collect();
+            sender.send(producers).ok();
+        })
+        .detach();
+
+    actix_rt::spawn(track_speakers(Arc::downgrade(channel), receiver));
+}
+
+async fn track_speakers(
+    channel: Weak<VoiceChannel>,
+    mut volumes: UnboundedReceiver<Vec<ProducerId>>,
+) {
+    let interval = Duration::from_millis(VOLUME_INTERVAL.into());
+
+    // identity -> when they were last heard
+    let mut last_heard: HashMap<String, Instant> = HashMap::new();
+    let mut speaking: HashSet<String> = HashSet::new();
+
+    loop {
+        let heard = match actix_rt::time::timeout(interval, volumes.recv()).await {
+            Ok(Some(heard)) => heard,
+            // the observer is gone along with the channel
+            Ok(None) => break,
+            // the observer is quiet when nobody is speaking, but people who
+            // were speaking still need to be stopped
+            Err(_) => vec![],
+        };
+
+        let Some(channel) = channel.upgrade() else {
+            break;
+        };
+
+        let now = Instant::now();
+
+        for identity in identities_of(&channel, &heard).await {
+            last_heard.insert(identity, now);
+        }
+
+        last_heard.retain(|_, at| now.duration_since(*at) < SPEAKING_HOLD);
+
+        let clients: HashSet<String> = channel
+            .clients
+            .lock()
+            .await
+            .iter()
+            .map(|client| client.identity.clone())
+            .collect();
+
+        // whoever left the channel doesn't need to be told they stopped
+        last_heard.retain(|identity, _| clients.contains(identity));
+        speaking.retain(|identity| clients.contains(identity));
+
+        let stopped: Vec<String> = speaking
+            .iter()
+            .filter(|identity| !last_heard.contains_key(*identity))
+            .cloned()
+            .collect();
+
+        for identity in stopped {
+            speaking.remove(&identity);
+            channel.notify_speaking(&identity, false).await;
+        }
+
+        for identity in last_heard.keys() {
+            if speaking.insert(identity.clone()) {
+                channel.notify_speaking(identity, true).await;
+            }
+        }
+    }
+}
+
+/// The identities of the clients the producers belong to
+async fn identities_of(channel: &VoiceChannel, producers: &[ProducerId]) -> Vec<String> {
+    if producers.is_empty() {
+        return vec![];
+    }
+
+    let producers: Vec<String> = producers.iter().map(|id| id.to_string()).collect();
+
+    channel
+        .clients
+        .lock()
+        .await
+        .iter()
+        .filter(|client| {
+            let own = client.producers.lock().unwrap();
+            producers.iter().any(|id| own.contains_key(id))
+        })
+        .map(|client| client.identity.clone())
+        .collect()
+}
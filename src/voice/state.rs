@@ -0,0 +1,44 @@
+use serde::Serialize;
+use utoipa::ToSchema;
+
+use crate::auth::user::PublicUserInfo;
+
+/// What someone in a voice channel is doing. Most of it is reported by their
+/// client, apart from what moderators did to them.
+#[derive(Debug, Clone, Copy, Default, Serialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct VoiceState {
+    /// Not sending audio
+    pub muted: bool,
+    /// Not listening to anyone
+    pub deafened: bool,
+    /// Sending video from a camera
+    pub video: bool,
+    /// Sharing their screen
+    pub screen_share: bool,
+    /// Muted by a moderator, nobody can hear them
+    pub server_muted: bool,
+    /// Deafened by a moderator, they can't hear anyone
+    pub server_deafened: bool,
+}
+
+/// Someone connected to a voice channel, as seen from outside of it.
+#[derive(Serialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct VoiceMemberState {
+    /// RTC identity of the connection. A user may be connected more than once,
+    /// for example from two devices.
+    #[schema(example = "YDjdIc06vuaVQZy4LS7hb")]
+    pub identity: String,
+    pub user: PublicUserInfo,
+    pub state: VoiceState,
+}
+
+/// Everyone connected to one voice channel of a guild
+#[derive(Serialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct VoiceChannelState {
+    #[schema(example = "jqNNyhSbOl1AwqCTMAZ2G")]
+    pub channel_id: String,
+    pub members: Vec<VoiceMemberState>,
+}
@@ -1,12 +1,169 @@
+use log::{error, info, warn};
+use mediasoup::{
+    consumer::{Consumer, ConsumerOptions},
+    data_structures::DtlsState,
+    plain_transport::PlainTransport,
+    prelude::IceParameters,
+    producer::{Producer, ProducerOptions},
+    transport::{ConsumeError, ProduceError, Transport, TransportGeneric},
+    webrtc_transport::WebRtcTransport,
+    worker::RequestError,
+};
 use serde::Deserialize;
+use serde_json::Value;
 use utoipa::ToSchema;
 
+use crate::error::{macros::err, HResult, IntoHandlerErrorResult};
+
+use super::client::VoiceClient;
+
 /// This enum is used to specify the type of transport to create.
 /// It is used in the query string of the request.
-#[derive(Debug, Deserialize, ToSchema)]
+#[derive(Debug, Clone, Copy, Deserialize, ToSchema)]
 pub enum TransportType {
     #[serde(rename = "send")]
     Send,
     #[serde(rename = "recv")]
     Receive,
 }
+
+/// A client's send or receive transport. Browsers use WebRTC, bots can use
+/// plain RTP instead.
+#[derive(Clone)]
+pub enum ClientTransport {
+    WebRtc(WebRtcTransport),
+    Plain(PlainTransport),
+}
+
+impl ClientTransport {
+    pub fn closed(&self) -> bool {
+        match self {
+            Self::WebRtc(transport) => transport.closed(),
+            Self::Plain(transport) => transport.closed(),
+        }
+    }
+
+    pub async fn produce(&self, options: ProducerOptions) -> Result<Producer, ProduceError> {
+        match self {
+            Self::WebRtc(transport) => transport.produce(options).await,
+            Self::Plain(transport) => transport.produce(options).await,
+        }
+    }
+
+    pub async fn consume(&self, options: ConsumerOptions) -> Result<Consumer, ConsumeError> {
+        match self {
+            Self::WebRtc(transport) => transport.consume(options).await,
+            Self::Plain(transport) => transport.consume(options).await,
+        }
+    }
+
+    /// mediasoup's stats of the transport, which include its bitrates and,
+    /// for WebRTC, the ICE and DTLS state and the selected tuple
+    pub async fn get_stats(&self) -> Result<Value, RequestError> {
+        let stats = match self {
+            Self::WebRtc(transport) => serde_json::to_value(transport.get_stats().await?),
+            Self::Plain(transport) => serde_json::to_value(transport.get_stats().await?),
+        };
+
+        Ok(stats.unwrap_or_default())
+    }
+
+    /// Logs how the transport's connection is doing from now on, so there is
+    /// something to go by when someone reports bad audio
+    pub fn log_state_changes(&self, identity: &str, transport_type: TransportType) {
+        match self {
+            Self::WebRtc(transport) => {
+                let id = identity.to_owned();
+                transport
+                    .on_ice_state_change(move |state| {
+                        info!(
+                            "client[{:?}]: {:?} transport ice state: {:?}",
+                            id, transport_type, state
+                        );
+                    })
+                    .detach();
+
+                let id = identity.to_owned();
+                transport
+                    .on_ice_selected_tuple_change(move |tuple| {
+                        info!(
+                            "client[{:?}]: {:?} transport selected tuple: {:?}",
+                            id, transport_type, tuple
+                        );
+                    })
+                    .detach();
+
+                let id = identity.to_owned();
+                transport
+                    .on_dtls_state_change(move |state| {
+                        if state == DtlsState::Failed {
+                            warn!(
+                                "client[{:?}]: {:?} transport dtls failed",
+                                id, transport_type
+                            );
+                        } else {
+                            info!(
+                                "client[{:?}]: {:?} transport dtls state: {:?}",
+                                id, transport_type, state
+                            );
+                        }
+                    })
+                    .detach();
+            }
+            Self::Plain(transport) => {
+                let id = identity.to_owned();
+                transport
+                    .on_tuple(move |tuple| {
+                        info!(
+                            "client[{:?}]: plain {:?} transport tuple: {:?}",
+                            id, transport_type, tuple
+                        );
+                    })
+                    .detach();
+            }
+        }
+    }
+}
+
+impl VoiceClient {
+    /// Restarts ICE on one of the client's transports, for when its network
+    /// changed. The new parameters go to the clientside transport's
+    /// `restartIce()`.
+    pub async fn restart_ice(&self, transport_type: TransportType) -> HResult<IceParameters> {
+        let transport = match self.transport(transport_type).await {
+            Some(ClientTransport::WebRtc(transport)) => transport,
+            Some(ClientTransport::Plain(_)) => {
+                return err!(400, "Plain transports don't use ICE.");
+            }
+            None => return err!(400, "The transport has not been created yet."),
+        };
+
+        let ice_parameters = transport
+            .restart_ice()
+            .await
+            .map_err(|e| {
+                error!(
+                    "client[{:?}]: {:?} ice restart failed: {}",
+                    self.identity, transport_type, e
+                );
+                e
+            })
+            .or_err(500)?;
+
+        info!(
+            "client[{:?}]: {:?} transport ice restarted",
+            self.identity, transport_type
+        );
+
+        Ok(ice_parameters)
+    }
+
+    async fn transport(&self, transport_type: TransportType) -> Option<ClientTransport> {
+        let transport = match transport_type {
+            TransportType::Send => &self.c2s_transport,
+            TransportType::Receive => &self.s2c_transport,
+        };
+
+        transport.read().await.clone()
+    }
+}